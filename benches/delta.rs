@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sparse_hash_delta::*;
+use std::hint::black_box;
+
+const LEN: usize = 1 << 18;
+const MIN_MATCH_LEN: usize = 32;
+
+// A xorshift generator, so that the inputs are the same on every run.
+fn random_bytes(len: usize, mut state: u64) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+// Pairs of `a` and `b`:
+// - identical: `b` is `a`,
+// - disjoint: `b` shares nothing with `a`,
+// - repetitive: runs of one byte broken up every 64 bytes, against all one byte,
+// - random: `a` with a short random edit every 4 KiB.
+fn inputs() -> Vec<(&'static str, Vec<u8>, Vec<u8>)> {
+    let a = random_bytes(LEN, 0x9E37_79B9_7F4A_7C15);
+    let disjoint = random_bytes(LEN, 0xD1B5_4A32_D192_ED03);
+    let same = vec![b'x'; LEN];
+    let runs: Vec<u8> = (0..LEN)
+        .map(|i| if i % 64 == 63 { b'y' } else { b'x' })
+        .collect();
+    let mut edited = a.clone();
+    let edits = random_bytes(LEN / 4096 * 16, 0x2545_F491_4F6C_DD1D);
+    for (chunk, edit) in edited.chunks_mut(4096).zip(edits.chunks(16)) {
+        let at = edit[0] as usize * 8;
+        chunk[at..at + edit.len()].copy_from_slice(edit);
+    }
+    vec![
+        ("identical", a.clone(), a.clone()),
+        ("disjoint", a.clone(), disjoint),
+        ("repetitive", same, runs),
+        ("random", a, edited),
+    ]
+}
+
+fn bench_delta(c: &mut Criterion) {
+    let mut group = c.benchmark_group("delta");
+    for (name, a, b) in inputs() {
+        group.throughput(Throughput::Bytes(b.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &(a, b),
+            |bench, (a, b)| bench.iter(|| delta(black_box(a), black_box(b), MIN_MATCH_LEN)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_restore(c: &mut Criterion) {
+    let mut group = c.benchmark_group("restore");
+    for (name, a, b) in inputs() {
+        let d = delta(&a, &b, MIN_MATCH_LEN);
+        group.throughput(Throughput::Bytes(b.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &(a, d),
+            |bench, (a, d)| bench.iter(|| patch(black_box(a), black_box(d))),
+        );
+    }
+    group.finish();
+}
+
+// The scan for matches alone, as `matches` returns them: the table of `a` is built,
+// but no delta is assembled from the matches.
+fn bench_extract_matches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extract_matches");
+    for (name, a, b) in inputs() {
+        group.throughput(Throughput::Bytes(b.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            &(a, b),
+            |bench, (a, b)| bench.iter(|| matches(black_box(a), black_box(b), MIN_MATCH_LEN)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_delta, bench_restore, bench_extract_matches);
+criterion_main!(benches);
@@ -9,31 +9,31 @@ fn main() -> std::io::Result<()> {
     let b = std::fs::read(file_b)?;
 
     // The bigger the file size is, the more sparse the hash interval should be.
-    let hash_len = std::cmp::max(10, b.len() / 1000);
+    let policy = HashLenPolicy::Adaptive {
+        floor: 10,
+        divisor: 1000,
+    };
+    let hash_len = policy.min_match_len(b.len());
 
     // d = b - a.
     let d = delta(&a, &b, hash_len);
+    println!("matching ratio: {}", stats(&d).matching_ratio());
 
-    // Calculate matching ratio.
-    let matching_sum: usize = d
-        .iter()
-        .map(|m| {
-            return match m {
-                Compression::Match(_, len) => *len,
-                Compression::Raw(_) => 0,
-            };
-        })
-        .sum();
-    println!(
-        "matching ratio: {}",
-        (matching_sum as f64) / (b.len() as f64),
-    );
+    // Same as above, but seeded with the 64-bit rolling hash.
+    let d64 = delta64(&a, &b, hash_len);
+    println!("matching ratio (64-bit): {}", stats(&d64).matching_ratio());
 
     // r = a + d.
     let r = restore(&a, &d);
     let len = r.iter().map(|x| x.len()).sum();
     assert_eq!(b.len(), len);
 
+    // The same, going through the encoded form.
+    let encoded = encode_delta(&d);
+    println!("encoded size: {} bytes", encoded.len());
+    let r = apply(&a, &encoded).map_err(std::io::Error::other)?;
+    assert_eq!(b, r);
+
     println!("{} ms", now.elapsed().as_millis());
     Ok(())
 }
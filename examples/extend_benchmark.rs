@@ -0,0 +1,53 @@
+use sparse_hash_delta::*;
+
+// Times how long it takes to extend a match over a common run of `len` bytes, in
+// both directions, which is what `delta` does from each seed it finds. Run it once
+// as is and once with `--features fast-extend` to compare the scalar and word-wise
+// comparisons.
+fn main() {
+    let len: usize = std::env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(64 << 20);
+    let rounds: u32 = std::env::args()
+        .nth(2)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10);
+
+    let mut state: u64 = 0x9E3779B97F4A7C15;
+    let a: Vec<u8> = (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    // `b` differs from `a` only in its first and last bytes.
+    let mut b = a.clone();
+    b[0] ^= 0xFF;
+    b[len - 1] ^= 0xFF;
+
+    let mut best = std::time::Duration::MAX;
+    let mut total = std::time::Duration::ZERO;
+    for _ in 0..rounds {
+        let now = std::time::Instant::now();
+        let forward = u8::common_prefix_len(&a[1..], &b[1..]);
+        let backward = u8::common_suffix_len(&a[..len - 1], &b[..len - 1]);
+        let elapsed = now.elapsed();
+        assert_eq!((forward, backward), (len - 2, len - 2));
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+
+    let extend = if cfg!(feature = "fast-extend") {
+        "word-wise"
+    } else {
+        "scalar"
+    };
+    println!(
+        "{extend}: best {:.2} ms, mean {:.2} ms over {rounds} rounds of {len} bytes",
+        best.as_secs_f64() * 1e3,
+        total.as_secs_f64() * 1e3 / rounds as f64
+    );
+}
@@ -0,0 +1,38 @@
+use sparse_hash_delta::*;
+
+// Times `delta` on the same pair of files several times. Run it once as is and
+// once with `--features fast-hash` to compare the fingerprint table hashers.
+fn main() -> std::io::Result<()> {
+    let file_a = std::env::args().nth(1).unwrap_or("a.txt".to_string());
+    let file_b = std::env::args().nth(2).unwrap_or("b.txt".to_string());
+    let rounds: u32 = std::env::args()
+        .nth(3)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10);
+    let a = std::fs::read(file_a)?;
+    let b = std::fs::read(file_b)?;
+    let hash_len = std::cmp::max(10, b.len() / 1000);
+
+    let mut best = std::time::Duration::MAX;
+    let mut total = std::time::Duration::ZERO;
+    for _ in 0..rounds {
+        let now = std::time::Instant::now();
+        let d = delta(&a, &b, hash_len);
+        let elapsed = now.elapsed();
+        assert!(!d.is_empty());
+        best = best.min(elapsed);
+        total += elapsed;
+    }
+
+    let hasher = if cfg!(feature = "fast-hash") {
+        "fx"
+    } else {
+        "siphash"
+    };
+    println!(
+        "{hasher}: best {:.2} ms, mean {:.2} ms over {rounds} rounds",
+        best.as_secs_f64() * 1e3,
+        total.as_secs_f64() * 1e3 / rounds as f64
+    );
+    Ok(())
+}
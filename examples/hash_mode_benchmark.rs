@@ -0,0 +1,58 @@
+use sparse_hash_delta::*;
+use std::collections::HashMap;
+
+// Compares `HashMode::Single` and `HashMode::Double`: how many seed candidates share a
+// fingerprint with a window of `b` without being equal to it, and how long `delta_with`
+// takes. A weak modulus is included to show the effect when collisions are common.
+fn main() -> std::io::Result<()> {
+    let file_a = std::env::args().nth(1).unwrap_or("a.txt".to_string());
+    let file_b = std::env::args().nth(2).unwrap_or("b.txt".to_string());
+    let a = std::fs::read(file_a)?;
+    let b = std::fs::read(file_b)?;
+
+    let min_match_len = 32;
+    let hash_len = min_match_len / 2;
+    for params in [HashParams::default(), HashParams::new(257, 65521)] {
+        let single = false_positives(&a, &b, hash_len, PolynomialHasher::new(params));
+        let double = false_positives(&a, &b, hash_len, DoubleHasher::with_params(params));
+        println!("modulus {}:", params.modulus);
+        for (mode, fp) in [(HashMode::Single, single), (HashMode::Double, double)] {
+            let opts = DeltaOptions::default()
+                .min_match_len(min_match_len)
+                .hash_params(params)
+                .hash_mode(mode);
+            let now = std::time::Instant::now();
+            let d = delta_with(&a, &b, &opts);
+            let elapsed = now.elapsed();
+            assert!(verify(&a, &b, &d));
+            println!(
+                "  {mode:?}: {fp} false positives, {:.2} ms",
+                elapsed.as_secs_f64() * 1e3
+            );
+        }
+    }
+    Ok(())
+}
+
+fn false_positives<H: WindowHasher + Clone>(
+    a: &[u8],
+    b: &[u8],
+    hash_len: usize,
+    hasher: H,
+) -> usize {
+    let mut table: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (h, i) in RollingHash::with_hasher(a, hash_len, hasher.clone()).step_by(hash_len) {
+        table.entry(h).or_default().push(i);
+    }
+    RollingHash::with_hasher(b, hash_len, hasher)
+        .map(|(h, i)| {
+            let window = &b[i..i + hash_len];
+            table.get(&h).map_or(0, |positions| {
+                positions
+                    .iter()
+                    .filter(|&&j| &a[j..j + hash_len] != window)
+                    .count()
+            })
+        })
+        .sum()
+}
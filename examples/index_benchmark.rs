@@ -0,0 +1,56 @@
+use sparse_hash_delta::*;
+
+// Diffs several targets against one source, hashing the source for every target
+// with `delta_with` and once with `SourceIndex`.
+// Run with `cargo run --release --example index_benchmark -- a b1 b2 ...`.
+fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let a = std::fs::read(args.next().unwrap_or("a.txt".to_string()))?;
+    let mut targets = args
+        .map(std::fs::read)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if targets.is_empty() {
+        targets.push(std::fs::read("b.txt")?);
+    }
+    // Each target is diffed several times, as if there were that many of them.
+    const ROUNDS: usize = 10;
+
+    let opts = DeltaOptions::default().min_match_len(std::cmp::max(10, a.len() / 1000));
+
+    let now = std::time::Instant::now();
+    let mut uncached = Vec::new();
+    for _ in 0..ROUNDS {
+        for b in &targets {
+            uncached.push(delta_with(&a, b, &opts));
+        }
+    }
+    let without_index = now.elapsed();
+    println!(
+        "{} targets without index: {} ms",
+        uncached.len(),
+        without_index.as_millis()
+    );
+
+    let now = std::time::Instant::now();
+    let index = SourceIndex::build(&a, &opts);
+    println!("index: {} ms", now.elapsed().as_millis());
+    let mut cached = Vec::new();
+    for _ in 0..ROUNDS {
+        for b in &targets {
+            cached.push(delta_indexed(&index, &a, b));
+        }
+    }
+    let with_index = now.elapsed();
+    println!(
+        "{} targets with index: {} ms",
+        cached.len(),
+        with_index.as_millis()
+    );
+
+    assert_eq!(uncached, cached);
+    println!(
+        "speedup: {:.2}x",
+        without_index.as_secs_f64() / with_index.as_secs_f64()
+    );
+    Ok(())
+}
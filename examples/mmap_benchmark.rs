@@ -0,0 +1,37 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+use sparse_hash_delta::*;
+
+// Same as `benchmark`, but both files are memory-mapped instead of read into memory,
+// so inputs larger than RAM can be diffed. The delta borrows its raw runs from the
+// mapping of `b`, and the restored output is checked chunk by chunk without
+// assembling a copy of `b`.
+// Run with `cargo run --release --example mmap_benchmark -- a b`.
+fn main() -> std::io::Result<()> {
+    let now = std::time::Instant::now();
+
+    let file_a = std::env::args().nth(1).unwrap_or("a.txt".to_string());
+    let file_b = std::env::args().nth(2).unwrap_or("b.txt".to_string());
+    // SAFETY: the files must not be modified while they are mapped.
+    let a = unsafe { Mmap::map(&File::open(file_a)?)? };
+    let b = unsafe { Mmap::map(&File::open(file_b)?)? };
+
+    let hash_len = std::cmp::max(10, b.len() / 1000);
+
+    // d = b - a.
+    let d = delta(&a, &b, hash_len);
+    println!("matching ratio: {}", stats(&d).matching_ratio());
+    println!("encoded size: {} bytes", encoded_size(&d));
+
+    // r = a + d, compared against the mapping of `b` as it is produced.
+    let mut pos = 0;
+    for chunk in restore(&a, &d) {
+        assert_eq!(chunk, &b[pos..pos + chunk.len()]);
+        pos += chunk.len();
+    }
+    assert_eq!(pos, b.len());
+
+    println!("{} ms", now.elapsed().as_millis());
+    Ok(())
+}
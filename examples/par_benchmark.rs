@@ -0,0 +1,33 @@
+use sparse_hash_delta::*;
+
+// Compares `delta_with` against `par_delta_with` on the same pair of files.
+// Run with `cargo run --release --features rayon --example par_benchmark -- a b`.
+fn main() -> std::io::Result<()> {
+    let file_a = std::env::args().nth(1).unwrap_or("a.txt".to_string());
+    let file_b = std::env::args().nth(2).unwrap_or("b.txt".to_string());
+    let a = std::fs::read(file_a)?;
+    let b = std::fs::read(file_b)?;
+
+    let opts = DeltaOptions::default().min_match_len(std::cmp::max(10, b.len() / 1000));
+
+    let now = std::time::Instant::now();
+    let d = delta_with(&a, &b, &opts);
+    let sequential = now.elapsed();
+    println!("sequential: {} ms", sequential.as_millis());
+
+    let now = std::time::Instant::now();
+    let p = par_delta_with(&a, &b, &opts);
+    let parallel = now.elapsed();
+    println!(
+        "parallel ({} threads): {} ms",
+        rayon::current_num_threads(),
+        parallel.as_millis()
+    );
+
+    assert_eq!(d, p);
+    println!(
+        "speedup: {:.2}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use sparse_hash_delta::*;
+
+// Times `delta` on inputs where every window of `b` hashes to a bucket holding most of
+// `a`, for doubling lengths. Each seed then has that many candidates to extend, and
+// the time should still grow about linearly with the length.
+fn main() {
+    let max_len: usize = std::env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1 << 20);
+    let min_match_len = 16;
+
+    let mut len = 1 << 12;
+    while len <= max_len {
+        // All-identical bytes, and runs of them broken up every 64 bytes, so that
+        // every seed matches a long way on each of its candidates.
+        let same = vec![b'x'; len];
+        let runs: Vec<u8> = (0..len)
+            .map(|i| if i % 64 == 63 { b'y' } else { b'x' })
+            .collect();
+        for (name, b) in [("identical", &same), ("runs", &runs)] {
+            let now = std::time::Instant::now();
+            let d = delta(&same, b, min_match_len);
+            let elapsed = now.elapsed();
+            assert!(verify(&same, b, &d));
+            println!(
+                "{name:>9} {len:>8} bytes: {:>9.2} ms, {} compressions",
+                elapsed.as_secs_f64() * 1e3,
+                d.len()
+            );
+        }
+        len *= 2;
+    }
+}
@@ -0,0 +1,57 @@
+use sparse_hash_delta::*;
+
+// Compares `DeltaOptions::seed_phases` on a `b` made of many short copies from random
+// offsets of `a`, separated by random bytes. With a sparse sampling step, copies that
+// do not cover a whole sampled window are missed; more phases find more of them, at
+// the cost of a larger table.
+fn main() {
+    let len: usize = std::env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1 << 20);
+    let min_match_len = 32;
+    let step_factor = 4;
+
+    let mut state: u64 = 0x9E3779B97F4A7C15;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let a: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+    // Copies of `min_match_len` to three times that, each followed by a few random
+    // bytes.
+    let mut b = Vec::with_capacity(len);
+    let mut copied = 0;
+    while b.len() < len {
+        let copy_len = min_match_len + next() as usize % (2 * min_match_len);
+        let offset = next() as usize % (len - copy_len);
+        b.extend_from_slice(&a[offset..offset + copy_len]);
+        copied += copy_len;
+        b.extend((0..4).map(|_| next() as u8));
+    }
+
+    println!(
+        "{} of {} bytes copied from a, min_match_len {min_match_len}, step_factor {step_factor}",
+        copied,
+        b.len()
+    );
+    for phases in [1, 2, 4, 8] {
+        let opts = DeltaOptions::default()
+            .min_match_len(min_match_len)
+            .step_factor(step_factor)
+            .seed_phases(phases);
+        let seeds = collision_report(&a, &opts).windows;
+        let now = std::time::Instant::now();
+        let d = delta_with(&a, &b, &opts);
+        let elapsed = now.elapsed();
+        assert!(verify(&a, &b, &d));
+        let matched = stats(&d).matched_bytes;
+        println!(
+            "{phases} phases: {seeds:>7} seeds, recall {:.1}%, {:.2} ms",
+            100.0 * matched as f64 / copied as f64,
+            elapsed.as_secs_f64() * 1e3
+        );
+    }
+}
@@ -0,0 +1,19 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use sparse_hash_delta::{apply, delta, encode_delta, patch};
+
+#[derive(Debug, Arbitrary)]
+struct Input<'a> {
+    a: &'a [u8],
+    b: &'a [u8],
+    min_match_len: usize,
+}
+
+fuzz_target!(|input: Input| {
+    let Input { a, b, min_match_len } = input;
+    let d = delta(a, b, min_match_len);
+    assert_eq!(patch(a, &d), b);
+    assert_eq!(apply(a, &encode_delta(&d)).unwrap(), b);
+});
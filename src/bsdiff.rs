@@ -0,0 +1,146 @@
+use crate::Compression;
+use alloc::vec::Vec;
+
+/// Translates a delta of `b` against `a` into bsdiff control triples
+/// `(diff_len, extra_len, seek)`.
+///
+/// bsdiff rebuilds `b` by adding `diff_len` bytes of its diff block to as many bytes
+/// of `a` from the current source offset, then copying `extra_len` bytes of its extra
+/// block, and finally moving the source offset by `seek`. Here each `Match` becomes
+/// the diff part of a triple, with all-zero diff bytes as the match is exact, and the
+/// raw runs after it become its extra part. Back-references into the output have no
+/// bsdiff equivalent and are sent as extra bytes, taken from `b`.
+///
+/// Consecutive matches that are also consecutive in `a` share a triple. The diff
+/// and extra blocks are left to the caller: the diff block is
+/// `b[i] - a[j]` (wrapping) along the diff parts, and the extra block the bytes of
+/// `b` along the extra parts.
+pub fn to_bsdiff_controls(
+    a: &[u8],
+    b: &[u8],
+    compressions: &[Compression],
+) -> Vec<(i64, i64, i64)> {
+    let mut controls = Vec::new();
+    // The triple being built, and the source offset its diff part starts at.
+    let (mut diff_len, mut extra_len, mut old_start) = (0, 0, 0);
+    let mut new_pos = 0;
+    for c in compressions {
+        match *c {
+            Compression::Match(la, len) if la < a.len() => {
+                if len == 0 {
+                    continue;
+                }
+                if extra_len > 0 || la != old_start + diff_len {
+                    let seek = la as i64 - (old_start + diff_len) as i64;
+                    if diff_len > 0 || extra_len > 0 || seek != 0 {
+                        controls.push((diff_len as i64, extra_len as i64, seek));
+                    }
+                    (diff_len, extra_len, old_start) = (0, 0, la);
+                }
+                diff_len += len;
+                new_pos += len;
+            }
+            Compression::Match(_, len) => {
+                extra_len += len;
+                new_pos += len;
+            }
+            Compression::Raw(data) => {
+                extra_len += data.len();
+                new_pos += data.len();
+            }
+        }
+    }
+    debug_assert_eq!(new_pos, b.len(), "the delta does not restore `b`");
+    if diff_len > 0 || extra_len > 0 {
+        controls.push((diff_len as i64, extra_len as i64, 0));
+    }
+    controls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta, delta_with, DeltaOptions};
+
+    // Builds the diff and extra blocks for `controls` and applies them to `a` the way
+    // bspatch does. Also returns whether every diff byte is zero.
+    fn bspatch(a: &[u8], b: &[u8], controls: &[(i64, i64, i64)]) -> (Vec<u8>, bool) {
+        let (mut old_pos, mut new_pos) = (0i64, 0);
+        let (mut diff, mut extra) = (Vec::new(), Vec::new());
+        for &(x, y, z) in controls {
+            for i in 0..x as usize {
+                diff.push(b[new_pos + i].wrapping_sub(a[old_pos as usize + i]));
+            }
+            new_pos += x as usize;
+            extra.extend_from_slice(&b[new_pos..new_pos + y as usize]);
+            new_pos += y as usize;
+            old_pos += x + z;
+        }
+
+        let mut out = Vec::new();
+        let mut old_pos = 0;
+        let (mut diff_pos, mut extra_pos) = (0, 0);
+        for &(x, y, z) in controls {
+            for i in 0..x as usize {
+                out.push(a[old_pos as usize + i].wrapping_add(diff[diff_pos + i]));
+            }
+            diff_pos += x as usize;
+            old_pos += x;
+            out.extend_from_slice(&extra[extra_pos..extra_pos + y as usize]);
+            extra_pos += y as usize;
+            old_pos += z;
+        }
+        (out, diff.iter().all(|&d| d == 0))
+    }
+
+    #[test]
+    fn bsdiff_controls_vector() {
+        use Compression::*;
+        let a = b"0123456789abcdef";
+        let b = b"xy89ab234567zz";
+        let d = [
+            Raw(b"xy"),
+            Match(8, 4),
+            Match(2, 3),
+            Match(5, 3),
+            Raw(b"zz"),
+        ];
+        let controls = to_bsdiff_controls(a, b, &d);
+        assert_eq!(controls, vec![(0, 2, 8), (4, 0, -10), (6, 2, 0)]);
+        assert_eq!(bspatch(a, b, &controls), (b.to_vec(), true));
+    }
+
+    #[test]
+    fn bsdiff_controls_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let controls = to_bsdiff_controls(a, b, &delta(a, b, n));
+            assert_eq!(bspatch(a, b, &controls), (b.to_vec(), true));
+            let controls = to_bsdiff_controls(b, a, &delta(b, a, n));
+            assert_eq!(bspatch(b, a, &controls), (a.to_vec(), true));
+        }
+    }
+
+    #[test]
+    fn bsdiff_controls_back_reference() {
+        let a = b"xyz";
+        let b = b"abcabcabcabcxyzxyz";
+        let opts = DeltaOptions::default()
+            .min_match_len(3)
+            .allow_self_reference(true);
+        let d = delta_with(a, b, &opts);
+        assert!(d
+            .iter()
+            .any(|c| matches!(c, Compression::Match(la, _) if *la >= a.len())));
+        let controls = to_bsdiff_controls(a, b, &d);
+        assert_eq!(bspatch(a, b, &controls), (b.to_vec(), true));
+    }
+
+    #[test]
+    fn bsdiff_controls_empty() {
+        assert!(to_bsdiff_controls(b"abc", b"", &[]).is_empty());
+        let d = [Compression::Raw(&b"new"[..])];
+        assert_eq!(to_bsdiff_controls(b"", b"new", &d), vec![(0, 3, 0)]);
+    }
+}
@@ -0,0 +1,472 @@
+use std::io::{self, Read};
+
+use crate::{
+    shape_intervals, CompressionOwned, DeltaOptions, DoubleHasher, HashMode, MatchInterval,
+    PolynomialHasher, RollingHash, SeedTable, WindowHasher,
+};
+
+/// Same as `delta_with`, but reads `b` from `b_reader` in chunks of `chunk_size`
+/// bytes, so only the index of `a` and about one chunk of `b` are held in memory.
+///
+/// The last `hash_len - 1` bytes of a chunk are carried over to the next, so windows
+/// straddling two chunks are still scanned, and a match reaching the end of a chunk
+/// is extended into the next one. Matches are always chosen greedily and only refer
+/// to `a`: `strategy`, `allow_self_reference`, `resync_window`,
+/// `partition_for_parallel`, `source_window`, `max_displacement` and
+/// `hash_len_policy` are ignored.
+///
+/// Unmatched bytes are also kept for one sampling step behind the scan, which is as
+/// far back as a match usually extends from its seed. A match that would reach
+/// further back into bytes already emitted as raw is cut short there, so the delta
+/// may differ slightly from `delta_with`, but it restores the same `b`. Once
+/// `max_matches` matches are taken, the rest of `b` is read as raw bytes without
+/// being hashed.
+pub fn delta_chunked<R: Read>(
+    a: &[u8],
+    b_reader: &mut R,
+    chunk_size: usize,
+    opts: &DeltaOptions,
+) -> io::Result<Vec<CompressionOwned>> {
+    match opts.hash_mode {
+        HashMode::Single => {
+            let hasher = PolynomialHasher::new(opts.hash_params);
+            delta_chunked_with(a, b_reader, chunk_size, opts, hasher)
+        }
+        HashMode::Double => {
+            let hasher = DoubleHasher::with_params(opts.hash_params);
+            delta_chunked_with(a, b_reader, chunk_size, opts, hasher)
+        }
+    }
+}
+
+fn delta_chunked_with<R: Read, H: WindowHasher + Clone>(
+    a: &[u8],
+    b_reader: &mut R,
+    chunk_size: usize,
+    opts: &DeltaOptions,
+    hasher: H,
+) -> io::Result<Vec<CompressionOwned>> {
+    let table = SeedTable::sampled(a, &opts.clone().max_displacement(None), hasher.clone());
+    scan_chunks(InMemory { a, table }, b_reader, chunk_size, opts, hasher)
+}
+
+// Where the scan of `b` in chunks finds `a`: in memory for `delta_chunked`, or
+// behind a reader for `delta_streams`.
+pub(crate) trait ChunkSource {
+    fn a_len(&self) -> usize;
+
+    // The longest match seeded at `buf[ib..]`, whose window is `window` with hash
+    // `hb`, chosen as `SeedTable::best_match` does with no previous match.
+    fn best_match(
+        &mut self,
+        buf: &[u8],
+        window: &[u8],
+        hb: u64,
+        ib: usize,
+    ) -> io::Result<MatchInterval>;
+
+    // The number of leading bytes of `data` that `a[from..]` starts with.
+    fn common_prefix(&mut self, from: usize, data: &[u8]) -> io::Result<usize>;
+
+    // Same as `MatchInterval::extend_left` on `a` and `buf`.
+    fn extend_left(&mut self, m: &mut MatchInterval, buf: &[u8], bound: usize) -> io::Result<()>;
+
+    // Appends `a[start..end]` to `out`.
+    fn copy_to(&mut self, start: usize, end: usize, out: &mut Vec<u8>) -> io::Result<()>;
+}
+
+struct InMemory<'a> {
+    a: &'a [u8],
+    table: SeedTable<'a, u8>,
+}
+
+impl ChunkSource for InMemory<'_> {
+    fn a_len(&self) -> usize {
+        self.a.len()
+    }
+
+    fn best_match(
+        &mut self,
+        buf: &[u8],
+        window: &[u8],
+        hb: u64,
+        ib: usize,
+    ) -> io::Result<MatchInterval> {
+        let m = self
+            .table
+            .best_match(buf, window, hb, ib, &MatchInterval::empty());
+        Ok(m)
+    }
+
+    fn common_prefix(&mut self, from: usize, data: &[u8]) -> io::Result<usize> {
+        let len = self.a[from..]
+            .iter()
+            .zip(data)
+            .take_while(|(x, y)| x == y)
+            .count();
+        Ok(len)
+    }
+
+    fn extend_left(&mut self, m: &mut MatchInterval, buf: &[u8], bound: usize) -> io::Result<()> {
+        m.extend_left(self.a, buf, bound);
+        Ok(())
+    }
+
+    fn copy_to(&mut self, start: usize, end: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        out.extend_from_slice(&self.a[start..end]);
+        Ok(())
+    }
+}
+
+// Scans `b_reader` in chunks of `chunk_size` bytes for matches in `source`, as
+// `delta_chunked` describes.
+pub(crate) fn scan_chunks<S: ChunkSource, R: Read, H: WindowHasher + Clone>(
+    source: S,
+    b_reader: &mut R,
+    chunk_size: usize,
+    opts: &DeltaOptions,
+    hasher: H,
+) -> io::Result<Vec<CompressionOwned>> {
+    let chunk_size = std::cmp::max(1, chunk_size);
+    let hash_len = opts.hash_len();
+    let step = opts.step();
+    let mut out = Output {
+        source,
+        opts,
+        compressions: Vec::new(),
+        written: 0,
+        matches: 0,
+    };
+    // The bytes of `b` read but not yet emitted, whose windows from `scan_from` on
+    // have not been scanned.
+    let mut buf = Vec::new();
+    let mut scan_from = 0;
+    // A match that reached the end of the previous chunk, as `(la, len)`.
+    let mut open: Option<(usize, usize)> = None;
+    loop {
+        let read = b_reader.take(chunk_size as u64).read_to_end(&mut buf)?;
+        let eof = read < chunk_size;
+
+        if let Some((la, len)) = open.take() {
+            let extended = out.source.common_prefix(la + len, &buf)?;
+            if extended == buf.len() && !eof {
+                open = Some((la, len + extended));
+                buf.clear();
+                continue;
+            }
+            out.push_match(la, len + extended)?;
+            buf.drain(..extended);
+        }
+        if out.is_full() {
+            // No more matches are taken, so the rest of `b` is not scanned.
+            out.push_raw(&buf);
+            buf.clear();
+            scan_from = 0;
+            if eof {
+                return Ok(out.compressions);
+            }
+            continue;
+        }
+        if buf.len() - scan_from < hash_len && !eof {
+            continue;
+        }
+
+        let window_len = std::cmp::min(buf.len() - scan_from, hash_len);
+        let hashes = RollingHash::with_hasher(&buf[scan_from..], hash_len, hasher.clone());
+        let mut acc = MatchInterval::empty();
+        let mut state = 0;
+        let mut prev = 0;
+        let mut scanned = scan_from;
+        for (hb, i) in hashes {
+            let ib = scan_from + i;
+            scanned = ib + 1;
+            if ib < state {
+                continue;
+            }
+            let window = &buf[ib..ib + window_len];
+            let mut m = out.source.best_match(&buf, window, hb, ib)?;
+            if m.len == 0 {
+                continue;
+            }
+            state = m.br();
+            m.remove_overlap(&acc);
+            if m.len == 0 {
+                continue;
+            }
+            out.source.extend_left(&mut m, &buf, acc.br())?;
+            acc = m;
+            if prev < m.lb {
+                out.push_raw(&buf[prev..m.lb]);
+            }
+            prev = m.br();
+            if m.br() == buf.len() && !eof {
+                open = Some((m.la, m.len));
+                break;
+            }
+            out.push_match(m.la, m.len)?;
+            if out.is_full() {
+                break;
+            }
+        }
+
+        if eof {
+            out.push_raw(&buf[prev..]);
+            return Ok(out.compressions);
+        }
+        // Windows that did not fit in this chunk start in the carried-over tail.
+        let next_scan = std::cmp::max(prev, scanned);
+        let keep = std::cmp::max(prev, scanned.saturating_sub(step - 1));
+        out.push_raw(&buf[prev..keep]);
+        buf.drain(..keep);
+        scan_from = next_scan - keep;
+    }
+}
+
+// The delta built so far. Raw runs of consecutive chunks are joined, and matches
+// go through the same output-shaping options as `finish_delta`.
+struct Output<'a, S> {
+    source: S,
+    opts: &'a DeltaOptions,
+    compressions: Vec<CompressionOwned>,
+    // The length of the output so far.
+    written: usize,
+    matches: usize,
+}
+
+impl<S: ChunkSource> Output<'_, S> {
+    // Whether `max_matches` matches have been written.
+    fn is_full(&self) -> bool {
+        self.opts.max_matches == Some(self.matches)
+    }
+
+    fn push_raw(&mut self, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        self.written += data.len();
+        match self.compressions.last_mut() {
+            Some(CompressionOwned::Raw(raw)) => raw.extend_from_slice(data),
+            _ => self.compressions.push(CompressionOwned::Raw(data.to_vec())),
+        }
+    }
+
+    // Appends `a[start..end]` as raw bytes.
+    fn push_source(&mut self, start: usize, end: usize) -> io::Result<()> {
+        let mut data = Vec::with_capacity(end - start);
+        self.source.copy_to(start, end, &mut data)?;
+        self.push_raw(&data);
+        Ok(())
+    }
+
+    fn push_match(&mut self, la: usize, len: usize) -> io::Result<()> {
+        let m = MatchInterval {
+            la,
+            lb: self.written,
+            len,
+        };
+        // Parts of `m` dropped as pieces are written as raw bytes copied from `a`.
+        let mut from = m.lb;
+        for piece in shape_intervals(vec![m], self.source.a_len(), 0, self.opts) {
+            if self.is_full() {
+                break;
+            }
+            self.matches += 1;
+            self.push_source(m.la + from - m.lb, piece.la)?;
+            self.compressions
+                .push(CompressionOwned::Match(piece.la, piece.len));
+            self.written += piece.len;
+            from = piece.br();
+        }
+        self.push_source(m.la + from - m.lb, m.la + m.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta_with, restore, xorshift_bytes, Compression};
+
+    fn restored(a: &[u8], d: &[CompressionOwned]) -> Vec<u8> {
+        let d: Vec<Compression> = d.iter().map(Compression::from).collect();
+        restore(a, &d).concat()
+    }
+
+    fn owned(d: Vec<Compression>) -> Vec<CompressionOwned> {
+        d.into_iter().map(CompressionOwned::from).collect()
+    }
+
+    #[test]
+    fn delta_chunked_restores_b() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [1, 2, 10, 32] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            for chunk_size in [0, 1, 2, 7, 64, 1000] {
+                let d = delta_chunked(a, &mut &b[..], chunk_size, &opts).unwrap();
+                assert_eq!(restored(a, &d), b, "n {n} chunk {chunk_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn delta_chunked_single_chunk_same_as_delta_with() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for opts in [
+            DeltaOptions::default().min_match_len(2),
+            DeltaOptions::default().min_match_len(10),
+            DeltaOptions::default().max_match_len(7),
+            DeltaOptions::default().hash_mode(HashMode::Double),
+        ] {
+            for chunk_size in [b.len(), b.len() + 1] {
+                let d = delta_chunked(a, &mut &b[..], chunk_size, &opts).unwrap();
+                assert_eq!(d, owned(delta_with(a, b, &opts)));
+            }
+        }
+    }
+
+    #[test]
+    fn delta_chunked_match_across_chunks() {
+        let a = xorshift_bytes(5000, 0x9E3779B97F4A7C15);
+        let b = &a[100..4900];
+        let opts = DeltaOptions::default().min_match_len(16);
+        for chunk_size in [1, 5, 64, 1000] {
+            let d = delta_chunked(&a, &mut &b[..], chunk_size, &opts).unwrap();
+            assert_eq!(d, vec![CompressionOwned::Match(100, 4800)]);
+        }
+    }
+
+    #[test]
+    fn delta_chunked_disjoint_sources() {
+        let a: Vec<u8> = (0..200).collect();
+        let b = &a[30..];
+        let opts = DeltaOptions::default()
+            .min_match_len(8)
+            .disjoint_sources(true);
+        for chunk_size in [7, 64, 1000] {
+            let d = delta_chunked(&a, &mut &b[..], chunk_size, &opts).unwrap();
+            assert_eq!(restored(&a, &d), b);
+            let mut pos = 0;
+            for c in &d {
+                match c {
+                    CompressionOwned::Match(la, len) => {
+                        assert!(pos + len <= *la);
+                        pos += len;
+                    }
+                    CompressionOwned::Raw(raw) => pos += raw.len(),
+                }
+            }
+        }
+        let d = delta_chunked(&a, &mut &b[..], b.len(), &opts).unwrap();
+        assert_eq!(d, owned(delta_with(&a, b, &opts)));
+    }
+
+    #[test]
+    fn delta_chunked_max_matches() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for max_matches in [0, 3, 40] {
+            let opts = DeltaOptions::default()
+                .min_match_len(4)
+                .max_match_len(5)
+                .max_matches(Some(max_matches));
+            for chunk_size in [7, 1000, b.len()] {
+                let d = delta_chunked(a, &mut &b[..], chunk_size, &opts).unwrap();
+                assert_eq!(restored(a, &d), b);
+                let matches = d
+                    .iter()
+                    .filter(|c| matches!(c, CompressionOwned::Match(..)))
+                    .count();
+                assert_eq!(matches, max_matches);
+            }
+        }
+    }
+
+    // An `InMemory` that counts the seeds looked up.
+    struct Counted<'a> {
+        source: InMemory<'a>,
+        lookups: &'a mut usize,
+    }
+
+    impl ChunkSource for Counted<'_> {
+        fn a_len(&self) -> usize {
+            self.source.a_len()
+        }
+
+        fn best_match(
+            &mut self,
+            buf: &[u8],
+            window: &[u8],
+            hb: u64,
+            ib: usize,
+        ) -> io::Result<MatchInterval> {
+            *self.lookups += 1;
+            self.source.best_match(buf, window, hb, ib)
+        }
+
+        fn common_prefix(&mut self, from: usize, data: &[u8]) -> io::Result<usize> {
+            self.source.common_prefix(from, data)
+        }
+
+        fn extend_left(
+            &mut self,
+            m: &mut MatchInterval,
+            buf: &[u8],
+            bound: usize,
+        ) -> io::Result<()> {
+            self.source.extend_left(m, buf, bound)
+        }
+
+        fn copy_to(&mut self, start: usize, end: usize, out: &mut Vec<u8>) -> io::Result<()> {
+            self.source.copy_to(start, end, out)
+        }
+    }
+
+    #[test]
+    fn delta_chunked_stops_scanning_at_max_matches() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for max_matches in [0, 2] {
+            let opts = DeltaOptions::default()
+                .min_match_len(4)
+                .max_match_len(5)
+                .max_matches(Some(max_matches));
+            for chunk_size in [7, 1000, b.len()] {
+                let hasher = PolynomialHasher::new(opts.hash_params);
+                let mut lookups = 0;
+                let source = Counted {
+                    source: InMemory {
+                        a,
+                        table: SeedTable::sampled(a, &opts, hasher.clone()),
+                    },
+                    lookups: &mut lookups,
+                };
+                let d = scan_chunks(source, &mut &b[..], chunk_size, &opts, hasher).unwrap();
+                assert_eq!(restored(a, &d), b);
+                // Only the windows up to the last match are looked up.
+                let matched_to = d
+                    .iter()
+                    .scan(0, |pos, c| {
+                        let len = Compression::from(c).output_len();
+                        *pos += len;
+                        Some((*pos, c))
+                    })
+                    .filter(|(_, c)| matches!(c, CompressionOwned::Match(..)))
+                    .map(|(pos, _)| pos)
+                    .last()
+                    .unwrap_or(0);
+                assert!(lookups <= matched_to, "chunk {chunk_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn delta_chunked_empty_inputs() {
+        let opts = DeltaOptions::default();
+        assert!(delta_chunked(b"abc", &mut &b""[..], 4, &opts)
+            .unwrap()
+            .is_empty());
+        let d = delta_chunked(b"", &mut &b"hello"[..], 2, &opts).unwrap();
+        assert_eq!(d, vec![CompressionOwned::Raw(b"hello".to_vec())]);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Compression, CompressionOwned};
+use alloc::vec::Vec;
+
+// A run of `mid` as `d1` produces it: copied from `a[la..]`, or anything else.
+#[derive(Clone, Copy)]
+enum Origin {
+    Source(usize),
+    Literal,
+}
+
+/// Composes `d1`, a delta from `a` to `mid`, and `d2`, a delta from `mid` to `b`,
+/// into a delta from `a` to `b` without diffing them again.
+///
+/// The parts of `mid` that matches of `d2` copy are looked up in `d1`: those `d1`
+/// copies from `a` become matches in `a`, and those it inserts as raw data or copies
+/// from earlier in `mid` become raw data taken from `mid`. Back-references of `d2`
+/// into `b` are kept as they are.
+///
+/// # Panics
+///
+/// If `d1` does not restore to as many elements as `mid` has, or `d2` copies from
+/// outside of `mid` and its own output.
+pub fn compose<T: Clone>(
+    a: &[T],
+    mid: &[T],
+    d1: &[Compression<T>],
+    d2: &[Compression<T>],
+) -> Vec<CompressionOwned<T>> {
+    // `(start, len, origin)` for each run of `mid`, in order.
+    let mut runs = Vec::with_capacity(d1.len());
+    let mut pos = 0;
+    for c in d1 {
+        let (len, origin) = match *c {
+            Compression::Match(la, len) if la < a.len() => (len, Origin::Source(la)),
+            Compression::Match(_, len) => (len, Origin::Literal),
+            Compression::Raw(data) => (data.len(), Origin::Literal),
+        };
+        if len > 0 {
+            runs.push((pos, len, origin));
+            pos += len;
+        }
+    }
+    assert_eq!(pos, mid.len(), "`d1` does not restore `mid`");
+
+    let mut out = Output {
+        a_len: a.len(),
+        compressions: Vec::with_capacity(d2.len()),
+    };
+    for c in d2 {
+        match *c {
+            Compression::Match(la, len) if la < mid.len() => {
+                let mut from = la;
+                let end = la + len;
+                let mut i = runs.partition_point(|&(start, len, _)| start + len <= from);
+                while from < end {
+                    let (start, run_len, origin) = runs[i];
+                    let to = core::cmp::min(end, start + run_len);
+                    match origin {
+                        Origin::Source(source) => out.push_match(source + from - start, to - from),
+                        Origin::Literal => out.push_raw(&mid[from..to]),
+                    }
+                    from = to;
+                    i += 1;
+                }
+            }
+            // The output is `b` either way, only addressed after `a` instead of `mid`.
+            Compression::Match(la, len) => out.push_match(la - mid.len() + a.len(), len),
+            Compression::Raw(data) => out.push_raw(data),
+        }
+    }
+    out.compressions
+}
+
+// The composed delta, with runs that continue each other joined.
+struct Output<T> {
+    a_len: usize,
+    compressions: Vec<CompressionOwned<T>>,
+}
+
+impl<T: Clone> Output<T> {
+    fn push_raw(&mut self, data: &[T]) {
+        if data.is_empty() {
+            return;
+        }
+        match self.compressions.last_mut() {
+            Some(CompressionOwned::Raw(raw)) => raw.extend_from_slice(data),
+            _ => self.compressions.push(CompressionOwned::Raw(data.to_vec())),
+        }
+    }
+
+    fn push_match(&mut self, la: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        if let Some(CompressionOwned::Match(last, last_len)) = self.compressions.last_mut() {
+            if *last + *last_len == la && (*last < self.a_len) == (la < self.a_len) {
+                *last_len += len;
+                return;
+            }
+        }
+        self.compressions.push(CompressionOwned::Match(la, len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta, delta_with, patch_owned, DeltaOptions};
+
+    #[test]
+    fn compose_vector() {
+        use Compression::*;
+        let a = b"abcdefgh";
+        let mid = b"cdeXYfgh";
+        let b = b"XYfghcdeY";
+        let d1 = [Match(2, 3), Raw(b"XY"), Match(5, 3)];
+        let d2 = [Match(3, 5), Match(0, 3), Match(4, 1)];
+        assert_eq!(
+            compose(a, mid, &d1, &d2),
+            vec![
+                CompressionOwned::Raw(b"XY".to_vec()),
+                CompressionOwned::Match(5, 3),
+                CompressionOwned::Match(2, 3),
+                CompressionOwned::Raw(b"Y".to_vec()),
+            ]
+        );
+        assert_eq!(patch_owned(a, &compose(a, mid, &d1, &d2)), b);
+    }
+
+    #[test]
+    fn compose_reconstructs_b() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        // Half of the edits from `a` to `b`, then the rest.
+        let mut mid = a[..a.len() / 2].to_vec();
+        mid.extend_from_slice(&b[b.len() / 2..]);
+        for n in [2, 10, 32] {
+            let d1 = delta(a, &mid, n);
+            let d2 = delta(&mid, b, n);
+            let composed = compose(a, &mid, &d1, &d2);
+            assert_eq!(patch_owned(a, &composed), b);
+
+            let d1 = delta(b, &mid, n);
+            let d2 = delta(&mid, a, n);
+            assert_eq!(patch_owned(b, &compose(b, &mid, &d1, &d2)), a);
+        }
+    }
+
+    #[test]
+    fn compose_back_references() {
+        let a = b"0123456789";
+        let mid = b"0123456789abcabcabcabc";
+        let b = b"abcabcabcabc0123456789xyzxyzxyzxyz";
+        let opts = DeltaOptions::default()
+            .min_match_len(3)
+            .allow_self_reference(true);
+        let d1 = delta_with(a, mid, &opts);
+        let d2 = delta_with(mid, b, &opts);
+        assert!(d2
+            .iter()
+            .any(|c| matches!(c, Compression::Match(la, _) if *la >= mid.len())));
+        assert_eq!(patch_owned(a, &compose(a, mid, &d1, &d2)), b);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not restore")]
+    fn compose_wrong_mid() {
+        let d1 = [Compression::Raw(&b"abc"[..])];
+        compose(b"", b"abcd", &d1, &[]);
+    }
+}
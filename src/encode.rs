@@ -0,0 +1,1283 @@
+use crate::patch::crc32_update;
+use crate::{
+    crc32, delta_iter, delta_windows, delta_with, try_restore_with_limit, Compression,
+    CompressionOwned, DeltaOptions, RestoreError,
+};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+const OP_RAW: u8 = 0;
+const OP_MATCH: u8 = 1;
+#[cfg(feature = "flate2")]
+const OP_RAW_DEFLATE: u8 = 2;
+const OP_WINDOW: u8 = 3;
+// Shorter raw runs are never worth the DEFLATE block overhead.
+#[cfg(feature = "flate2")]
+const MIN_DEFLATE_LEN: usize = 16;
+// DEFLATE expands its input by at most 1032 times, 258 bytes for each 2-bit code.
+#[cfg(feature = "flate2")]
+const MAX_DEFLATE_RATIO: usize = 1032;
+#[cfg(feature = "std")]
+const MAX_VARINT_LEN: usize = (usize::BITS as usize).div_ceil(7);
+
+/// Encodes a delta as an opcode per compression followed by LEB128 varints.
+///
+/// ```text
+/// Raw      : 0, len, data[..len]
+/// Match    : 1, la, len
+/// Deflated : 2, len, deflated_len, deflate(data)[..deflated_len]
+/// Window   : 3, start, payload_len, checksum as u32 LE, payload[..payload_len]
+/// ```
+///
+/// Deflated raw runs are only written by `encode_delta_deflated` and
+/// `write_delta_deflated`, with the `flate2` feature. Windows are only written by `delta_bytes` with
+/// `DeltaOptions::resync_window`: `payload` holds the compressions of the window
+/// starting at `start` in `b`, and `checksum` is the `crc32` of the rest of the
+/// window: its opcode, `start`, `payload_len` and `payload`. Decoders other than
+/// `apply_windows` read through the window headers.
+pub fn encode_delta(compressions: &[Compression]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(encoded_size(compressions));
+    for c in compressions {
+        encode_compression(&mut out, c);
+    }
+    out
+}
+
+/// Same as `encode_delta(&delta_with(a, b, opts))`, in one call.
+///
+/// When `opts` differs from the defaults only in `min_match_len`, the compressions
+/// are encoded as `delta_iter` yields them, without collecting them first. With
+/// `DeltaOptions::resync_window`, each window is written in a checksummed frame.
+pub fn delta_bytes(a: &[u8], b: &[u8], opts: &DeltaOptions) -> Vec<u8> {
+    let opts = &*opts.resolved(b.len());
+    if opts.resync_window.is_some() {
+        let mut out = Vec::new();
+        for (start, compressions) in delta_windows(a, b, opts, None) {
+            let payload = encode_delta(&compressions);
+            let header_start = out.len();
+            out.push(OP_WINDOW);
+            write_varint(&mut out, start);
+            write_varint(&mut out, payload.len());
+            let checksum = crc32_update(crc32(&out[header_start..]), &payload);
+            out.extend_from_slice(&checksum.to_le_bytes());
+            out.extend_from_slice(&payload);
+        }
+        return out;
+    }
+    if *opts != DeltaOptions::default().min_match_len(opts.min_match_len) {
+        return encode_delta(&delta_with(a, b, opts));
+    }
+    let mut out = Vec::new();
+    for c in delta_iter(a, b, opts.min_match_len) {
+        encode_compression(&mut out, &c);
+    }
+    out
+}
+
+fn encode_compression(out: &mut Vec<u8>, c: &Compression) {
+    write_header(out, c);
+    if let Compression::Raw(data) = c {
+        out.extend_from_slice(data);
+    }
+}
+
+/// Same as `encode_delta`, but raw runs whose DEFLATE stream takes fewer bytes are
+/// stored deflated. The result is at most `encoded_size(compressions)` long, and
+/// only decodes with the `flate2` feature.
+#[cfg(feature = "flate2")]
+pub fn encode_delta_deflated(compressions: &[Compression]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for c in compressions {
+        match c {
+            Compression::Raw(data) => match deflate_if_smaller(data) {
+                Some(deflated) => {
+                    write_deflated_header(&mut out, data.len(), deflated.len());
+                    out.extend_from_slice(&deflated);
+                }
+                None => encode_compression(&mut out, c),
+            },
+            Compression::Match(..) => encode_compression(&mut out, c),
+        }
+    }
+    out
+}
+
+/// Same format as `encode_delta`, written to `w` one compression at a time.
+/// Raw data is passed to `w` directly from the borrowed slice.
+#[cfg(feature = "std")]
+pub fn write_delta<W: Write>(compressions: &[Compression], w: &mut W) -> io::Result<()> {
+    let mut header = Vec::with_capacity(1 + 2 * MAX_VARINT_LEN);
+    for c in compressions {
+        header.clear();
+        write_header(&mut header, c);
+        w.write_all(&header)?;
+        if let Compression::Raw(data) = c {
+            w.write_all(data)?;
+        }
+    }
+    Ok(())
+}
+
+/// Same format as `encode_delta_deflated`, written to `w` one compression at a time.
+#[cfg(feature = "flate2")]
+pub fn write_delta_deflated<W: Write>(compressions: &[Compression], w: &mut W) -> io::Result<()> {
+    let mut header = Vec::with_capacity(1 + 2 * MAX_VARINT_LEN);
+    for c in compressions {
+        header.clear();
+        if let Compression::Raw(data) = c {
+            if let Some(deflated) = deflate_if_smaller(data) {
+                write_deflated_header(&mut header, data.len(), deflated.len());
+                w.write_all(&header)?;
+                w.write_all(&deflated)?;
+                continue;
+            }
+        }
+        write_header(&mut header, c);
+        w.write_all(&header)?;
+        if let Compression::Raw(data) = c {
+            w.write_all(data)?;
+        }
+    }
+    Ok(())
+}
+
+// Appends the encoding of `c` to `out`, except for the data of a raw run.
+fn write_header(out: &mut Vec<u8>, c: &Compression) {
+    match c {
+        Compression::Match(la, len) => {
+            out.push(OP_MATCH);
+            write_varint(out, *la);
+            write_varint(out, *len);
+        }
+        Compression::Raw(data) => {
+            out.push(OP_RAW);
+            write_varint(out, data.len());
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+fn write_deflated_header(out: &mut Vec<u8>, len: usize, deflated_len: usize) {
+    out.push(OP_RAW_DEFLATE);
+    write_varint(out, len);
+    write_varint(out, deflated_len);
+}
+
+// The DEFLATE stream of `data`, if storing it takes fewer bytes than `data` itself.
+#[cfg(feature = "flate2")]
+fn deflate_if_smaller(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < MIN_DEFLATE_LEN {
+        return None;
+    }
+    let mut encoder =
+        flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).ok()?;
+    let deflated = encoder.finish().ok()?;
+    (varint_len(deflated.len()) + deflated.len() < data.len()).then_some(deflated)
+}
+
+// Inflates `deflated`, which must expand to exactly `len` bytes. A `len` that
+// `deflated` is too short to expand to is refused without inflating anything.
+#[cfg(feature = "flate2")]
+fn inflate(deflated: &[u8], len: usize) -> Option<Vec<u8>> {
+    if len / MAX_DEFLATE_RATIO > deflated.len() {
+        return None;
+    }
+    let mut out = Vec::new();
+    flate2::read::DeflateDecoder::new(deflated)
+        .take((len as u64).saturating_add(1))
+        .read_to_end(&mut out)
+        .ok()?;
+    (out.len() == len).then_some(out)
+}
+
+/// The length of `encode_delta(compressions)`, computed without encoding.
+pub fn encoded_size(compressions: &[Compression]) -> usize {
+    compressions
+        .iter()
+        .map(|c| match c {
+            Compression::Match(la, len) => encoded_match_size(*la, *len),
+            Compression::Raw(data) => 1 + varint_len(data.len()) + data.len(),
+        })
+        .sum()
+}
+
+/// Why `decode_delta` refused its input.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("unexpected end of delta at byte {offset}")]
+    UnexpectedEof { offset: usize },
+    #[error("unknown opcode {opcode} at byte {offset}")]
+    UnknownOpcode { opcode: u8, offset: usize },
+    #[error("varint at byte {offset} overflows usize")]
+    VarintOverflow { offset: usize },
+    /// A deflated raw run that does not inflate to its stated length. Only
+    /// reported with the `flate2` feature.
+    #[error("invalid deflated data at byte {offset}")]
+    InvalidDeflate { offset: usize },
+    /// No window with a matching checksum starts at `offset`. Only reported by
+    /// `apply_windows`.
+    #[error("no valid window at byte {offset}")]
+    BadWindow { offset: usize },
+}
+
+pub fn decode_delta(bytes: &[u8]) -> Result<Vec<CompressionOwned>, DecodeError> {
+    decode_delta_with(bytes, |_, _| Ok(()))
+}
+
+// `decode_delta`, calling `grow(index, len)` with the output length of each
+// compression before its data is read.
+fn decode_delta_with<E: From<DecodeError>>(
+    bytes: &[u8],
+    mut grow: impl FnMut(usize, usize) -> Result<(), E>,
+) -> Result<Vec<CompressionOwned>, E> {
+    let mut results = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let offset = pos;
+        let opcode = bytes[pos];
+        pos += 1;
+        match opcode {
+            OP_MATCH => {
+                let la = read_varint(bytes, &mut pos)?;
+                let len = read_varint(bytes, &mut pos)?;
+                grow(results.len(), len)?;
+                results.push(CompressionOwned::Match(la, len));
+            }
+            OP_RAW => {
+                let len = read_varint(bytes, &mut pos)?;
+                grow(results.len(), len)?;
+                if bytes.len() - pos < len {
+                    return Err(DecodeError::UnexpectedEof {
+                        offset: bytes.len(),
+                    }
+                    .into());
+                }
+                results.push(CompressionOwned::Raw(bytes[pos..pos + len].to_vec()));
+                pos += len;
+            }
+            #[cfg(feature = "flate2")]
+            OP_RAW_DEFLATE => {
+                let len = read_varint(bytes, &mut pos)?;
+                grow(results.len(), len)?;
+                let deflated_len = read_varint(bytes, &mut pos)?;
+                if bytes.len() - pos < deflated_len {
+                    return Err(DecodeError::UnexpectedEof {
+                        offset: bytes.len(),
+                    }
+                    .into());
+                }
+                let data = inflate(&bytes[pos..pos + deflated_len], len)
+                    .ok_or(DecodeError::InvalidDeflate { offset })?;
+                results.push(CompressionOwned::Raw(data));
+                pos += deflated_len;
+            }
+            OP_WINDOW => {
+                read_varint(bytes, &mut pos)?;
+                read_varint(bytes, &mut pos)?;
+                if bytes.len() - pos < 4 {
+                    return Err(DecodeError::UnexpectedEof {
+                        offset: bytes.len(),
+                    }
+                    .into());
+                }
+                pos += 4;
+            }
+            _ => return Err(DecodeError::UnknownOpcode { opcode, offset }.into()),
+        }
+    }
+    Ok(results)
+}
+
+// The output length the compressions of `bytes` add up to, as far as they decode,
+// without restoring anything.
+#[cfg(feature = "std")]
+pub(crate) fn stated_output_len(bytes: &[u8]) -> usize {
+    let mut len: usize = 0;
+    let _ = decode_delta_with(bytes, |_, n| -> Result<(), DecodeError> {
+        len = len.saturating_add(n);
+        Ok(())
+    });
+    len
+}
+
+/// Options for `apply_with`, `apply_windows_with` and `apply_reader_with`, built up
+/// from `ApplyOptions::default()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ApplyOptions {
+    /// The length the delta must restore to, such as the one stored in a patch file
+    /// header. Applying stops with `ApplyError::OutputTooLong` as soon as the output
+    /// would grow past it, before the compression doing so is read or copied.
+    pub expected_output_len: Option<usize>,
+    /// The most bytes `apply_with` restores, or `apply_windows_with` restores per
+    /// window. A few bytes of delta can ask for any amount of output, so set this
+    /// for deltas from untrusted sources: longer ones are then refused with
+    /// `RestoreError::OutputTooLong` before the output is allocated. Otherwise the
+    /// output is bounded by `expected_output_len`, or by the most a `Vec` can hold.
+    /// `apply_reader_with` does not hold the output, and is not bounded by it.
+    pub max_output_len: Option<usize>,
+}
+
+impl ApplyOptions {
+    pub fn expected_output_len(mut self, expected_output_len: Option<usize>) -> Self {
+        self.expected_output_len = expected_output_len;
+        self
+    }
+
+    pub fn max_output_len(mut self, max_output_len: Option<usize>) -> Self {
+        self.max_output_len = max_output_len;
+        self
+    }
+
+    // The longest output to restore, for `try_restore_with_limit`.
+    fn output_limit(&self) -> usize {
+        let limit = self.max_output_len.unwrap_or(isize::MAX as usize);
+        core::cmp::min(limit, self.expected_output_len.unwrap_or(usize::MAX))
+    }
+}
+
+/// Why `apply` could not reconstruct `b`: the delta is malformed, or does not apply
+/// to `a`, or restores to a length other than the expected one.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ApplyError {
+    #[error("malformed delta: {0}")]
+    Decode(#[from] DecodeError),
+    #[error("delta does not apply: {0}")]
+    Restore(#[from] RestoreError),
+    /// Compression `index` makes the output longer than `expected` elements.
+    #[error("compression {index} restores more than the expected {expected} elements")]
+    OutputTooLong { index: usize, expected: usize },
+    /// The delta restores only `actual` of the `expected` elements.
+    #[error("delta restores {actual} elements instead of {expected}")]
+    OutputTooShort { expected: usize, actual: usize },
+}
+
+/// Reconstructs `b` from `a` and a delta in the `encode_delta` format.
+pub fn apply(a: &[u8], encoded_delta: &[u8]) -> Result<Vec<u8>, ApplyError> {
+    apply_with(a, encoded_delta, &ApplyOptions::default())
+}
+
+/// Same as `apply`, with the output checked against `opts`.
+pub fn apply_with(
+    a: &[u8],
+    encoded_delta: &[u8],
+    opts: &ApplyOptions,
+) -> Result<Vec<u8>, ApplyError> {
+    let mut output_len = OutputLen::new(opts);
+    let owned = decode_delta_with(encoded_delta, |index, len| output_len.grow(index, len))?;
+    output_len.finish()?;
+    let compressions: Vec<Compression> = owned.iter().map(Compression::from).collect();
+    Ok(try_restore_with_limit(
+        a,
+        &compressions,
+        opts.output_limit(),
+    )?)
+}
+
+/// Reconstructs `b` window by window from `a` and a delta written by `delta_bytes`
+/// with `DeltaOptions::resync_window`, as `(start, data)` with `data` the window
+/// starting at `start` in `b`.
+///
+/// A window that is damaged, so that its checksum does not match, is reported as an
+/// error, and decoding resumes at the next window with a valid checksum. A corrupted
+/// byte thus only loses the window holding it.
+pub fn apply_windows(a: &[u8], encoded: &[u8]) -> Vec<Result<(usize, Vec<u8>), ApplyError>> {
+    apply_windows_with(a, encoded, &ApplyOptions::default())
+}
+
+/// Same as `apply_windows`, with each window bounded by `opts.max_output_len`. The
+/// windows of a damaged delta do not add up to a known length, so
+/// `expected_output_len` is not checked.
+pub fn apply_windows_with(
+    a: &[u8],
+    encoded: &[u8],
+    opts: &ApplyOptions,
+) -> Vec<Result<(usize, Vec<u8>), ApplyError>> {
+    let limit = opts.clone().expected_output_len(None).output_limit();
+    let mut results = Vec::new();
+    let mut pos = 0;
+    while pos < encoded.len() {
+        match read_window(encoded, pos) {
+            Ok((start, payload, end)) => {
+                let data = apply_window(a, start, payload, limit);
+                results.push(data.map(|data| (start, data)));
+                pos = end;
+            }
+            Err(e) => {
+                results.push(Err(e.into()));
+                pos = (pos + 1..encoded.len())
+                    .find(|&p| read_window(encoded, p).is_ok())
+                    .unwrap_or(encoded.len());
+            }
+        }
+    }
+    results
+}
+
+// The window at `offset`, as `(start, payload, end)` with `end` the offset after it,
+// if its checksum matches.
+fn read_window(bytes: &[u8], offset: usize) -> Result<(usize, &[u8], usize), DecodeError> {
+    if bytes[offset] != OP_WINDOW {
+        return Err(DecodeError::BadWindow { offset });
+    }
+    let mut pos = offset + 1;
+    let start = read_varint(bytes, &mut pos)?;
+    let len = read_varint(bytes, &mut pos)?;
+    let header_end = pos;
+    if bytes.len() - pos < 4 || bytes.len() - pos - 4 < len {
+        return Err(DecodeError::UnexpectedEof {
+            offset: bytes.len(),
+        });
+    }
+    let checksum = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+    let payload = &bytes[pos + 4..pos + 4 + len];
+    if crc32_update(crc32(&bytes[offset..header_end]), payload) != checksum {
+        return Err(DecodeError::BadWindow { offset });
+    }
+    Ok((start, payload, pos + 4 + len))
+}
+
+// Back-references in `payload` address the whole output, and are resolved within
+// the window, which starts at `start` and holds at most `limit` bytes.
+fn apply_window(
+    a: &[u8],
+    start: usize,
+    payload: &[u8],
+    limit: usize,
+) -> Result<Vec<u8>, ApplyError> {
+    let owned = decode_delta(payload)?;
+    let mut compressions = Vec::with_capacity(owned.len());
+    for (index, c) in owned.iter().enumerate() {
+        let c = match *c {
+            CompressionOwned::Match(la, len) if la >= a.len() => {
+                let la = la.checked_sub(start).filter(|&la| la >= a.len()).ok_or(
+                    RestoreError::MatchOutOfBounds {
+                        index,
+                        la,
+                        len,
+                        a_len: a.len(),
+                    },
+                )?;
+                Compression::Match(la, len)
+            }
+            _ => Compression::from(c),
+        };
+        compressions.push(c);
+    }
+    Ok(try_restore_with_limit(a, &compressions, limit)?)
+}
+
+// The output length so far, checked against `ApplyOptions::expected_output_len`.
+struct OutputLen {
+    expected: Option<usize>,
+    len: usize,
+}
+
+impl OutputLen {
+    fn new(opts: &ApplyOptions) -> Self {
+        Self {
+            expected: opts.expected_output_len,
+            len: 0,
+        }
+    }
+
+    fn grow(&mut self, index: usize, len: usize) -> Result<(), ApplyError> {
+        self.len = self.len.saturating_add(len);
+        match self.expected {
+            Some(expected) if self.len > expected => {
+                Err(ApplyError::OutputTooLong { index, expected })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&self) -> Result<(), ApplyError> {
+        match self.expected {
+            Some(expected) if self.len < expected => Err(ApplyError::OutputTooShort {
+                expected,
+                actual: self.len,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reconstructs `b` from `a` and an encoded delta read from `r`, writing it to `out`
+/// as the delta is parsed. Malformed input is reported as `InvalidData` wrapping a
+/// `DecodeError` or `RestoreError`.
+///
+/// Since the output is not kept, matches referring back to it (see
+/// `DeltaOptions::allow_self_reference`) are rejected as out of bounds.
+#[cfg(feature = "std")]
+pub fn apply_reader<R: Read, W: Write>(a: &[u8], r: &mut R, out: &mut W) -> io::Result<()> {
+    apply_reader_with(a, r, out, &ApplyOptions::default())
+}
+
+/// Same as `apply_reader`, with the output checked against `opts`. An
+/// `ApplyError::OutputTooLong` is reported before any of the compression that
+/// overruns the expected length is written to `out`.
+#[cfg(feature = "std")]
+pub fn apply_reader_with<R: Read, W: Write>(
+    a: &[u8],
+    r: &mut R,
+    out: &mut W,
+    opts: &ApplyOptions,
+) -> io::Result<()> {
+    let mut output_len = OutputLen::new(opts);
+    let mut pos = 0;
+    let mut index = 0;
+    while let Some(opcode) = read_byte(r, &mut pos)? {
+        match opcode {
+            OP_MATCH => {
+                let la = read_varint_io(r, &mut pos)?;
+                let len = read_varint_io(r, &mut pos)?;
+                output_len.grow(index, len).map_err(invalid_data)?;
+                if la.checked_add(len).is_none_or(|end| end > a.len()) {
+                    return Err(invalid_data(RestoreError::MatchOutOfBounds {
+                        index,
+                        la,
+                        len,
+                        a_len: a.len(),
+                    }));
+                }
+                out.write_all(&a[la..la + len])?;
+            }
+            OP_RAW => {
+                let len = read_varint_io(r, &mut pos)?;
+                output_len.grow(index, len).map_err(invalid_data)?;
+                let copied = io::copy(&mut r.take(len as u64), out)?;
+                if copied != len as u64 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                pos += len;
+            }
+            #[cfg(feature = "flate2")]
+            OP_RAW_DEFLATE => {
+                let offset = pos - 1;
+                let len = read_varint_io(r, &mut pos)?;
+                output_len.grow(index, len).map_err(invalid_data)?;
+                let deflated_len = read_varint_io(r, &mut pos)?;
+                let mut deflated = Vec::new();
+                r.take(deflated_len as u64).read_to_end(&mut deflated)?;
+                if deflated.len() != deflated_len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                pos += deflated_len;
+                let data = inflate(&deflated, len)
+                    .ok_or_else(|| invalid_data(DecodeError::InvalidDeflate { offset }))?;
+                out.write_all(&data)?;
+            }
+            OP_WINDOW => {
+                read_varint_io(r, &mut pos)?;
+                read_varint_io(r, &mut pos)?;
+                r.read_exact(&mut [0; 4])?;
+                pos += 4;
+                continue;
+            }
+            _ => {
+                let offset = pos - 1;
+                return Err(invalid_data(DecodeError::UnknownOpcode { opcode, offset }));
+            }
+        }
+        index += 1;
+    }
+    output_len.finish().map_err(invalid_data)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(crate) fn encoded_match_size(la: usize, len: usize) -> usize {
+    1 + varint_len(la) + varint_len(len)
+}
+
+// The number of bytes `write_varint` emits for `value`.
+fn varint_len(value: usize) -> usize {
+    let bits = usize::BITS - value.leading_zeros();
+    core::cmp::max(1, bits.div_ceil(7) as usize)
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<usize, DecodeError> {
+    let offset = *pos;
+    let next = || {
+        let byte = *bytes.get(*pos).ok_or(DecodeError::UnexpectedEof {
+            offset: bytes.len(),
+        })?;
+        *pos += 1;
+        Ok(byte)
+    };
+    varint_from(next, || DecodeError::VarintOverflow { offset })
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn read_varint_io<R: Read>(r: &mut R, pos: &mut usize) -> io::Result<usize> {
+    let offset = *pos;
+    let next = || read_byte(r, pos)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into());
+    varint_from(next, || {
+        invalid_data(DecodeError::VarintOverflow { offset })
+    })
+}
+
+fn varint_from<E>(
+    mut next: impl FnMut() -> Result<u8, E>,
+    overflow: impl FnOnce() -> E,
+) -> Result<usize, E> {
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+        let byte = next()?;
+        let low = (byte & 0x7F) as usize;
+        if shift >= usize::BITS || (low << shift) >> shift != low {
+            return Err(overflow());
+        }
+        value |= low << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+// Returns `None` at the end of the stream.
+#[cfg(feature = "std")]
+fn read_byte<R: Read>(r: &mut R, pos: &mut usize) -> io::Result<Option<u8>> {
+    let mut buf = [0];
+    loop {
+        match r.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {
+                *pos += 1;
+                return Ok(Some(buf[0]));
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xorshift;
+
+    fn varint(value: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn varint_small() {
+        assert_eq!(varint(0), vec![0x00]);
+        assert_eq!(varint(1), vec![0x01]);
+        assert_eq!(varint(127), vec![0x7F]);
+    }
+
+    #[test]
+    fn varint_multi_byte() {
+        assert_eq!(varint(128), vec![0x80, 0x01]);
+        assert_eq!(varint(300), vec![0xAC, 0x02]);
+        assert_eq!(varint(16384), vec![0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn encode_match_raw() {
+        use Compression::*;
+        let delta = [Match(5, 3), Raw(&[9, 9]), Match(300, 1)];
+        let result = encode_delta(&delta);
+        assert_eq!(result, vec![1, 5, 3, 0, 2, 9, 9, 1, 0xAC, 0x02, 1]);
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        for value in [0, 1, 127, 128, 300, 1 << 32, usize::MAX] {
+            let bytes = varint(value);
+            let mut pos = 0;
+            assert_eq!(read_varint(&bytes, &mut pos), Ok(value));
+            assert_eq!(pos, bytes.len());
+        }
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let mut bytes = vec![0xFF; usize::BITS as usize / 7];
+        bytes.push(0x7F);
+        assert_eq!(
+            read_varint(&bytes, &mut 0),
+            Err(DecodeError::VarintOverflow { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn decode_roundtrip() {
+        use Compression::*;
+        let deltas: [&[Compression]; 4] = [
+            &[],
+            &[Raw(&[1, 2, 3])],
+            &[Match(5, 3), Raw(&[9, 9]), Match(300, 1)],
+            &[Raw(&[0; 200]), Match(usize::MAX, 1 << 20), Raw(&[])],
+        ];
+        for delta in deltas {
+            let decoded = decode_delta(&encode_delta(delta)).unwrap();
+            let result: Vec<Compression> = decoded.iter().map(Into::into).collect();
+            assert_eq!(result, delta);
+        }
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert_eq!(
+            decode_delta(&[1, 5]),
+            Err(DecodeError::UnexpectedEof { offset: 2 })
+        );
+        assert_eq!(
+            decode_delta(&[0, 3, 9, 9]),
+            Err(DecodeError::UnexpectedEof { offset: 4 })
+        );
+        assert_eq!(
+            decode_delta(&[1, 0x80]),
+            Err(DecodeError::UnexpectedEof { offset: 2 })
+        );
+    }
+
+    #[test]
+    fn decode_unknown_opcode() {
+        assert_eq!(
+            decode_delta(&[1, 5, 3, 7]),
+            Err(DecodeError::UnknownOpcode {
+                opcode: 7,
+                offset: 3
+            })
+        );
+    }
+
+    #[test]
+    fn decode_random_bytes() {
+        let mut rng = xorshift(0x9E3779B97F4A7C15);
+        let mut next = move || rng.next().unwrap();
+        for _ in 0..10000 {
+            let len = next() as usize % 64;
+            let bytes: Vec<u8> = (0..len).map(|_| (next() % 4) as u8 * 0x55).collect();
+            let _ = decode_delta(&bytes);
+            let bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            let _ = decode_delta(&bytes);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_delta_matches_encode() {
+        use Compression::*;
+        let raw = [7; 300];
+        let delta = [Match(5, 3), Raw(&[9, 9]), Match(300, 1), Raw(&raw)];
+        let mut out = Vec::new();
+        write_delta(&delta, &mut out).unwrap();
+        assert_eq!(out, encode_delta(&delta));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_delta_propagates_error() {
+        use Compression::*;
+        let mut out = [0u8; 4];
+        let result = write_delta(&[Raw(&[1, 2, 3, 4])], &mut &mut out[..]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::WriteZero);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_reader_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let encoded = encode_delta(&crate::delta(&a, &b, 3));
+        let mut out = Vec::new();
+        apply_reader(&a, &mut &encoded[..], &mut out).unwrap();
+        assert_eq!(out, b);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_reader_out_of_bounds() {
+        use Compression::*;
+        let a = [0, 1, 2, 3];
+        let encoded = encode_delta(&[Raw(&[9]), Match(3, 2)]);
+        let mut out = Vec::new();
+        let e = apply_reader(&a, &mut &encoded[..], &mut out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.into_inner().unwrap().downcast_ref::<RestoreError>(),
+            Some(&RestoreError::MatchOutOfBounds {
+                index: 1,
+                la: 3,
+                len: 2,
+                a_len: 4
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_reader_truncated() {
+        let a = [0, 1, 2, 3];
+        for encoded in [&[1, 0][..], &[1, 0x80], &[0, 3, 9, 9]] {
+            let mut out = Vec::new();
+            let e = apply_reader(&a, &mut &encoded[..], &mut out).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_reader_unknown_opcode() {
+        let mut out = Vec::new();
+        let e = apply_reader(&[], &mut &[0, 1, 9, 7][..], &mut out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(out, vec![9]);
+    }
+
+    #[test]
+    fn apply_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        assert_eq!(
+            apply(&a, &encode_delta(&crate::delta(&a, &b, 3))),
+            Ok(b.to_vec())
+        );
+    }
+
+    #[test]
+    fn apply_back_reference() {
+        use Compression::*;
+        let encoded = encode_delta(&[Raw(&[1, 2]), Match(2, 3)]);
+        assert_eq!(apply(&[7, 8], &encoded), Ok(vec![1, 2, 1, 2, 1]));
+    }
+
+    #[test]
+    fn apply_decode_error() {
+        assert_eq!(
+            apply(&[], &[0, 3, 9]),
+            Err(ApplyError::Decode(DecodeError::UnexpectedEof { offset: 3 }))
+        );
+    }
+
+    #[test]
+    fn apply_out_of_bounds() {
+        use Compression::*;
+        let encoded = encode_delta(&[Raw(&[9]), Match(3, 2)]);
+        assert_eq!(
+            apply(&[0, 1, 2, 3], &encoded),
+            Err(ApplyError::Restore(RestoreError::MatchOutOfBounds {
+                index: 1,
+                la: 3,
+                len: 2,
+                a_len: 4
+            }))
+        );
+    }
+
+    #[test]
+    fn apply_error_messages_and_sources() {
+        use core::error::Error;
+        let e = apply(&[], &[7]).unwrap_err();
+        assert_eq!(
+            e,
+            ApplyError::Decode(DecodeError::UnknownOpcode {
+                opcode: 7,
+                offset: 0
+            })
+        );
+        assert_eq!(e.to_string(), "malformed delta: unknown opcode 7 at byte 0");
+        assert_eq!(
+            e.source().unwrap().downcast_ref::<DecodeError>(),
+            Some(&DecodeError::UnknownOpcode {
+                opcode: 7,
+                offset: 0
+            })
+        );
+        let e = apply(&[0, 1], &encode_delta(&[Compression::Match(1, 2)])).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "delta does not apply: compression 0 copies a[1..1+2] but a has only 2 bytes"
+        );
+        assert!(e.source().unwrap().is::<RestoreError>());
+        let e = ApplyError::OutputTooShort {
+            expected: 3,
+            actual: 2,
+        };
+        assert_eq!(e.to_string(), "delta restores 2 elements instead of 3");
+        assert!(e.source().is_none());
+    }
+
+    #[test]
+    fn apply_refuses_overflowing_and_oversized_output() {
+        use Compression::*;
+        let a = [0, 1];
+        // A back-reference repeating the output up to `usize::MAX` bytes.
+        let encoded = encode_delta(&[Raw(b"x"), Match(a.len(), usize::MAX)]);
+        assert_eq!(
+            apply(&a, &encoded),
+            Err(ApplyError::Restore(RestoreError::OutputTooLong {
+                index: 1,
+                max: isize::MAX as usize
+            }))
+        );
+        // `apply_reader` does not keep the output to refer back to.
+        #[cfg(feature = "std")]
+        {
+            let e = apply_reader(&a, &mut &encoded[..], &mut Vec::new()).unwrap_err();
+            assert_eq!(
+                e.get_ref().unwrap().downcast_ref::<RestoreError>(),
+                Some(&RestoreError::MatchOutOfBounds {
+                    index: 1,
+                    la: 2,
+                    len: usize::MAX,
+                    a_len: 2
+                })
+            );
+        }
+        let encoded = encode_delta(&[Raw(b"x"), Match(a.len(), usize::MAX), Raw(b"y")]);
+        assert_eq!(
+            apply(&a, &encoded),
+            Err(ApplyError::Restore(RestoreError::OutputTooLong {
+                index: 1,
+                max: isize::MAX as usize
+            }))
+        );
+
+        let encoded = encode_delta(&[0; 6].map(|_| Match(0, 2)));
+        let opts = ApplyOptions::default().max_output_len(Some(12));
+        assert_eq!(apply_with(&a, &encoded, &opts), Ok([0, 1].repeat(6)));
+        let opts = ApplyOptions::default().max_output_len(Some(11));
+        let e = apply_with(&a, &encoded, &opts).unwrap_err();
+        assert_eq!(
+            e,
+            ApplyError::Restore(RestoreError::OutputTooLong { index: 5, max: 11 })
+        );
+        assert_eq!(
+            e.to_string(),
+            "delta does not apply: compression 5 restores more than 11 elements"
+        );
+
+        #[cfg(feature = "flate2")]
+        {
+            let mut encoded = vec![OP_RAW_DEFLATE];
+            write_varint(&mut encoded, usize::MAX);
+            write_varint(&mut encoded, 0);
+            assert_eq!(
+                apply(&a, &encoded),
+                Err(ApplyError::Decode(DecodeError::InvalidDeflate {
+                    offset: 0
+                }))
+            );
+            let e = apply_reader(&a, &mut &encoded[..], &mut Vec::new()).unwrap_err();
+            assert_eq!(
+                e.get_ref().unwrap().downcast_ref::<DecodeError>(),
+                Some(&DecodeError::InvalidDeflate { offset: 0 })
+            );
+        }
+    }
+
+    #[test]
+    fn apply_with_expected_output_len() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let encoded = encode_delta(&crate::delta(&a, &b, 3));
+        let opts = ApplyOptions::default().expected_output_len(Some(b.len()));
+        assert_eq!(apply_with(&a, &encoded, &opts), Ok(b.to_vec()));
+        let opts = ApplyOptions::default().expected_output_len(Some(b.len() + 1));
+        assert_eq!(
+            apply_with(&a, &encoded, &opts),
+            Err(ApplyError::OutputTooShort {
+                expected: 9,
+                actual: 8
+            })
+        );
+    }
+
+    #[test]
+    fn apply_with_rejects_oversized_delta() {
+        // A corrupt raw run claiming 2^40 bytes is refused before its data is read.
+        let mut encoded = encode_delta(&[Compression::Raw(&[1, 2])]);
+        encoded.push(OP_RAW);
+        write_varint(&mut encoded, 1 << 40);
+        encoded.extend_from_slice(&[9; 16]);
+        let opts = ApplyOptions::default().expected_output_len(Some(16));
+        assert_eq!(
+            apply_with(&[], &encoded, &opts),
+            Err(ApplyError::OutputTooLong {
+                index: 1,
+                expected: 16
+            })
+        );
+        assert!(matches!(
+            apply(&[], &encoded),
+            Err(ApplyError::Decode(DecodeError::UnexpectedEof { .. }))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_reader_with_rejects_oversized_delta() {
+        use Compression::*;
+        let a = [0, 1, 2, 3];
+        let encoded = encode_delta(&[Raw(&[9, 9]), Match(0, 4), Raw(&[8])]);
+        let opts = ApplyOptions::default().expected_output_len(Some(5));
+        let mut out = Vec::new();
+        let e = apply_reader_with(&a, &mut &encoded[..], &mut out, &opts).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.into_inner().unwrap().downcast_ref::<ApplyError>(),
+            Some(&ApplyError::OutputTooLong {
+                index: 1,
+                expected: 5
+            })
+        );
+        assert_eq!(out, [9, 9]);
+
+        let opts = ApplyOptions::default().expected_output_len(Some(7));
+        let mut out = Vec::new();
+        apply_reader_with(&a, &mut &encoded[..], &mut out, &opts).unwrap();
+        assert_eq!(out, [9, 9, 0, 1, 2, 3, 8]);
+        let opts = ApplyOptions::default().expected_output_len(Some(8));
+        let e = apply_reader_with(&a, &mut &encoded[..], &mut Vec::new(), &opts).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encode_empty() {
+        assert!(encode_delta(&[]).is_empty());
+        assert_eq!(encoded_size(&[]), 0);
+    }
+
+    #[test]
+    fn delta_bytes_same_as_two_steps() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for opts in [
+            DeltaOptions::default(),
+            DeltaOptions::default().min_match_len(2),
+            DeltaOptions::default().min_match_len(32),
+            DeltaOptions::default().max_match_len(7),
+            DeltaOptions::default().allow_self_reference(true),
+        ] {
+            let encoded = delta_bytes(a, b, &opts);
+            assert_eq!(encoded, encode_delta(&delta_with(a, b, &opts)));
+            assert_eq!(apply(a, &encoded).unwrap(), b);
+            assert_eq!(
+                delta_bytes(b, a, &opts),
+                encode_delta(&delta_with(b, a, &opts))
+            );
+        }
+        let opts = DeltaOptions::default();
+        assert_eq!(delta_bytes(a, b"", &opts), [OP_RAW, 0]);
+        assert_eq!(
+            delta_bytes(a, b"", &opts.clone().max_match_len(1)),
+            [OP_RAW, 0]
+        );
+    }
+
+    fn windowed(a: &[u8], b: &[u8]) -> Vec<u8> {
+        let opts = DeltaOptions::default()
+            .min_match_len(8)
+            .allow_self_reference(true)
+            .resync_window(Some(100));
+        delta_bytes(a, b, &opts)
+    }
+
+    #[test]
+    fn resync_window_encoding_reads_through() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let encoded = windowed(a, b);
+        assert_eq!(encoded[0], OP_WINDOW);
+        assert_eq!(apply(a, &encoded).unwrap(), b);
+        #[cfg(feature = "std")]
+        {
+            let mut out = Vec::new();
+            let encoded = delta_bytes(a, b, &DeltaOptions::default().resync_window(Some(100)));
+            apply_reader(a, &mut &encoded[..], &mut out).unwrap();
+            assert_eq!(out, b);
+        }
+        let windows: Vec<_> = apply_windows(a, &encoded)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(windows.len(), b.len().div_ceil(100));
+        for (i, (start, data)) in windows.iter().enumerate() {
+            assert_eq!(*start, i * 100);
+            assert_eq!(data[..], b[*start..*start + data.len()]);
+        }
+    }
+
+    #[test]
+    fn apply_windows_with_max_output_len() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let encoded = windowed(a, b);
+        let opts = ApplyOptions::default()
+            .max_output_len(Some(100))
+            .expected_output_len(Some(1));
+        assert_eq!(
+            apply_windows_with(a, &encoded, &opts),
+            apply_windows(a, &encoded)
+        );
+        let results = apply_windows_with(a, &encoded, &opts.max_output_len(Some(99)));
+        for result in &results[..results.len() - 1] {
+            assert!(matches!(
+                result,
+                Err(ApplyError::Restore(RestoreError::OutputTooLong {
+                    max: 99,
+                    ..
+                }))
+            ));
+        }
+    }
+
+    #[test]
+    fn apply_windows_resumes_after_corruption() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let encoded = windowed(a, b);
+        let windows = b.len().div_ceil(100);
+        for pos in (0..encoded.len()).step_by(7) {
+            let mut corrupted = encoded.clone();
+            corrupted[pos] ^= 0x5A;
+            let results = apply_windows(a, &corrupted);
+            let restored: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+            assert_eq!(results.len() - restored.len(), 1, "byte {pos}");
+            assert_eq!(restored.len(), windows - 1, "byte {pos}");
+            for (start, data) in restored {
+                assert_eq!(data[..], b[*start..*start + data.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn varint_len_matches_write_varint() {
+        for shift in 0..usize::BITS {
+            for value in [(1 << shift) - 1, 1 << shift, (1 << shift) + 1] {
+                assert_eq!(varint_len(value), varint(value).len(), "{value}");
+            }
+        }
+        assert_eq!(varint_len(usize::MAX), varint(usize::MAX).len());
+    }
+
+    #[test]
+    fn encoded_size_random_deltas() {
+        let mut rng = xorshift(0x2545F4914F6CDD1D);
+        let mut next = move || rng.next().unwrap();
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        for _ in 0..2000 {
+            let count = next() as usize % 16;
+            let delta: Vec<Compression> = (0..count)
+                .map(|_| {
+                    // Spread values over every varint length.
+                    let is_match = next() % 2 == 0;
+                    let mut value = || (next() as usize) >> (next() % usize::BITS as u64);
+                    if is_match {
+                        Compression::Match(value(), value())
+                    } else {
+                        Compression::Raw(&data[..value() % data.len()])
+                    }
+                })
+                .collect();
+            assert_eq!(encoded_size(&delta), encode_delta(&delta).len());
+            #[cfg(feature = "flate2")]
+            assert!(encoded_size(&delta) >= encode_delta_deflated(&delta).len());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn encode_deflates_compressible_raw() {
+        use Compression::*;
+        let a = b"the quick brown fox";
+        let b: Vec<u8> = b"abcd".iter().cycle().take(4000).copied().collect();
+        let delta = [Match(4, 5), Raw(&b), Match(16, 3)];
+        assert_eq!(encode_delta(&delta)[3], OP_RAW);
+        let encoded = encode_delta_deflated(&delta);
+        assert_eq!(encoded[3], OP_RAW_DEFLATE);
+        assert!(encoded.len() < 100, "{}", encoded.len());
+        assert!(encoded.len() < encoded_size(&delta));
+        let mut expected = b"quick".to_vec();
+        expected.extend_from_slice(&b);
+        expected.extend_from_slice(b"fox");
+        assert_eq!(apply(a, &encoded), Ok(expected.clone()));
+
+        let mut out = Vec::new();
+        write_delta_deflated(&delta, &mut out).unwrap();
+        assert_eq!(out, encoded);
+        out.clear();
+        apply_reader(a, &mut &encoded[..], &mut out).unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn encode_keeps_incompressible_raw() {
+        let noise = crate::xorshift_bytes(500, 0x9E3779B97F4A7C15);
+        let delta = [Compression::Raw(&noise)];
+        assert_eq!(encode_delta_deflated(&delta), encode_delta(&delta));
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn decode_invalid_deflate() {
+        let data = [b'x'; 64];
+        let mut encoded = encode_delta_deflated(&[Compression::Raw(&data)]);
+        assert_eq!(
+            decode_delta(&encoded).unwrap()[0],
+            CompressionOwned::Raw(data.to_vec())
+        );
+        // Claim one byte more than the stream inflates to.
+        encoded[1] += 1;
+        assert_eq!(
+            decode_delta(&encoded),
+            Err(DecodeError::InvalidDeflate { offset: 0 })
+        );
+        let mut out = Vec::new();
+        let e = apply_reader(&[], &mut &encoded[..], &mut out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // A truncated stream.
+        encoded[1] -= 1;
+        encoded.pop();
+        encoded[2] -= 1;
+        assert_eq!(
+            decode_delta(&encoded),
+            Err(DecodeError::InvalidDeflate { offset: 0 })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn decode_deflate_length_out_of_reach() {
+        assert_eq!(inflate(&[], usize::MAX), None);
+        let mut encoded = vec![OP_RAW_DEFLATE];
+        write_varint(&mut encoded, usize::MAX);
+        write_varint(&mut encoded, 0);
+        assert_eq!(
+            decode_delta(&encoded),
+            Err(DecodeError::InvalidDeflate { offset: 0 })
+        );
+        // No two bytes of DEFLATE expand to 4000.
+        let mut encoded = vec![OP_RAW_DEFLATE];
+        write_varint(&mut encoded, 4000);
+        write_varint(&mut encoded, 2);
+        encoded.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            decode_delta(&encoded),
+            Err(DecodeError::InvalidDeflate { offset: 0 })
+        );
+    }
+}
@@ -0,0 +1,1216 @@
+//! Rabin-Karp fingerprinting used to seed matches, usable on its own.
+
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::{collections::VecDeque, io};
+
+const M: usize = 1e9 as usize + 7;
+const B: usize = 100;
+const M2: usize = 998_244_353;
+const B2: usize = 257;
+const M64: u64 = (1 << 61) - 1;
+const B64: u64 = 1_000_003;
+// `B64^-1 mod M64`.
+const INV_B64: u64 = 1_229_363_429_801_019_168;
+
+/// Parameters of the polynomial rolling hash.
+///
+/// `base` should exceed the alphabet size (by default each byte is mapped to
+/// `1..=256`, see `SymbolMap`) and `modulus` should be a prime larger than `base`.
+/// Products are computed without overflow, so any modulus below `2^62` can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashParams {
+    pub base: usize,
+    pub modulus: usize,
+    pub symbol_map: SymbolMap,
+}
+
+impl HashParams {
+    /// `base` and `modulus` with the default `SymbolMap`.
+    pub fn new(base: usize, modulus: usize) -> Self {
+        Self {
+            base,
+            modulus,
+            symbol_map: SymbolMap::default(),
+        }
+    }
+
+    pub fn symbol_map(mut self, symbol_map: SymbolMap) -> Self {
+        self.symbol_map = symbol_map;
+        self
+    }
+}
+
+impl Default for HashParams {
+    fn default() -> Self {
+        Self::new(B, M)
+    }
+}
+
+/// How `PolynomialHasher` turns an element into the value folded into the hash.
+///
+/// Each mapping starts from `Symbol::to_usize`, which is `x + 1` for the symbols
+/// implemented here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolMap {
+    /// `x.to_usize()`, so that runs of zeros still change the hash.
+    #[default]
+    Shifted,
+    /// `x.to_usize() - 1`, the value of the element itself.
+    Identity,
+    /// `table[x.to_usize() - 1]`, looking the element up by its value. Values beyond
+    /// the table map as `Shifted`.
+    Table(&'static [usize]),
+}
+
+impl SymbolMap {
+    /// The value `x` is folded into the hash as.
+    pub fn map<T: Symbol>(self, x: T) -> usize {
+        let value = x.to_usize();
+        match self {
+            SymbolMap::Shifted => value,
+            SymbolMap::Identity => value.wrapping_sub(1),
+            SymbolMap::Table(table) => table.get(value.wrapping_sub(1)).copied().unwrap_or(value),
+        }
+    }
+}
+
+/// An element of the sequences being diffed.
+///
+/// `to_usize` maps an element to the value folded into the polynomial hash. Bytes
+/// are mapped to `x + 1` so that runs of zeros still change the hash; `SymbolMap`
+/// can map them differently.
+///
+/// `normalize` applies a `DeltaOptions::normalize` mapping, which is defined on
+/// bytes; other elements are left as they are.
+///
+/// `common_prefix_len` and `common_suffix_len` extend matches from their seeds. They
+/// compare no further than the shorter of their inputs, so a seed in the last window
+/// of either sequence extends up to its end and never past it. With the `fast-extend`
+/// feature, bytes compare eight at a time.
+pub trait Symbol: Copy + Eq + Hash {
+    fn to_usize(self) -> usize;
+
+    fn normalize(self, f: fn(u8) -> u8) -> Self {
+        let _ = f;
+        self
+    }
+
+    fn common_prefix_len(a: &[Self], b: &[Self]) -> usize {
+        a.iter().zip(b).take_while(|(x, y)| x == y).count()
+    }
+
+    fn common_suffix_len(a: &[Self], b: &[Self]) -> usize {
+        a.iter()
+            .rev()
+            .zip(b.iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count()
+    }
+}
+
+impl Symbol for u8 {
+    fn to_usize(self) -> usize {
+        (self as usize).wrapping_add(1)
+    }
+
+    fn normalize(self, f: fn(u8) -> u8) -> Self {
+        f(self)
+    }
+
+    #[cfg(feature = "fast-extend")]
+    fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+        let len = core::cmp::min(a.len(), b.len());
+        let (a, b) = (&a[..len], &b[..len]);
+        let mut i = 0;
+        for (x, y) in a.chunks_exact(8).zip(b.chunks_exact(8)) {
+            // Little-endian, so the lowest differing bit is in the first differing byte.
+            let diff = word(x) ^ word(y);
+            if diff != 0 {
+                return i + diff.trailing_zeros() as usize / 8;
+            }
+            i += 8;
+        }
+        i + a[i..]
+            .iter()
+            .zip(&b[i..])
+            .take_while(|(x, y)| x == y)
+            .count()
+    }
+
+    #[cfg(feature = "fast-extend")]
+    fn common_suffix_len(a: &[u8], b: &[u8]) -> usize {
+        let len = core::cmp::min(a.len(), b.len());
+        let (a, b) = (&a[a.len() - len..], &b[b.len() - len..]);
+        let mut i = 0;
+        for (x, y) in a.rchunks_exact(8).zip(b.rchunks_exact(8)) {
+            let diff = word(x) ^ word(y);
+            if diff != 0 {
+                return i + diff.leading_zeros() as usize / 8;
+            }
+            i += 8;
+        }
+        i + a[..len - i]
+            .iter()
+            .rev()
+            .zip(b[..len - i].iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count()
+    }
+}
+
+// Eight bytes read as a little-endian word.
+#[cfg(feature = "fast-extend")]
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+macro_rules! impl_symbol {
+    ($($t:ty),*) => {
+        $(
+            impl Symbol for $t {
+                fn to_usize(self) -> usize {
+                    (self as usize).wrapping_add(1)
+                }
+            }
+        )*
+    };
+}
+
+impl_symbol!(u16, u32, u64, usize, i8, i16, i32, i64, isize, char);
+
+/// A fingerprint over a fixed-size window that can be updated as the window slides.
+pub trait WindowHasher<T = u8> {
+    /// Resets the state to `window` and returns its hash.
+    fn init(&mut self, window: &[T]) -> u64;
+
+    /// Slides the window by one element and returns the hash of the new window.
+    fn roll(&mut self, entering: T, leaving: T) -> u64;
+
+    /// Slides the window back by one element: `entering` becomes its first element
+    /// and `leaving` drops off its end. Returns the hash of the new window, or `None`
+    /// if this hasher cannot roll backwards, in which case the window is re-hashed
+    /// with `init`.
+    fn roll_back(&mut self, entering: T, leaving: T) -> Option<u64> {
+        let _ = (entering, leaving);
+        None
+    }
+}
+
+/// The Rabin-Karp polynomial hash used by `delta`.
+#[derive(Debug, Clone, Default)]
+pub struct PolynomialHasher {
+    params: HashParams,
+    hash: usize,
+    base_pow: usize,
+    // The window length `base_pow` was computed for, so that `init` on windows of
+    // the same length can reuse it.
+    pow_len: Option<usize>,
+    // `base^-1` and `base^(len - 1)`, computed on the first `roll_back` after
+    // `base_pow` changes.
+    back_pows: Option<(usize, usize)>,
+}
+
+impl PolynomialHasher {
+    pub fn new(params: HashParams) -> Self {
+        Self {
+            params,
+            hash: 0,
+            base_pow: 1,
+            pow_len: None,
+            back_pows: None,
+        }
+    }
+
+    // The inverse of the base only exists for a prime modulus; otherwise `None`.
+    fn back_pows(&mut self) -> Option<(usize, usize)> {
+        if self.back_pows.is_none() {
+            let HashParams { base, modulus, .. } = self.params;
+            let inv_base = modpow(base, modulus.checked_sub(2)?, modulus);
+            if mulmod(base, inv_base, modulus) != 1 % modulus {
+                return None;
+            }
+            self.back_pows = Some((inv_base, mulmod(self.base_pow, inv_base, modulus)));
+        }
+        self.back_pows
+    }
+}
+
+impl<T: Symbol> WindowHasher<T> for PolynomialHasher {
+    fn init(&mut self, window: &[T]) -> u64 {
+        let HashParams {
+            base,
+            modulus,
+            symbol_map,
+        } = self.params;
+        if self.pow_len != Some(window.len()) {
+            self.base_pow = modpow(base, window.len(), modulus);
+            self.pow_len = Some(window.len());
+            self.back_pows = None;
+        }
+        self.hash = window.iter().fold(0, |hash, &x| {
+            (mulmod(hash, base, modulus) + symbol_map.map(x) % modulus) % modulus
+        });
+        self.hash as u64
+    }
+
+    fn roll(&mut self, entering: T, leaving: T) -> u64 {
+        let HashParams {
+            base,
+            modulus,
+            symbol_map,
+        } = self.params;
+        let v1 = mulmod(base, self.hash, modulus);
+        let v2 = symbol_map.map(entering) % modulus;
+        let v3 = mulmod(self.base_pow, symbol_map.map(leaving) % modulus, modulus);
+        self.hash = (v1 + v2 + modulus - v3) % modulus; // v1 + v2 - v3
+        self.hash as u64
+    }
+
+    fn roll_back(&mut self, entering: T, leaving: T) -> Option<u64> {
+        let HashParams {
+            modulus,
+            symbol_map,
+            ..
+        } = self.params;
+        let (inv_base, top_pow) = self.back_pows()?;
+        let v1 = (self.hash + modulus - symbol_map.map(leaving) % modulus) % modulus;
+        let v2 = mulmod(top_pow, symbol_map.map(entering) % modulus, modulus);
+        self.hash = (mulmod(v1, inv_base, modulus) + v2) % modulus; // (hash - v1) / base + v2
+        Some(self.hash as u64)
+    }
+}
+
+/// Two `PolynomialHasher`s with independent parameters combined into one fingerprint,
+/// so that two windows collide only if both hashes do.
+///
+/// The first hash fills the high 32 bits of the fingerprint and the second the low
+/// 32 bits, which keeps both intact as long as the moduli fit in 32 bits.
+#[derive(Debug, Clone)]
+pub struct DoubleHasher {
+    first: PolynomialHasher,
+    second: PolynomialHasher,
+}
+
+impl DoubleHasher {
+    pub fn new(first: HashParams, second: HashParams) -> Self {
+        Self {
+            first: PolynomialHasher::new(first),
+            second: PolynomialHasher::new(second),
+        }
+    }
+
+    /// `first` paired with the default second hash, which maps elements with the
+    /// same `SymbolMap` as `first`.
+    pub fn with_params(first: HashParams) -> Self {
+        Self::new(first, HashParams::new(B2, M2).symbol_map(first.symbol_map))
+    }
+
+    fn combine(first: u64, second: u64) -> u64 {
+        (first << 32) ^ second
+    }
+}
+
+impl Default for DoubleHasher {
+    fn default() -> Self {
+        Self::with_params(HashParams::default())
+    }
+}
+
+impl<T: Symbol> WindowHasher<T> for DoubleHasher {
+    fn init(&mut self, window: &[T]) -> u64 {
+        Self::combine(self.first.init(window), self.second.init(window))
+    }
+
+    fn roll(&mut self, entering: T, leaving: T) -> u64 {
+        Self::combine(
+            self.first.roll(entering, leaving),
+            self.second.roll(entering, leaving),
+        )
+    }
+
+    fn roll_back(&mut self, entering: T, leaving: T) -> Option<u64> {
+        Some(Self::combine(
+            self.first.roll_back(entering, leaving)?,
+            self.second.roll_back(entering, leaving)?,
+        ))
+    }
+}
+
+/// A polynomial hash computed in `u64` with the Mersenne prime `2^61 - 1` as modulus.
+///
+/// Windows are hashed the same way as `PolynomialHasher` with `SymbolMap::Shifted`,
+/// but the far larger modulus makes collisions between distinct windows much rarer
+/// on large inputs.
+#[derive(Debug, Clone, Default)]
+pub struct Polynomial64Hasher {
+    hash: u64,
+    base_pow: u64,
+}
+
+impl Polynomial64Hasher {
+    fn to_u64<T: Symbol>(x: T) -> u64 {
+        x.to_usize() as u64 % M64
+    }
+}
+
+impl<T: Symbol> WindowHasher<T> for Polynomial64Hasher {
+    fn init(&mut self, window: &[T]) -> u64 {
+        self.base_pow = modpow64(B64, window.len() as u64);
+        self.hash = window
+            .iter()
+            .fold(0, |hash, &x| (mulmod64(hash, B64) + Self::to_u64(x)) % M64);
+        self.hash
+    }
+
+    fn roll(&mut self, entering: T, leaving: T) -> u64 {
+        let v1 = mulmod64(B64, self.hash);
+        let v2 = Self::to_u64(entering);
+        let v3 = mulmod64(self.base_pow, Self::to_u64(leaving));
+        self.hash = (v1 + v2 + M64 - v3) % M64; // v1 + v2 - v3
+        self.hash
+    }
+
+    fn roll_back(&mut self, entering: T, leaving: T) -> Option<u64> {
+        let v1 = (self.hash + M64 - Self::to_u64(leaving)) % M64;
+        let v2 = mulmod64(mulmod64(self.base_pow, INV_B64), Self::to_u64(entering));
+        self.hash = (mulmod64(v1, INV_B64) + v2) % M64; // (hash - v1) / base + v2
+        Some(self.hash)
+    }
+}
+
+#[derive(Clone)]
+pub struct RollingHash<'a, T = u8, H = PolynomialHasher> {
+    data: &'a [T],
+    // The window length asked for, and the one used: no longer than `data`.
+    max_hash_len: usize,
+    hash_len: usize,
+    // Windows `front..back` have not been yielded yet.
+    front: usize,
+    back: usize,
+    // The window the front hasher is initialized at.
+    start: usize,
+    last: Option<usize>,
+    hasher: H,
+    // Rolls from the back end, so that both ends can be consumed independently.
+    back_hasher: Option<H>,
+}
+
+impl<'a, T: Symbol> RollingHash<'a, T> {
+    pub fn new(data: &'a [T], hash_len: usize) -> Self {
+        Self::with_params(data, hash_len, HashParams::default())
+    }
+
+    pub fn with_params(data: &'a [T], hash_len: usize, params: HashParams) -> Self {
+        Self::with_hasher(data, hash_len, PolynomialHasher::new(params))
+    }
+}
+
+impl<'a, T: Copy, H: WindowHasher<T>> RollingHash<'a, T, H> {
+    pub fn with_hasher(data: &'a [T], hash_len: usize, hasher: H) -> Self {
+        let mut hashes = Self {
+            data,
+            max_hash_len: hash_len,
+            hash_len: 0,
+            front: 0,
+            back: 0,
+            start: 0,
+            last: None,
+            hasher,
+            back_hasher: None,
+        };
+        hashes.reset(data);
+        hashes
+    }
+
+    /// Starts over on `data` with the same window length and hasher, as if built
+    /// again with `with_hasher`. The hasher keeps what it caches for the window
+    /// length, such as `PolynomialHasher`'s power of the base, so one iterator can
+    /// be reused across many buffers.
+    pub fn reset(&mut self, data: &'a [T]) {
+        let hash_len = core::cmp::min(data.len(), self.max_hash_len);
+        // The first window may span the whole data, so there is one more window
+        // than there are steps to slide.
+        let windows = if hash_len == 0 {
+            0
+        } else {
+            data.len() - hash_len + 1
+        };
+        self.data = data;
+        self.hash_len = hash_len;
+        self.front = 0;
+        self.back = windows;
+        self.start = 0;
+        self.last = None;
+        // The back end re-hashes its first window with `init` when it starts over.
+        self.back_hasher = None;
+    }
+
+    /// The indices of the windows whose hash has all the bits of `mask` clear, as
+    /// cut points for content-defined chunking. Whether a window is an anchor depends
+    /// only on its content, so an edit moves only the anchors whose windows it
+    /// touches. With a `mask` of `2^k - 1`, below the hasher's modulus, about one
+    /// window in `2^k` is an anchor.
+    pub fn anchors(self, mask: usize) -> impl Iterator<Item = usize> + 'a
+    where
+        H: 'a,
+    {
+        self.filter(move |&(hash, _)| hash & mask as u64 == 0)
+            .map(|(_, i)| i)
+    }
+
+    // Skips the windows before `front` without hashing them.
+    pub(crate) fn starting_at(mut self, front: usize) -> Self {
+        self.front = core::cmp::min(front, self.back);
+        self.start = self.front;
+        self
+    }
+}
+
+impl<'a, T, H> RollingHash<'a, T, H> {
+    /// The window whose hash was yielded last, from either end, or an empty slice
+    /// before the first.
+    pub fn window(&self) -> &'a [T] {
+        match self.last {
+            Some(index) => &self.data[index..index + self.hash_len],
+            None => &self.data[..0],
+        }
+    }
+}
+
+impl<'a, T: Copy, H: WindowHasher<T>> Iterator for RollingHash<'a, T, H> {
+    type Item = (u64, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        let index = self.front;
+        let hash = if index == self.start {
+            self.hasher.init(&self.data[index..index + self.hash_len])
+        } else {
+            let entering = self.data[index - 1 + self.hash_len];
+            let leaving = self.data[index - 1];
+            self.hasher.roll(entering, leaving)
+        };
+
+        self.front += 1;
+        self.last = Some(index);
+        Some((hash, index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back.saturating_sub(self.front);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: Copy, H: WindowHasher<T> + Clone> DoubleEndedIterator for RollingHash<'a, T, H> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front >= self.back {
+            return None;
+        }
+
+        let index = self.back - 1;
+        let window = &self.data[index..index + self.hash_len];
+        let hash = match &mut self.back_hasher {
+            Some(hasher) => {
+                let entering = self.data[index];
+                let leaving = self.data[index + self.hash_len];
+                match hasher.roll_back(entering, leaving) {
+                    Some(hash) => hash,
+                    None => hasher.init(window),
+                }
+            }
+            None => self.back_hasher.insert(self.hasher.clone()).init(window),
+        };
+
+        self.back = index;
+        self.last = Some(index);
+        Some((hash, index))
+    }
+}
+
+impl<'a, T: Copy, H: WindowHasher<T>> ExactSizeIterator for RollingHash<'a, T, H> {}
+
+/// Same as `RollingHash` over bytes, but reads them from `reader` as it goes, holding
+/// only the current window in memory.
+///
+/// The reader is read one byte at a time, so unbuffered readers such as files are
+/// best wrapped in a `BufReader`. After a read error is yielded, the iterator ends.
+#[cfg(feature = "std")]
+pub struct StreamingRollingHash<R, H = PolynomialHasher> {
+    reader: R,
+    hash_len: usize,
+    window: VecDeque<u8>,
+    // The index of the next window to yield.
+    index: usize,
+    done: bool,
+    hasher: H,
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read> StreamingRollingHash<R> {
+    pub fn new(reader: R, hash_len: usize) -> Self {
+        Self::with_hasher(
+            reader,
+            hash_len,
+            PolynomialHasher::new(HashParams::default()),
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, H: WindowHasher> StreamingRollingHash<R, H> {
+    pub fn with_hasher(reader: R, hash_len: usize, hasher: H) -> Self {
+        Self {
+            reader,
+            hash_len,
+            window: VecDeque::with_capacity(hash_len),
+            index: 0,
+            done: hash_len == 0,
+            hasher,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = 0;
+        loop {
+            match self.reader.read(core::slice::from_mut(&mut byte)) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn next_hash(&mut self) -> io::Result<Option<u64>> {
+        if self.index == 0 {
+            // Like `RollingHash`, data shorter than `hash_len` is a single window.
+            while self.window.len() < self.hash_len {
+                match self.read_byte()? {
+                    Some(byte) => self.window.push_back(byte),
+                    None => break,
+                }
+            }
+            if self.window.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some(self.hasher.init(self.window.make_contiguous())));
+        }
+        if self.window.len() < self.hash_len {
+            return Ok(None);
+        }
+        let Some(entering) = self.read_byte()? else {
+            return Ok(None);
+        };
+        let leaving = self.window.pop_front().unwrap_or_default();
+        self.window.push_back(entering);
+        Ok(Some(self.hasher.roll(entering, leaving)))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: io::Read, H: WindowHasher> Iterator for StreamingRollingHash<R, H> {
+    type Item = io::Result<(u64, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_hash() {
+            Ok(Some(hash)) => {
+                let index = self.index;
+                self.index += 1;
+                Some(Ok((hash, index)))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The `PolynomialHasher` fingerprint of `window` with the given base and modulus.
+pub fn rolling_hash_of<T: Symbol>(window: &[T], base: usize, modulus: usize) -> usize {
+    PolynomialHasher::new(HashParams::new(base, modulus)).init(window) as usize
+}
+
+/// `base^exponent % modulus`, for any modulus below `2^62` and any base. Products
+/// that would overflow `usize` are computed in `u128`.
+///
+/// # Panics
+///
+/// If `modulus` is 0.
+pub fn modpow(base: usize, exponent: usize, modulus: usize) -> usize {
+    assert_ne!(modulus, 0, "`modulus` is 0");
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = mulmod(result, base, modulus);
+        }
+        base = mulmod(base, base, modulus);
+        exponent /= 2;
+    }
+    result
+}
+
+// `x * y % modulus`, widened to `u128` only when the product overflows `usize`.
+fn mulmod(x: usize, y: usize, modulus: usize) -> usize {
+    match x.checked_mul(y) {
+        Some(product) => product % modulus,
+        None => (x as u128 * y as u128 % modulus as u128) as usize,
+    }
+}
+
+fn mulmod64(x: u64, y: u64) -> u64 {
+    (x as u128 * y as u128 % M64 as u128) as u64
+}
+
+fn modpow64(base: u64, exponent: u64) -> u64 {
+    let mut result = 1;
+    let mut base = base;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = mulmod64(result, base);
+        }
+        base = mulmod64(base, base);
+        exponent /= 2;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+
+    #[test]
+    fn modpow_31_41() {
+        let result = modpow(31, 41, M);
+        assert_eq!(result, 411956758);
+    }
+
+    #[test]
+    fn modpow_small_modulus() {
+        for base in [0, 1, 2, 3, 10] {
+            assert_eq!(modpow(base, 0, 2), 1);
+            assert_eq!(modpow(base, 5, 2), base % 2);
+            assert_eq!(modpow(base, 5, 1), 0);
+            assert_eq!(modpow(base, 0, 1), 0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "`modulus` is 0")]
+    fn modpow_zero_modulus() {
+        modpow(3, 4, 0);
+    }
+
+    #[test]
+    fn modpow_large_base() {
+        // Compared against repeated multiplication in `u128`.
+        let naive = |base: usize, exponent: usize, modulus: usize| {
+            (0..exponent).fold(1 % modulus as u128, |result, _| {
+                result * base as u128 % modulus as u128
+            }) as usize
+        };
+        for base in [u32::MAX as usize - 4, u32::MAX as usize, usize::MAX] {
+            for modulus in [M, u32::MAX as usize - 1, M64 as usize] {
+                for exponent in [0, 1, 2, 3, 17, 100] {
+                    assert_eq!(
+                        modpow(base, exponent, modulus),
+                        naive(base, exponent, modulus)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rolling_hash_0101x() {
+        let mut hashes = RollingHash::new(&[0, 1, 0, 1], 3);
+        assert_eq!(hashes.next(), Some((10201, 0)));
+        assert_eq!(hashes.next(), Some((20102, 1)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_010101() {
+        let mut hashes = RollingHash::new(&[0, 1, 0, 1, 0, 1], 3);
+        assert_eq!(hashes.next(), Some((10201, 0)));
+        assert_eq!(hashes.next(), Some((20102, 1)));
+        assert_eq!(hashes.next(), Some((10201, 2)));
+        assert_eq!(hashes.next(), Some((20102, 3)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_0123() {
+        let hashes: Vec<_> = RollingHash::new(&[0, 1, 2, 3], 2).map(|(_, i)| i).collect();
+        assert_eq!(hashes, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rolling_hash_whole_data() {
+        let mut hashes = RollingHash::new(&[0, 1, 0], 3);
+        assert_eq!(hashes.next(), Some((10201, 0)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_shorter_than_hash_len() {
+        let mut hashes = RollingHash::new(&[0, 1], 3);
+        assert_eq!(hashes.next(), Some((102, 0)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_empty() {
+        let mut hashes = RollingHash::<u8>::new(&[], 3);
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn common_prefix_and_suffix_len_same_as_u16() {
+        let wide = |bytes: &[u8]| -> Vec<u16> { bytes.iter().map(|&x| x as u16).collect() };
+        for len in 0..40 {
+            let a: Vec<u8> = (0..len as u8).collect();
+            for diff in 0..=len {
+                let mut b = a.clone();
+                if diff < len {
+                    b[diff] = 0xFF;
+                }
+                for skip in [0, 1, 9] {
+                    let short = &b[core::cmp::min(skip, len)..];
+                    let (wa, wb) = (wide(&a), wide(short));
+                    assert_eq!(
+                        u8::common_prefix_len(&a, short),
+                        u16::common_prefix_len(&wa, &wb)
+                    );
+                    assert_eq!(
+                        u8::common_suffix_len(&a, short),
+                        u16::common_suffix_len(&wa, &wb)
+                    );
+                    assert_eq!(
+                        u8::common_suffix_len(short, &a),
+                        u16::common_suffix_len(&wb, &wa)
+                    );
+                }
+                assert_eq!(u8::common_prefix_len(&a, &b), diff);
+                let suffix = if diff < len { len - diff - 1 } else { len };
+                assert_eq!(u8::common_suffix_len(&a, &b), suffix);
+            }
+        }
+    }
+
+    #[test]
+    fn symbol_map_identity() {
+        let shifted = HashParams::new(100, M);
+        let identity = shifted.symbol_map(SymbolMap::Identity);
+        assert_eq!(PolynomialHasher::new(shifted).init(&[0u8, 1]), 102);
+        assert_eq!(PolynomialHasher::new(identity).init(&[0u8, 1]), 1);
+        let data = [3u8, 0, 0, 7, 1, 0, 9];
+        let hashes: Vec<_> =
+            RollingHash::with_hasher(&data, 3, PolynomialHasher::new(identity)).collect();
+        for (h, i) in hashes {
+            assert_eq!(h, PolynomialHasher::new(identity).init(&data[i..i + 3]));
+        }
+    }
+
+    #[test]
+    fn symbol_map_table() {
+        static FOLD: [usize; 256] = {
+            let mut table = [0; 256];
+            let mut i = 0;
+            while i < 256 {
+                table[i] = (i as u8).to_ascii_lowercase() as usize;
+                i += 1;
+            }
+            table
+        };
+        let params = HashParams::default().symbol_map(SymbolMap::Table(&FOLD));
+        let mut hasher = PolynomialHasher::new(params);
+        assert_eq!(hasher.init(b"Hello"), hasher.init(b"hELLO"));
+        assert_ne!(hasher.init(b"Hello"), hasher.init(b"Hallo"));
+        let mut double = DoubleHasher::with_params(params);
+        assert_eq!(double.init(b"Hello"), double.init(b"hello"));
+        // Values beyond the table map as `Shifted`.
+        assert_eq!(SymbolMap::Table(&FOLD[..4]).map(b'A'), b'A' as usize + 1);
+        assert_eq!(SymbolMap::Table(&FOLD).map(b'A'), b'a' as usize);
+    }
+
+    #[test]
+    fn rolling_hash_u16_same_as_u8() {
+        let bytes: [u8; 6] = [0, 1, 0, 255, 0, 1];
+        let wide: Vec<u16> = bytes.iter().map(|&x| x as u16).collect();
+        let expected: Vec<_> = RollingHash::new(&bytes, 3).collect();
+        let result: Vec<_> = RollingHash::new(&wide, 3).collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn rolling_hash_abcdefg() {
+        let mut hashes = RollingHash::new("abcdefg".as_ref(), 4).step_by(2);
+        let expected = [99000101, 100010202, 101020303, 102030404];
+        assert_eq!(hashes.next(), Some((expected[0], 0)));
+        assert_eq!(hashes.next(), Some((expected[2], 2)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_size_hint() {
+        let data: Vec<u8> = (0..20).collect();
+        for len in [0, 3, 10, 20] {
+            for hash_len in [0, 1, 3, 7, 20, 25] {
+                let mut rh = RollingHash::new(&data[..len], hash_len);
+                let mut expected = RollingHash::new(&data[..len], hash_len).count();
+                assert_eq!(rh.len(), expected, "len {len} hash_len {hash_len}");
+                while rh.next().is_some() {
+                    expected -= 1;
+                    assert_eq!(rh.size_hint(), (expected, Some(expected)));
+                }
+                assert_eq!(expected, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn rolling_hash_window() {
+        let data = b"abcdefg";
+        let mut rh = RollingHash::new(data, 3);
+        assert!(rh.window().is_empty());
+        while let Some((hash, i)) = rh.next() {
+            assert_eq!(rh.window(), &data[i..i + 3]);
+            assert_eq!(PolynomialHasher::default().init(rh.window()), hash);
+        }
+        assert_eq!(rh.window(), b"efg");
+    }
+
+    #[test]
+    fn rolling_hash_window_whole_data() {
+        let mut rh = RollingHash::new(b"ab", 5);
+        assert_eq!(rh.next(), Some((rh.hasher.clone().init(b"ab"), 0)));
+        assert_eq!(rh.window(), b"ab");
+        assert_eq!(rh.next(), None);
+        assert_eq!(rh.window(), b"ab");
+    }
+
+    #[test]
+    fn rolling_hash_with_params() {
+        let params = HashParams::new(257, 65521);
+        let data = [0, 1, 2, 0, 1, 2];
+        let hashes: Vec<_> = RollingHash::with_params(&data, 3, params).collect();
+        let expected = |w: &[u8]| w.iter().fold(0, |h, &x| (h * 257 + x as usize + 1) % 65521);
+        assert_eq!(hashes.len(), 4);
+        for (hash, i) in hashes {
+            assert_eq!(hash, expected(&data[i..i + 3]) as u64);
+        }
+    }
+
+    #[test]
+    fn rolling_hash_default_params() {
+        let data = "abcdefg".as_bytes();
+        let default: Vec<_> = RollingHash::new(data, 4).collect();
+        let explicit: Vec<_> = RollingHash::with_params(data, 4, HashParams::default()).collect();
+        assert_eq!(default, explicit);
+    }
+
+    #[test]
+    fn rolling_hash64_matches_naive() {
+        let data = vec![255u8; 40];
+        let expected = data[..20].iter().fold(0u128, |h, &x| {
+            (h * B64 as u128 + x as u128 + 1) % M64 as u128
+        }) as u64;
+        let hashes: Vec<_> =
+            RollingHash::with_hasher(&data, 20, Polynomial64Hasher::default()).collect();
+        assert_eq!(hashes.len(), 21);
+        assert!(hashes.iter().all(|&(hash, _)| hash == expected));
+    }
+
+    #[test]
+    fn rolling_hash64_no_collision() {
+        // Collides under the 32-bit modulus (see extract_match_hash_collision).
+        let mut hasher = Polynomial64Hasher::default();
+        assert_ne!(
+            hasher.init(&[0, 0, 0, 0, 0]),
+            hasher.init(&[10, 0, 0, 0, 7])
+        );
+    }
+
+    #[test]
+    fn rolling_hash_double_matches_init() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+        for (hash, i) in RollingHash::with_hasher(data, 5, DoubleHasher::default()) {
+            assert_eq!(DoubleHasher::default().init(&data[i..i + 5]), hash);
+        }
+    }
+
+    #[test]
+    fn double_hasher_separates_single_collisions() {
+        // With modulus 7 these windows collide in the first hash alone.
+        let weak = HashParams::new(3, 7);
+        let (x, y) = ([1u8, 0], [0u8, 3]);
+        assert_eq!(
+            PolynomialHasher::new(weak).init(&x),
+            PolynomialHasher::new(weak).init(&y)
+        );
+        let mut double = DoubleHasher::with_params(weak);
+        assert_ne!(double.init(&x), double.init(&y));
+    }
+
+    #[test]
+    fn modpow_large_modulus() {
+        let p = M64 as usize;
+        assert_eq!(modpow(B64 as usize, p - 1, p), 1);
+        assert_eq!(modpow(2, 61, p), 1);
+        assert_eq!(mulmod(p - 1, p - 1, p), 1);
+    }
+
+    #[test]
+    fn rolling_hash_large_modulus() {
+        // Same modulus and base as `Polynomial64Hasher`, whose hashes are computed
+        // with `u128` products throughout.
+        let params = HashParams::new(B64 as usize, M64 as usize);
+        let data: Vec<u8> = (0..200).map(|i| (i * 37 % 256) as u8).collect();
+        let expected: Vec<_> =
+            RollingHash::with_hasher(&data, 16, Polynomial64Hasher::default()).collect();
+        let hashes: Vec<_> = RollingHash::with_params(&data, 16, params).collect();
+        assert_eq!(hashes, expected);
+    }
+
+    #[test]
+    fn modpow64_fermat() {
+        assert_eq!(modpow64(B64, M64 - 1), 1);
+    }
+
+    #[test]
+    fn rolling_hash_exceeds_mod() {
+        let data = vec![255u8; 20];
+        let mut hashes = RollingHash::new(&data, 10).step_by(3);
+        assert_eq!(hashes.next(), Some((757588431, 0)));
+        assert_eq!(hashes.next(), Some((757588431, 3)));
+        assert_eq!(hashes.next(), Some((757588431, 6)));
+        assert_eq!(hashes.next(), Some((757588431, 9)));
+        assert_eq!(hashes.next(), None);
+    }
+
+    #[test]
+    fn rolling_hash_of_matches_rolling_hash() {
+        let data = b"abcdefg";
+        for (hash, i) in RollingHash::new(data, 3) {
+            assert_eq!(rolling_hash_of(&data[i..i + 3], B, M) as u64, hash);
+        }
+        assert_eq!(rolling_hash_of(b"", B, M), 0);
+    }
+
+    fn assert_rev_matches_forward<H: WindowHasher + Clone>(hasher: H) {
+        let data: Vec<u8> = (0..100u32).map(|i| (i * i % 251) as u8).collect();
+        for len in [0, 1, 2, 7, 99, 100] {
+            let data = &data[..len];
+            for hash_len in [0, 1, 3, 16, 100, 120] {
+                let forward: Vec<_> =
+                    RollingHash::with_hasher(data, hash_len, hasher.clone()).collect();
+                let mut reverse: Vec<_> = RollingHash::with_hasher(data, hash_len, hasher.clone())
+                    .rev()
+                    .collect();
+                reverse.reverse();
+                assert_eq!(forward, reverse, "len {len} hash_len {hash_len}");
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn assert_streaming_matches_in_memory<H: WindowHasher + Clone>(hasher: H) {
+        let data: Vec<u8> = (0..100u32).map(|i| (i * i % 251) as u8).collect();
+        for len in [0, 1, 2, 7, 99, 100] {
+            let data = &data[..len];
+            for hash_len in [0, 1, 3, 16, 100, 120] {
+                let streamed: Vec<_> = StreamingRollingHash::with_hasher(
+                    std::io::Cursor::new(data),
+                    hash_len,
+                    hasher.clone(),
+                )
+                .collect::<std::io::Result<_>>()
+                .unwrap();
+                let in_memory: Vec<_> =
+                    RollingHash::with_hasher(data, hash_len, hasher.clone()).collect();
+                assert_eq!(streamed, in_memory, "len {len} hash_len {hash_len}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn streaming_rolling_hash_matches_in_memory() {
+        assert_streaming_matches_in_memory(PolynomialHasher::default());
+        assert_streaming_matches_in_memory(DoubleHasher::default());
+        assert_streaming_matches_in_memory(Polynomial64Hasher::default());
+        let data = include_bytes!("../examples/a.txt");
+        let streamed = StreamingRollingHash::new(&data[..], 10).map(Result::unwrap);
+        assert!(streamed.eq(RollingHash::new(data, 10)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn streaming_rolling_hash_read_error() {
+        use std::io::{ErrorKind, Read};
+        // Fails after the first five bytes, and is interrupted before every byte.
+        struct Flaky(usize, bool);
+        impl Read for Flaky {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 = !self.1;
+                if self.1 {
+                    return Err(ErrorKind::Interrupted.into());
+                }
+                if self.0 == 5 {
+                    return Err(ErrorKind::BrokenPipe.into());
+                }
+                buf[0] = self.0 as u8;
+                self.0 += 1;
+                Ok(1)
+            }
+        }
+        let mut hashes = StreamingRollingHash::new(Flaky(0, false), 3);
+        let ok: Vec<_> = hashes.by_ref().take(3).map(Result::unwrap).collect();
+        assert!(ok.into_iter().eq(RollingHash::new(&[0, 1, 2, 3, 4], 3)));
+        let e = hashes.next().unwrap().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::BrokenPipe);
+        assert!(hashes.next().is_none());
+    }
+
+    #[test]
+    fn rolling_hash_rev_matches_forward() {
+        assert_rev_matches_forward(PolynomialHasher::default());
+        assert_rev_matches_forward(DoubleHasher::default());
+        assert_rev_matches_forward(Polynomial64Hasher::default());
+        assert_rev_matches_forward(PolynomialHasher::new(HashParams::new(
+            B64 as usize,
+            M64 as usize,
+        )));
+    }
+
+    #[test]
+    fn rolling_hash_rev_without_inverse() {
+        // 256 is not prime, so the base has no inverse and windows are re-hashed.
+        assert_rev_matches_forward(PolynomialHasher::new(HashParams::new(2, 256)));
+    }
+
+    #[test]
+    fn rolling_hash_both_ends() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+        let forward: Vec<_> = RollingHash::new(data, 5).collect();
+        let mut rh = RollingHash::new(data, 5);
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while rh.len() > 0 {
+            front.push(rh.next().unwrap());
+            assert_eq!(rh.window(), &data[front.last().unwrap().1..][..5]);
+            if let Some(item) = rh.next_back() {
+                back.push(item);
+                assert_eq!(rh.window(), &data[item.1..item.1 + 5]);
+            }
+        }
+        assert_eq!(rh.next(), None);
+        assert_eq!(rh.next_back(), None);
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, forward);
+    }
+
+    #[test]
+    fn rolling_hash_reset_same_as_new() {
+        let buffers: [&[u8]; 5] = [
+            b"the quick brown fox",
+            b"abc",
+            b"jumps over the lazy dog",
+            b"",
+            b"the quick brown fox",
+        ];
+        let mut rh = RollingHash::new(buffers[0], 5);
+        let mut double = RollingHash::with_hasher(buffers[0], 5, DoubleHasher::default());
+        for data in buffers {
+            rh.reset(data);
+            double.reset(data);
+            assert_eq!(rh.len(), RollingHash::new(data, 5).len());
+            assert_eq!(rh.window(), b"");
+            // Part of each buffer is left unread before the next reset.
+            let front: Vec<_> = rh.by_ref().take(4).collect();
+            assert_eq!(front, RollingHash::new(data, 5).take(4).collect::<Vec<_>>());
+            let back: Vec<_> = double.by_ref().rev().take(3).collect();
+            let fresh = RollingHash::with_hasher(data, 5, DoubleHasher::default());
+            assert_eq!(back, fresh.rev().take(3).collect::<Vec<_>>());
+        }
+        rh.reset(buffers[2]);
+        assert!(rh.eq(RollingHash::new(buffers[2], 5)));
+    }
+
+    #[test]
+    fn rolling_hash_anchors() {
+        let a = include_bytes!("../examples/a.txt");
+        let anchors: Vec<usize> = RollingHash::new(a, 16).anchors(0xF).collect();
+        assert_eq!(
+            anchors,
+            RollingHash::new(a, 16).anchors(0xF).collect::<Vec<_>>()
+        );
+        let expected: Vec<usize> = RollingHash::new(a, 16)
+            .filter(|(hash, _)| hash % 16 == 0)
+            .map(|(_, i)| i)
+            .collect();
+        assert_eq!(anchors, expected);
+        assert!(anchors.len() > a.len() / 64 && anchors.len() < a.len() / 4);
+        // Anchors past an inserted prefix move along with the content.
+        let prefixed: Vec<u8> = b"inserted".iter().chain(a).copied().collect();
+        let shifted: Vec<usize> = RollingHash::new(&prefixed, 16)
+            .anchors(0xF)
+            .filter(|&i| i >= 8)
+            .map(|i| i - 8)
+            .collect();
+        assert_eq!(shifted, anchors);
+        assert!(RollingHash::new(a, 16).anchors(0).eq(0..a.len() - 15));
+        assert_eq!(RollingHash::new(b"", 16).anchors(0).count(), 0);
+    }
+
+    #[test]
+    fn rolling_hash_clone_continues_alike() {
+        let data = b"the quick brown fox jumps over the lazy dog";
+        let mut rh = RollingHash::new(data, 5);
+        rh.by_ref().take(7).for_each(drop);
+        rh.next_back();
+        let fork = rh.clone();
+        assert_eq!(fork.window(), rh.window());
+        assert!(fork.eq(rh.by_ref()));
+
+        let mut double = RollingHash::with_hasher(data, 5, DoubleHasher::default());
+        double.nth(10);
+        let mut fork = double.clone();
+        assert_eq!(fork.next(), double.next());
+        assert_eq!(fork.next_back(), double.next_back());
+        assert!(fork.rev().eq(double.rev()));
+    }
+
+    #[test]
+    fn roll_back_undoes_roll() {
+        let mut hasher = PolynomialHasher::default();
+        let start = hasher.init(b"abcd");
+        hasher.roll(b'e', b'a');
+        assert_eq!(hasher.roll_back(b'a', b'e'), Some(start));
+        let mut hasher = PolynomialHasher::new(HashParams::new(2, 256));
+        hasher.init(b"abcd");
+        assert_eq!(hasher.roll_back(b'z', b'd'), None);
+    }
+
+    #[test]
+    fn inverse_base64() {
+        assert_eq!(mulmod64(B64, INV_B64), 1);
+    }
+}
@@ -1,290 +1,4291 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::{vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
+#[cfg(feature = "std")]
 use std::collections::HashMap;
 
-const M: usize = 1e9 as usize + 7;
-const B: usize = 100;
+mod bsdiff;
+#[cfg(feature = "std")]
+mod chunked;
+mod compose;
+mod encode;
+pub mod hash;
+mod multi;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod partition;
+mod patch;
+mod segment;
+#[cfg(feature = "std")]
+mod streams;
+#[cfg(feature = "text")]
+mod text;
+mod update;
+mod vcdiff;
 
-#[derive(Debug, PartialEq)]
-pub enum Compression<'a> {
+pub use bsdiff::to_bsdiff_controls;
+#[cfg(feature = "std")]
+pub use chunked::delta_chunked;
+pub use compose::compose;
+pub use encode::{
+    apply, apply_windows, apply_windows_with, apply_with, decode_delta, delta_bytes, encode_delta,
+    encoded_size, ApplyError, ApplyOptions, DecodeError,
+};
+#[cfg(feature = "std")]
+pub use encode::{apply_reader, apply_reader_with, write_delta};
+#[cfg(feature = "flate2")]
+pub use encode::{encode_delta_deflated, write_delta_deflated};
+#[cfg(feature = "std")]
+pub use hash::StreamingRollingHash;
+pub use hash::{
+    DoubleHasher, HashParams, Polynomial64Hasher, PolynomialHasher, RollingHash, Symbol, SymbolMap,
+    WindowHasher,
+};
+pub use multi::{delta_multi, restore_multi, CompressionMulti};
+#[cfg(feature = "rayon")]
+pub use parallel::par_delta_with;
+pub use partition::{delta_partitions, restore_parallel, Partition};
+#[cfg(feature = "std")]
+pub use patch::{apply_file, read_patch_file, read_patch_file_with, write_patch_file};
+pub use patch::{crc32, patch_checked, PatchError};
+pub use segment::{segments, Segment};
+#[cfg(feature = "std")]
+pub use streams::delta_streams;
+#[cfg(feature = "text")]
+pub use text::render_unified;
+pub use update::{update_delta, Edit};
+pub use vcdiff::encode_vcdiff;
+
+/// One step of a delta: copy `a[la..la + len]` or insert raw elements of `b`.
+///
+/// A `Match` whose offset is at or past `a.len()` refers back to the output produced
+/// so far: `Match(a.len() + i, len)` copies `len` elements starting at `out[i]`, and
+/// the copy may overlap the elements it produces. Such matches are only emitted with
+/// `DeltaOptions::allow_self_reference`.
+///
+/// `T` defaults to `u8`, so `Compression<'a>` is the byte-oriented form.
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "&'a [T]: serde::Deserialize<'de>"))
+)]
+pub enum Compression<'a, T = u8> {
     Match(usize, usize),
-    Raw(&'a [u8]),
+    Raw(#[cfg_attr(feature = "serde", serde(borrow))] &'a [T]),
 }
 
-pub fn delta<'a>(a: &'a [u8], b: &'a [u8], min_match_len: usize) -> Vec<Compression<'a>> {
-    use Compression::*;
+const DISPLAY_PREVIEW_LEN: usize = 8;
 
-    let match_intervals = find_match_intervals(a, b, min_match_len);
-    if match_intervals.is_empty() {
-        return vec![Raw(b)];
+impl core::fmt::Display for Compression<'_> {
+    /// Renders `Match(12, 8)` as `COPY a[12..20] (8 bytes)` and `Raw(b"hello")` as
+    /// `ADD 5 bytes: 68 65 6c 6c 6f`. Only the first few raw bytes are shown.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Compression::Match(la, len) => {
+                write!(f, "COPY a[{}..{}] ({} bytes)", la, la + len, len)
+            }
+            Compression::Raw(data) => {
+                write!(f, "ADD {} bytes", data.len())?;
+                for (i, byte) in data.iter().take(DISPLAY_PREVIEW_LEN).enumerate() {
+                    let sep = if i == 0 { ": " } else { " " };
+                    write!(f, "{sep}{byte:02x}")?;
+                }
+                if data.len() > DISPLAY_PREVIEW_LEN {
+                    write!(f, " ...")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An owned counterpart of `Compression` that does not borrow `b`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CompressionOwned<T = u8> {
+    Match(usize, usize),
+    Raw(Vec<T>),
+}
+
+impl<T> Compression<'_, T> {
+    /// The number of elements this compression adds to the output.
+    pub fn output_len(&self) -> usize {
+        match self {
+            Compression::Match(_, len) => *len,
+            Compression::Raw(data) => data.len(),
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        matches!(self, Compression::Match(..))
+    }
+
+    /// The half-open range `la..la + len` a `Match` copies, or `None` for `Raw` and
+    /// for a `Match` whose end overflows `usize`, which no input can satisfy. For a
+    /// back-reference the range is past the end of `a` (see `Compression`).
+    pub fn source_range(&self) -> Option<core::ops::Range<usize>> {
+        match *self {
+            Compression::Match(la, len) => Some(la..la.checked_add(len)?),
+            Compression::Raw(_) => None,
+        }
+    }
+}
+
+impl<T: Clone> Compression<'_, T> {
+    /// Copies the raw elements, so the result no longer borrows `b`.
+    pub fn to_owned(&self) -> CompressionOwned<T> {
+        self.into()
+    }
+}
+
+impl<T: Clone> From<&Compression<'_, T>> for CompressionOwned<T> {
+    fn from(c: &Compression<'_, T>) -> Self {
+        match c {
+            Compression::Match(la, len) => CompressionOwned::Match(*la, *len),
+            Compression::Raw(data) => CompressionOwned::Raw(data.to_vec()),
+        }
+    }
+}
+
+impl<T: Clone> From<Compression<'_, T>> for CompressionOwned<T> {
+    fn from(c: Compression<'_, T>) -> Self {
+        Self::from(&c)
+    }
+}
+
+impl core::fmt::Display for CompressionOwned {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Compression::from(self).fmt(f)
+    }
+}
+
+impl<'a, T> From<&'a CompressionOwned<T>> for Compression<'a, T> {
+    fn from(c: &'a CompressionOwned<T>) -> Self {
+        match c {
+            CompressionOwned::Match(la, len) => Compression::Match(*la, *len),
+            CompressionOwned::Raw(data) => Compression::Raw(data),
+        }
+    }
+}
+
+/// Why `try_restore` refused a delta.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum RestoreError {
+    /// Compression `index` copies from neither `a` nor the output before it.
+    #[error("compression {index} copies a[{la}..{la}+{len}] but a has only {a_len} bytes")]
+    MatchOutOfBounds {
+        index: usize,
+        la: usize,
+        len: usize,
+        a_len: usize,
+    },
+    /// Compression `index` makes the output longer than `max` elements.
+    #[error("compression {index} restores more than {max} elements")]
+    OutputTooLong { index: usize, max: usize },
+}
+
+/// Summary of a delta, as returned by `stats`. Lengths count elements of `b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeltaStats {
+    /// Length of the output the delta restores to.
+    pub total_output: usize,
+    pub matched_bytes: usize,
+    pub raw_bytes: usize,
+    pub match_count: usize,
+    pub raw_count: usize,
+}
+
+impl DeltaStats {
+    /// The fraction of the output copied from matches, or 0 for an empty output.
+    pub fn matching_ratio(&self) -> f64 {
+        if self.total_output == 0 {
+            return 0.0;
+        }
+        self.matched_bytes as f64 / self.total_output as f64
+    }
+}
+
+/// Counts the matched and raw parts of a delta.
+pub fn stats<T>(compressions: &[Compression<T>]) -> DeltaStats {
+    let mut stats = DeltaStats::default();
+    for c in compressions {
+        stats.add(c);
+    }
+    stats
+}
+
+impl DeltaStats {
+    fn add<T>(&mut self, c: &Compression<T>) {
+        if c.is_match() {
+            self.matched_bytes += c.output_len();
+            self.match_count += 1;
+        } else {
+            self.raw_bytes += c.output_len();
+            self.raw_count += 1;
+        }
+        self.total_output += c.output_len();
+    }
+}
+
+/// A delta together with its `DeltaStats`, counted as it is built.
+///
+/// It dereferences to `[Compression]`, so it can be passed wherever a delta slice
+/// is expected:
+///
+/// ```
+/// use sparse_hash_delta::{delta_iter, restore, Delta};
+///
+/// let (a, b) = (b"phantom", b"elephant");
+/// let d: Delta = delta_iter(a, b, 5).collect();
+/// assert_eq!(d.stats().matched_bytes, 5);
+/// assert_eq!(restore(a, &d).concat(), b);
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct Delta<'a, T = u8> {
+    compressions: Vec<Compression<'a, T>>,
+    stats: DeltaStats,
+}
+
+impl<'a, T> Delta<'a, T> {
+    pub fn stats(&self) -> DeltaStats {
+        self.stats
+    }
+
+    pub fn into_vec(self) -> Vec<Compression<'a, T>> {
+        self.compressions
+    }
+}
+
+impl<'a, T> From<Vec<Compression<'a, T>>> for Delta<'a, T> {
+    fn from(compressions: Vec<Compression<'a, T>>) -> Self {
+        let stats = stats(&compressions);
+        Self {
+            compressions,
+            stats,
+        }
+    }
+}
+
+impl<'a, T> FromIterator<Compression<'a, T>> for Delta<'a, T> {
+    fn from_iter<I: IntoIterator<Item = Compression<'a, T>>>(iter: I) -> Self {
+        let mut stats = DeltaStats::default();
+        let compressions = iter.into_iter().inspect(|c| stats.add(c)).collect();
+        Self {
+            compressions,
+            stats,
+        }
+    }
+}
+
+impl<'a, T> core::ops::Deref for Delta<'a, T> {
+    type Target = [Compression<'a, T>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.compressions
+    }
+}
+
+impl<'a, T> IntoIterator for Delta<'a, T> {
+    type Item = Compression<'a, T>;
+    type IntoIter = alloc::vec::IntoIter<Compression<'a, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.compressions.into_iter()
+    }
+}
+
+impl<'d, 'a, T> IntoIterator for &'d Delta<'a, T> {
+    type Item = &'d Compression<'a, T>;
+    type IntoIter = core::slice::Iter<'d, Compression<'a, T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.compressions.iter()
+    }
+}
+
+/// Which elements of the output were copied by a `Match`, one flag per element of
+/// `b`. Output beyond `b_len` is ignored, and a shorter output leaves the rest
+/// `false`.
+pub fn coverage<T>(b_len: usize, compressions: &[Compression<T>]) -> Vec<bool> {
+    let mut covered = vec![false; b_len];
+    for (start, end) in coverage_ranges(compressions) {
+        if start >= b_len {
+            break;
+        }
+        covered[start..core::cmp::min(end, b_len)].fill(true);
+    }
+    covered
+}
+
+/// The spans of the output copied by matches, as `start..end` pairs in increasing
+/// order. Consecutive matches are joined into one span.
+pub fn coverage_ranges<T>(compressions: &[Compression<T>]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut pos = 0;
+    for c in compressions {
+        match c {
+            Compression::Match(_, 0) => {}
+            Compression::Match(_, len) => {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end == pos => *end += len,
+                    _ => ranges.push((pos, pos + len)),
+                }
+                pos += len;
+            }
+            Compression::Raw(data) => pos += data.len(),
+        }
+    }
+    ranges
+}
+
+/// How well the fingerprints of a source tell its windows apart, as reported by
+/// `collision_report`.
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CollisionReport {
+    /// The windows of `a` entered into the table.
+    pub windows: usize,
+    /// The distinct fingerprints among them.
+    pub buckets: usize,
+    /// Fingerprints shared by windows with different contents.
+    pub colliding_buckets: usize,
+    /// The most windows with different contents sharing one fingerprint.
+    pub largest_bucket: usize,
+}
+
+/// Counts the fingerprint collisions in the table `delta_with(a, _, opts)` builds
+/// from `a`, to tell whether a low matching ratio comes from `hash_params` or
+/// `hash_mode` being too weak for the data.
+///
+/// Windows with the same contents share a fingerprint by design and do not count
+/// as collisions. Colliding windows are still told apart when matching, at the
+/// cost of comparing each of them against every window of `b` with that
+/// fingerprint.
+///
+/// Only built with the `diagnostics` feature.
+#[cfg(feature = "diagnostics")]
+pub fn collision_report<T: Symbol>(a: &[T], opts: &DeltaOptions) -> CollisionReport {
+    let opts = &*opts.resolved(a.len());
+    let table = match opts.hash_mode {
+        HashMode::Single => SeedTable::sampled(a, opts, PolynomialHasher::new(opts.hash_params)),
+        HashMode::Double => {
+            SeedTable::sampled(a, opts, DoubleHasher::with_params(opts.hash_params))
+        }
+    };
+    let mut report = CollisionReport {
+        buckets: table.positions.len(),
+        ..CollisionReport::default()
+    };
+    for positions in table.positions.values() {
+        report.windows += positions.len();
+        let mut distinct: Vec<&[T]> = Vec::new();
+        for &i in positions {
+            let window = &a[i..i + table.window_len];
+            if !distinct.contains(&window) {
+                distinct.push(window);
+            }
+        }
+        if distinct.len() > 1 {
+            report.colliding_buckets += 1;
+        }
+        report.largest_bucket = core::cmp::max(report.largest_bucket, distinct.len());
+    }
+    report
+}
+
+/// The `min_match_len` values tried by `match_density`.
+pub const MATCH_DENSITY_LENGTHS: [usize; 9] = [4, 8, 16, 32, 64, 128, 256, 512, 1024];
+
+/// The matching ratio of `b` against `a` for each of `MATCH_DENSITY_LENGTHS`, as
+/// `(min_match_len, ratio)` pairs, to help pick `min_match_len` where the ratio
+/// starts to fall off.
+///
+/// `b` is scanned once with the shortest length, and the ratio for each length
+/// counts only the matches at least that long. Beyond the shortest length this only
+/// approximates `stats(&delta(a, b, n)).matching_ratio()`: the curve has the same
+/// shape, but as the scan with short seeds cuts matches at other places, single
+/// values can be lower by several percent or more.
+/// It is meant as a diagnostic, and hashing with the shortest windows costs more
+/// than a single `delta` call.
+pub fn match_density<T: Symbol>(a: &[T], b: &[T]) -> Vec<(usize, f64)> {
+    let opts = DeltaOptions::default().min_match_len(MATCH_DENSITY_LENGTHS[0]);
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let match_intervals = find_match_intervals_with(a, b, &opts, hasher);
+    MATCH_DENSITY_LENGTHS
+        .iter()
+        .map(|&n| {
+            let matched: usize = match_intervals
+                .iter()
+                .filter(|m| m.len >= n)
+                .map(|m| m.len)
+                .sum();
+            let ratio = if b.is_empty() {
+                0.0
+            } else {
+                matched as f64 / b.len() as f64
+            };
+            (n, ratio)
+        })
+        .collect()
+}
+
+/// Options for `delta_with`, built up from `DeltaOptions::default()`.
+///
+/// ```
+/// use sparse_hash_delta::DeltaOptions;
+///
+/// let opts = DeltaOptions::default().min_match_len(16).step_factor(2);
+/// assert_eq!(opts.min_match_len, 16);
+/// ```
+// `normalize` is compared by address, which is good enough to tell options apart.
+#[allow(unpredictable_function_pointer_comparisons)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaOptions {
+    /// Matches of at least this length are guaranteed to be found, and shorter ones
+    /// are left as raw data. 0 is treated as 1. If `b` is shorter than this, it is not
+    /// searched and becomes a single raw run. On highly repetitive inputs, where a seed
+    /// has many long candidates, only as many are extended as a fixed amount of work
+    /// allows, and a longer match may be missed.
+    pub min_match_len: usize,
+    /// Windows of `a` are sampled every `hash_len * step_factor` bytes. Values above 1
+    /// shrink the hash table but drop the guarantee given by `min_match_len`.
+    pub step_factor: usize,
+    /// Sample every `sample_step`-th window of `a` instead, regardless of
+    /// `min_match_len` and `step_factor`. The table of `a` holds about
+    /// `a.len() / sample_step` entries, and every match of at least
+    /// `sample_step + (min_match_len + 1) / 2 - 1` elements is found, so small
+    /// steps trade memory for sensitivity to short matches. 0 is treated as 1.
+    pub sample_step: Option<usize>,
+    /// Sample this many evenly spaced windows of `a` per sampling step instead of
+    /// one, so that `2` also seeds halfway between the usual windows. Matches not
+    /// aligned to the step are then found from about `step / seed_phases` elements
+    /// shorter, at the cost of a table this many times larger. 0 is treated as 1, and
+    /// values above the step as the step.
+    pub seed_phases: usize,
+    pub hash_params: HashParams,
+    pub hash_mode: HashMode,
+    pub strategy: MatchStrategy,
+    /// Longer matches are split into consecutive `Match`es of at most this length.
+    /// `0` and `usize::MAX` mean unbounded.
+    pub max_match_len: usize,
+    /// Also match against the part of `b` already scanned, LZ77-style. Such matches
+    /// are addressed after the end of `a` (see `Compression`).
+    pub allow_self_reference: bool,
+    /// Matches that cost at least as many bytes as they copy are replaced by raw data.
+    pub match_cost: MatchCost,
+    /// Make the delta safe to apply in place, over a buffer holding `a`. No match
+    /// reads a part of the buffer that earlier output, or the match itself, writes:
+    /// matches are cut into pieces that read far enough ahead of the output, and
+    /// those reading at or behind it are replaced by raw data.
+    pub disjoint_sources: bool,
+    /// Stop scanning `b` once this many matches are found, and emit the rest of it
+    /// as raw data. Bounds the work per delta at the cost of a larger delta.
+    pub max_matches: Option<usize>,
+    /// Trim matches so that their offsets in `a` and `b` and their lengths are all
+    /// multiples of this block size, leaving the unaligned edges as raw data.
+    /// Back-references are aligned by their offset in `b`. Options that cut matches
+    /// further, such as `max_match_len`, keep the alignment only if they cut at
+    /// multiples of the block size. `Some(0)` is treated as `None`.
+    pub block_align: Option<usize>,
+    /// Map every byte through this function before comparing, so that for example
+    /// `|x| x.to_ascii_lowercase()` matches text regardless of case. Matches found this
+    /// way are cut down to the runs where `a` and `b` are actually equal, however
+    /// short, and the rest is raw data from `b`. Only `delta_with` applies it, on
+    /// normalized copies of `a` and `b`, and only to `u8` elements.
+    pub normalize: Option<fn(u8) -> u8>,
+    /// Cut `b` into windows of this many elements and diff each against `a` on its
+    /// own, so no match crosses a window boundary and back-references stay within
+    /// their window. The other options apply to each window, with `block_align` and
+    /// `disjoint_sources` taking the window's offset in `b` into account.
+    /// `delta_bytes` then frames every window with its output offset and a checksum,
+    /// so that `apply_windows` can skip a corrupted window and resume at the next.
+    /// `Some(0)` is treated as 1.
+    pub resync_window: Option<usize>,
+    /// Cut `b` into this many partitions of equal length that restore independently
+    /// of each other, and copy from disjoint parts of `a`, so that `restore_parallel`
+    /// can restore them concurrently (see `delta_partitions`). `delta_with` returns
+    /// the partitions one after another, with `block_align` and `disjoint_sources`
+    /// holding for their offsets in `b`. Takes the place of `resync_window`. `Some(0)`
+    /// is treated as 1.
+    pub partition_for_parallel: Option<usize>,
+    /// Only match `b` against the last this many elements of `a` up to the same
+    /// position, as in an LZ window: a match copying `a[la..]` to `b[lb..]` needs
+    /// `la <= lb < la + source_window`. The table of `a` then holds only the windows
+    /// the scan can still reach, added as it reaches them and evicted once it is
+    /// past them. With `resync_window` or `partition_for_parallel`, positions in `b`
+    /// count from the start of each window or partition. `Some(0)` is treated as 1.
+    pub source_window: Option<usize>,
+    /// Only match `a[la..]` to `b[lb..]` when `la` and `lb` are at most this far
+    /// apart, so that formats encoding the offset as a displacement from the output
+    /// position keep it small. A longer match further away then gives way to shorter
+    /// ones nearby, or to raw data. Back-references are not bounded. With
+    /// `resync_window` or `partition_for_parallel`, positions in `b` count from the
+    /// start of each window or partition.
+    pub max_displacement: Option<usize>,
+    /// Pick `min_match_len` from the length of `b` instead. Where there is no `b`, as
+    /// in `SourceIndex::build` and `collision_report`, or it is not known up front, as
+    /// in `delta_streams`, the length of `a` is used, and `delta_chunked` ignores it.
+    pub hash_len_policy: Option<HashLenPolicy>,
+}
+
+/// How `DeltaOptions::hash_len_policy` picks `min_match_len`, and with it the
+/// length of the hashed windows, for an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashLenPolicy {
+    /// The same length for every input.
+    Fixed(usize),
+    /// `len / divisor`, but at least `floor`, so that the table of a large input
+    /// stays small. `divisor` 0 is treated as 1.
+    Adaptive { floor: usize, divisor: usize },
+}
+
+impl HashLenPolicy {
+    /// The `min_match_len` for an input of `len` elements.
+    pub fn min_match_len(self, len: usize) -> usize {
+        match self {
+            HashLenPolicy::Fixed(min_match_len) => min_match_len,
+            HashLenPolicy::Adaptive { floor, divisor } => {
+                core::cmp::max(floor, len / core::cmp::max(1, divisor))
+            }
+        }
+    }
+}
+
+/// The cost of a `Match` in bytes, weighed against the raw data it replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchCost {
+    /// Every match is kept.
+    #[default]
+    Free,
+    /// The size of the match in the `encode_delta` format.
+    Encoded,
+    /// The same cost for every match.
+    Fixed(usize),
+}
+
+impl MatchCost {
+    fn of(self, la: usize, len: usize) -> usize {
+        match self {
+            MatchCost::Free => 0,
+            MatchCost::Encoded => encode::encoded_match_size(la, len),
+            MatchCost::Fixed(cost) => cost,
+        }
+    }
+}
+
+/// How windows are fingerprinted for `delta_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashMode {
+    /// One `PolynomialHasher` with `DeltaOptions::hash_params`.
+    #[default]
+    Single,
+    /// A `DoubleHasher` pairing `DeltaOptions::hash_params` with a second, independent
+    /// hash. Fewer windows share a fingerprint, so fewer candidates have to be
+    /// compared element by element, at the price of hashing every window twice.
+    Double,
+}
+
+/// How matches found while scanning `b` are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchStrategy {
+    /// Take the first match found and resume scanning after it.
+    #[default]
+    Greedy,
+    /// Collect a candidate at every position of `b` and keep the longest ones,
+    /// trimming the shorter ones where they overlap. Slower than `Greedy`.
+    Longest,
+    /// Collect a candidate at every position of `b` and pick the ones that need the
+    /// fewest compressions, for decoders that are bound by their number rather than
+    /// by the bytes copied. A compression is weighed as `min_match_len` raw
+    /// elements, so longer matches win over several shorter ones that copy only a
+    /// little more. Never yields more compressions than `Greedy`, whose selection is
+    /// kept should it need fewer once both are shaped by `match_cost`,
+    /// `max_match_len` and the like, and cut to exact runs under `normalize`. Slower
+    /// than `Longest`.
+    MinimalOps,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self {
+            min_match_len: 10,
+            step_factor: 1,
+            sample_step: None,
+            seed_phases: 1,
+            hash_params: HashParams::default(),
+            hash_mode: HashMode::default(),
+            strategy: MatchStrategy::default(),
+            max_match_len: usize::MAX,
+            allow_self_reference: false,
+            match_cost: MatchCost::default(),
+            disjoint_sources: false,
+            max_matches: None,
+            block_align: None,
+            normalize: None,
+            resync_window: None,
+            partition_for_parallel: None,
+            source_window: None,
+            max_displacement: None,
+            hash_len_policy: None,
+        }
+    }
+}
+
+impl DeltaOptions {
+    pub fn min_match_len(mut self, min_match_len: usize) -> Self {
+        self.min_match_len = min_match_len;
+        self
+    }
+
+    pub fn step_factor(mut self, step_factor: usize) -> Self {
+        self.step_factor = step_factor;
+        self
+    }
+
+    pub fn sample_step(mut self, sample_step: usize) -> Self {
+        self.sample_step = Some(sample_step);
+        self
+    }
+
+    pub fn seed_phases(mut self, seed_phases: usize) -> Self {
+        self.seed_phases = seed_phases;
+        self
+    }
+
+    pub fn hash_params(mut self, hash_params: HashParams) -> Self {
+        self.hash_params = hash_params;
+        self
+    }
+
+    pub fn hash_mode(mut self, hash_mode: HashMode) -> Self {
+        self.hash_mode = hash_mode;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: MatchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn max_match_len(mut self, max_match_len: usize) -> Self {
+        self.max_match_len = max_match_len;
+        self
+    }
+
+    pub fn allow_self_reference(mut self, allow_self_reference: bool) -> Self {
+        self.allow_self_reference = allow_self_reference;
+        self
+    }
+
+    pub fn match_cost(mut self, match_cost: MatchCost) -> Self {
+        self.match_cost = match_cost;
+        self
+    }
+
+    pub fn disjoint_sources(mut self, disjoint_sources: bool) -> Self {
+        self.disjoint_sources = disjoint_sources;
+        self
+    }
+
+    pub fn max_matches(mut self, max_matches: Option<usize>) -> Self {
+        self.max_matches = max_matches;
+        self
+    }
+
+    pub fn block_align(mut self, block_align: Option<usize>) -> Self {
+        self.block_align = block_align;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: Option<fn(u8) -> u8>) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn resync_window(mut self, resync_window: Option<usize>) -> Self {
+        self.resync_window = resync_window;
+        self
+    }
+
+    pub fn partition_for_parallel(mut self, partition_for_parallel: Option<usize>) -> Self {
+        self.partition_for_parallel = partition_for_parallel;
+        self
+    }
+
+    pub fn source_window(mut self, source_window: Option<usize>) -> Self {
+        self.source_window = source_window;
+        self
+    }
+
+    pub fn max_displacement(mut self, max_displacement: Option<usize>) -> Self {
+        self.max_displacement = max_displacement;
+        self
+    }
+
+    pub fn hash_len_policy(mut self, hash_len_policy: Option<HashLenPolicy>) -> Self {
+        self.hash_len_policy = hash_len_policy;
+        self
+    }
+
+    // These options with `min_match_len` picked by `hash_len_policy` for an input of
+    // `len` elements, if there is a policy.
+    pub(crate) fn resolved(&self, len: usize) -> Cow<'_, DeltaOptions> {
+        match self.hash_len_policy {
+            Some(policy) => Cow::Owned(
+                self.clone()
+                    .hash_len_policy(None)
+                    .min_match_len(policy.min_match_len(len)),
+            ),
+            None => Cow::Borrowed(self),
+        }
+    }
+
+    // At least 1, so that windows are never empty and `step` never 0.
+    fn hash_len(&self) -> usize {
+        core::cmp::max(1, self.min_match_len).div_ceil(2)
+    }
+
+    fn step(&self) -> usize {
+        match self.sample_step {
+            Some(step) => core::cmp::max(1, step),
+            None => self.hash_len() * core::cmp::max(1, self.step_factor),
+        }
+    }
+
+    // Whether the window of `a` at `i` is sampled: the first of each step, and
+    // `seed_phases - 1` more spread evenly over it.
+    fn is_seed(&self, i: usize) -> bool {
+        let step = self.step();
+        let phases = self.seed_phases.clamp(1, step);
+        // The phases are at `j * step / phases`, which for `phases <= step` are
+        // the offsets whose `offset * phases / step` rounds up to a distinct `j`.
+        let offset = i % step;
+        let j = (offset * phases).div_ceil(step);
+        j < phases && j * step / phases == offset
+    }
+
+    // Whether `min_match_len` exceeds `b`, so that `b` cannot hold a match that long.
+    fn longer_than<T>(&self, b: &[T]) -> bool {
+        b.len() < self.min_match_len
+    }
+}
+
+/// The fraction of `b` that `delta(a, b, min_match_len)` copies from `a`, between
+/// 0.0 and 1.0, computed without building the delta.
+///
+/// This is an estimate of how similar the inputs are, not a symmetric measure: it
+/// only counts matches at least about `min_match_len` long, so the same inputs
+/// score higher with a shorter `min_match_len`. An empty `b` scores 0.0.
+pub fn similarity<T: Symbol>(a: &[T], b: &[T], min_match_len: usize) -> f64 {
+    if b.is_empty() {
+        return 0.0;
+    }
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let matched: usize = find_match_intervals_with(a, b, &opts, hasher)
+        .iter()
+        .filter(|m| m.len >= min_match_len)
+        .map(|m| m.len)
+        .sum();
+    matched as f64 / b.len() as f64
+}
+
+pub fn delta<'a, T: Symbol>(a: &[T], b: &'a [T], min_match_len: usize) -> Vec<Compression<'a, T>> {
+    // The scan would find one match over all of `b`, but only after hashing `a`.
+    if a == b && !a.is_empty() && b.len() >= min_match_len {
+        return vec![Compression::Match(0, b.len())];
+    }
+    delta_iter(a, b, min_match_len).collect()
+}
+
+/// Same as `delta`, but yields the compressions lazily while scanning `b`
+/// instead of collecting every match first.
+///
+/// Only the index of `a` is held in memory, so the delta can be encoded as it
+/// is produced.
+pub fn delta_iter<'s, 'a: 's, T: Symbol>(
+    a: &'s [T],
+    b: &'a [T],
+    min_match_len: usize,
+) -> impl Iterator<Item = Compression<'a, T>> + 's {
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let hash_len = opts.hash_len();
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    // With nothing to match against, or no room in `b` for a match, skip hashing and
+    // emit `b` as one raw run.
+    let skip = a.is_empty() || opts.longer_than(b);
+    DeltaIter {
+        b,
+        source: SeedTable::sampled(if skip { &a[..0] } else { a }, &opts, hasher.clone()),
+        hashes: RollingHash::with_hasher(if skip { &b[..0] } else { b }, hash_len, hasher),
+        window_len: core::cmp::min(b.len(), hash_len),
+        state: 0,
+        acc: MatchInterval::empty(),
+        prev: 0,
+        min_match_len,
+        pending: None,
+        matched: false,
+        done: false,
+    }
+}
+
+// The greedy scan of `find_match_intervals_with` fused with overlap removal and
+// `compressions_from_intervals`, one match at a time.
+struct DeltaIter<'s, 'a, T> {
+    b: &'a [T],
+    source: SeedTable<'s, T>,
+    hashes: RollingHash<'a, T, PolynomialHasher>,
+    window_len: usize,
+    // Windows of `b` before `state` are covered by the last match found.
+    state: usize,
+    // The last match emitted, and where it ends in `b`.
+    acc: MatchInterval,
+    prev: usize,
+    min_match_len: usize,
+    // A match waiting behind the raw run that precedes it.
+    pending: Option<Compression<'a, T>>,
+    matched: bool,
+    done: bool,
+}
+
+impl<'a, T: Symbol> Iterator for DeltaIter<'_, 'a, T> {
+    type Item = Compression<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(c) = self.pending.take() {
+            return Some(c);
+        }
+        for (hb, ib) in self.hashes.by_ref() {
+            if ib < self.state {
+                continue;
+            }
+            let window = &self.b[ib..ib + self.window_len];
+            let mut m = self
+                .source
+                .best_match(self.b, window, hb, ib, &MatchInterval::empty());
+            if m.len == 0 {
+                continue;
+            }
+            self.state = m.br();
+            m.remove_overlap(&self.acc);
+            if m.len == 0 {
+                continue;
+            }
+            m.extend_left(self.source.data, self.b, self.acc.br());
+            self.acc = m;
+            if m.len < self.min_match_len {
+                continue;
+            }
+            self.matched = true;
+            let raw = (self.prev < m.lb).then(|| Compression::Raw(&self.b[self.prev..m.lb]));
+            self.prev = m.br();
+            let matched = Compression::Match(m.la, m.len);
+            return match raw {
+                Some(raw) => {
+                    self.pending = Some(matched);
+                    Some(raw)
+                }
+                None => Some(matched),
+            };
+        }
+
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        if !self.matched {
+            return Some(Compression::Raw(self.b));
+        }
+        (self.prev != self.b.len()).then(|| Compression::Raw(&self.b[self.prev..]))
+    }
+}
+
+pub fn delta_with<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    opts: &DeltaOptions,
+) -> Vec<Compression<'a, T>> {
+    delta_in(a, b, 0, &opts.resolved(b.len()), None)
+}
+
+// `delta_with` for the part of the output at `start`, with the fingerprints of `a`
+// taken from `index` if there is one. Back-references address the part's own output.
+fn delta_in<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    start: usize,
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+) -> Vec<Compression<'a, T>> {
+    if opts.partition_for_parallel.is_some() {
+        return partition::partition_intervals(a, b, start, opts, index)
+            .into_iter()
+            .flat_map(|(output_offset, part, match_intervals)| {
+                let compressions = compressions_from_intervals(part, match_intervals);
+                rebased(a.len(), output_offset, compressions)
+            })
+            .collect();
+    }
+    if opts.resync_window.is_some() {
+        return delta_windows(a, b, opts, index)
+            .into_iter()
+            .flat_map(|(_, compressions)| compressions)
+            .collect();
+    }
+    compressions_from_intervals(b, delta_intervals(a, b, start, opts, index))
+}
+
+/// Same as `stats(&delta_with(a, b, opts))`, counted while scanning `b` without
+/// building the delta, to see how large it would be before asking for it.
+///
+/// Only the matches found are held in memory, not the compressions between them.
+pub fn delta_summary<T: Symbol>(a: &[T], b: &[T], opts: &DeltaOptions) -> DeltaStats {
+    let opts = &*opts.resolved(b.len());
+    let mut stats = DeltaStats::default();
+    if opts.partition_for_parallel.is_some() {
+        for (_, part, match_intervals) in partition::partition_intervals(a, b, 0, opts, None) {
+            for_each_compression(part, match_intervals, |c| stats.add(&c));
+        }
+        return stats;
+    }
+    match opts.resync_window {
+        Some(window) => {
+            let window = core::cmp::max(1, window);
+            let opts = opts.clone().resync_window(None);
+            for (i, chunk) in b.chunks(window).enumerate() {
+                let match_intervals = delta_intervals(a, chunk, i * window, &opts, None);
+                for_each_compression(chunk, match_intervals, |c| stats.add(&c));
+            }
+        }
+        None => for_each_compression(b, delta_intervals(a, b, 0, opts, None), |c| stats.add(&c)),
+    }
+    stats
+}
+
+// The shaped intervals `delta_with` fills the gaps between, for one window of `b`
+// that the output holds at `start`.
+fn delta_intervals<T: Symbol>(
+    a: &[T],
+    b: &[T],
+    start: usize,
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+) -> Vec<MatchInterval> {
+    if opts.strategy != MatchStrategy::MinimalOps {
+        return strategy_intervals(a, b, start, opts, index);
+    }
+    // Shaping, and cutting matches to exact runs under `normalize`, can leave the
+    // selection with the fewest compressions with more of them than greedy's, so
+    // the two are compared once finished.
+    let fewest = strategy_intervals(a, b, start, opts, index);
+    let greedy_opts = opts.clone().strategy(MatchStrategy::Greedy);
+    let greedy = strategy_intervals(a, b, start, &greedy_opts, index);
+    if op_count(&fewest, b.len()) <= op_count(&greedy, b.len()) {
+        fewest
+    } else {
+        greedy
+    }
+}
+
+// `delta_intervals` with the selection of `opts.strategy` alone.
+fn strategy_intervals<T: Symbol>(
+    a: &[T],
+    b: &[T],
+    start: usize,
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+) -> Vec<MatchInterval> {
+    if let Some(normalize) = opts.normalize {
+        return normalized_intervals(a, b, start, opts, normalize);
+    }
+    if a == b && !a.is_empty() && !opts.longer_than(b) {
+        let whole = MatchInterval {
+            la: 0,
+            lb: 0,
+            len: b.len(),
+        };
+        return finish_intervals(a.len(), start, vec![whole], opts);
+    }
+    let match_intervals = match opts.hash_mode {
+        HashMode::Single => {
+            let hasher = PolynomialHasher::new(opts.hash_params);
+            find_match_intervals_indexed(a, b, opts, index, hasher)
+        }
+        HashMode::Double => {
+            let hasher = DoubleHasher::with_params(opts.hash_params);
+            find_match_intervals_indexed(a, b, opts, index, hasher)
+        }
+    };
+    finish_intervals(a.len(), start, match_intervals, opts)
+}
+
+// `delta_with` on each `DeltaOptions::resync_window` of `b` on its own, paired with
+// the offset of the window in `b`. Back-references are rebased to address the whole
+// output.
+fn delta_windows<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+) -> Vec<(usize, Vec<Compression<'a, T>>)> {
+    let window = core::cmp::max(1, opts.resync_window.unwrap_or(usize::MAX));
+    let opts = opts.clone().resync_window(None);
+    b.chunks(window)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let start = i * window;
+            let compressions = delta_in(a, chunk, start, &opts, index);
+            let compressions = rebased(a.len(), start, compressions).collect();
+            (start, compressions)
+        })
+        .collect()
+}
+
+// The compressions of a delta of the part of `b` at `start`, with back-references
+// addressing the whole output.
+fn rebased<'a, T>(
+    a_len: usize,
+    start: usize,
+    compressions: Vec<Compression<'a, T>>,
+) -> impl Iterator<Item = Compression<'a, T>> {
+    compressions.into_iter().map(move |c| match c {
+        Compression::Match(la, len) if la >= a_len => Compression::Match(la + start, len),
+        c => c,
+    })
+}
+
+// The intervals of `delta_with` on normalized copies of `a` and `b`, with each match
+// then cut into the runs where the originals are equal.
+fn normalized_intervals<T: Symbol>(
+    a: &[T],
+    b: &[T],
+    start: usize,
+    opts: &DeltaOptions,
+    normalize: fn(u8) -> u8,
+) -> Vec<MatchInterval> {
+    let normalized_a: Vec<T> = a.iter().map(|x| x.normalize(normalize)).collect();
+    let normalized_b: Vec<T> = b.iter().map(|x| x.normalize(normalize)).collect();
+    let opts = opts.clone().normalize(None);
+    let mut match_intervals = match opts.hash_mode {
+        HashMode::Single => find_match_intervals_with(
+            &normalized_a,
+            &normalized_b,
+            &opts,
+            PolynomialHasher::new(opts.hash_params),
+        ),
+        HashMode::Double => find_match_intervals_with(
+            &normalized_a,
+            &normalized_b,
+            &opts,
+            DoubleHasher::with_params(opts.hash_params),
+        ),
+    };
+    match_intervals.retain(|m| m.len >= opts.min_match_len);
+    let mut exact = Vec::with_capacity(match_intervals.len());
+    for m in match_intervals {
+        // Back-references copy from the output, which is `b` itself.
+        let source = |i: usize| {
+            if m.la < a.len() {
+                a[m.la + i]
+            } else {
+                b[m.la - a.len() + i]
+            }
+        };
+        let mut i = 0;
+        while i < m.len {
+            let start = i;
+            while i < m.len && source(i) == b[m.lb + i] {
+                i += 1;
+            }
+            if start < i {
+                exact.push(m.slice(m.lb + start, m.lb + i));
+            }
+            while i < m.len && source(i) != b[m.lb + i] {
+                i += 1;
+            }
+        }
+    }
+    // The exact runs are kept however short they are.
+    finish_intervals(a.len(), start, exact, &opts.min_match_len(1))
+}
+
+/// The fingerprints of a source `a`, built once and reused by `delta_indexed` to
+/// diff many targets against the same `a` without hashing it again.
+///
+/// ```
+/// use sparse_hash_delta::{delta_indexed, DeltaOptions, SourceIndex};
+///
+/// let a = b"the quick brown fox jumps over the lazy dog";
+/// let index = SourceIndex::build(a, &DeltaOptions::default().min_match_len(8));
+/// for b in [&b"the quick red fox"[..], b"jumps over the lazy cat"] {
+///     let d = delta_indexed(&index, a, b);
+///     assert_eq!(sparse_hash_delta::restore(a, &d).concat(), b);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SourceIndex {
+    opts: DeltaOptions,
+    a_len: usize,
+    positions: FingerprintMap<Vec<usize>>,
+}
+
+impl SourceIndex {
+    /// Hashes the windows of `a` that `delta_with(a, _, opts)` would.
+    pub fn build<T: Symbol>(a: &[T], opts: &DeltaOptions) -> Self {
+        let opts = &*opts.resolved(a.len());
+        let table = match opts.hash_mode {
+            HashMode::Single => {
+                SeedTable::sampled(a, opts, PolynomialHasher::new(opts.hash_params))
+            }
+            HashMode::Double => {
+                SeedTable::sampled(a, opts, DoubleHasher::with_params(opts.hash_params))
+            }
+        };
+        Self {
+            opts: opts.clone(),
+            a_len: a.len(),
+            positions: table.positions.into_owned(),
+        }
+    }
+
+    /// The options the index was built with, which `delta_indexed` uses.
+    pub fn options(&self) -> &DeltaOptions {
+        &self.opts
+    }
+}
+
+/// Same as `delta_with(a, b, index.options())`, with the fingerprints of `a` taken
+/// from `index` instead of computed again.
+///
+/// # Panics
+///
+/// If `a` is not as long as the source `index` was built from. A different source
+/// of the same length is not detected and only finds fewer matches.
+pub fn delta_indexed<'a, T: Symbol>(
+    index: &SourceIndex,
+    a: &[T],
+    b: &'a [T],
+) -> Vec<Compression<'a, T>> {
+    assert_eq!(
+        a.len(),
+        index.a_len,
+        "`a` is not the source the index was built from"
+    );
+    delta_in(a, b, 0, &index.opts, Some(index))
+}
+
+// Shared tail of every delta entry point: applies the output-shaping options to the
+// selected intervals and fills the gaps with raw runs.
+fn finish_delta<'a, T>(
+    a: &[T],
+    b: &'a [T],
+    match_intervals: Vec<MatchInterval>,
+    opts: &DeltaOptions,
+) -> Vec<Compression<'a, T>> {
+    compressions_from_intervals(b, finish_intervals(a.len(), 0, match_intervals, opts))
+}
+
+// The intervals of a window of `b` that the output holds at `start`, merged and
+// shaped.
+fn finish_intervals(
+    a_len: usize,
+    start: usize,
+    match_intervals: Vec<MatchInterval>,
+    opts: &DeltaOptions,
+) -> Vec<MatchInterval> {
+    let match_intervals = merge_adjacent_intervals(match_intervals, a_len);
+    shape_intervals(match_intervals, a_len, start, opts)
+}
+
+// Drops matches shorter than `min_match_len`, then cuts and drops matches as
+// `block_align`, `max_match_len`, `disjoint_sources`, `match_cost` and
+// `max_matches` ask. The intervals are those of a window of `b` that the output
+// holds at `start`, which is where blocks are aligned to and where the matches
+// write when applied in place.
+fn shape_intervals(
+    mut match_intervals: Vec<MatchInterval>,
+    a_len: usize,
+    start: usize,
+    opts: &DeltaOptions,
+) -> Vec<MatchInterval> {
+    // Seeds are only half as long, so matches extended from them can fall short.
+    match_intervals.retain(|m| m.len >= opts.min_match_len);
+    if let Some(block) = opts.block_align.filter(|&block| block > 1) {
+        match_intervals = block_aligned_intervals(match_intervals, a_len, start, block);
+    }
+    let mut match_intervals = split_long_intervals(match_intervals, opts.max_match_len);
+    if opts.disjoint_sources {
+        match_intervals = disjoint_source_intervals(match_intervals, a_len, start);
+    }
+    // Dropped matches leave a gap that is filled by one raw run with its neighbours.
+    match_intervals.retain(|m| opts.match_cost.of(m.la, m.len) < m.len);
+    // Splitting may have made more matches than the scan stopped at.
+    if let Some(max_matches) = opts.max_matches {
+        match_intervals.truncate(max_matches);
+    }
+    match_intervals
+}
+
+// The whole blocks of each match, if its offsets in the source and in the output
+// are equally far from a block boundary; otherwise nothing of it. The output holds
+// the window of `b` the intervals are in at `start`.
+fn block_aligned_intervals(
+    match_intervals: Vec<MatchInterval>,
+    a_len: usize,
+    start: usize,
+    block: usize,
+) -> Vec<MatchInterval> {
+    match_intervals
+        .into_iter()
+        .filter_map(|m| {
+            let source = if m.la < a_len {
+                m.la
+            } else {
+                start + m.la - a_len
+            };
+            if source % block != (start + m.lb) % block {
+                return None;
+            }
+            let from = (start + m.lb).next_multiple_of(block);
+            let to = (start + m.br()) / block * block;
+            (from < to).then(|| m.slice(from - start, to - start))
+        })
+        .collect()
+}
+
+// Applied in place, a match writes `start + lb..` over the buffer after the output
+// written so far, where `start` is the offset of the window of `b` the intervals
+// are in. A piece of it may only read `a` at or after the end of its own write, or
+// output before the start of it, so matches are cut into pieces no longer than the
+// distance between the two. Matches reading `a` at or behind the write are dropped.
+fn disjoint_source_intervals(
+    match_intervals: Vec<MatchInterval>,
+    a_len: usize,
+    start: usize,
+) -> Vec<MatchInterval> {
+    let mut results = Vec::with_capacity(match_intervals.len());
+    for m in match_intervals {
+        let piece_len = if m.la < a_len {
+            m.la.saturating_sub(start + m.lb)
+        } else {
+            m.lb - (m.la - a_len)
+        };
+        if piece_len == 0 {
+            continue;
+        }
+        let mut from = m.lb;
+        while from < m.br() {
+            let to = core::cmp::min(from + piece_len, m.br());
+            results.push(m.slice(from, to));
+            from = to;
+        }
+    }
+    results
+}
+
+// Joins matches that continue each other in both `a` and `b`. A match into `a` is
+// never joined with a back-reference, since the result would straddle the end of `a`.
+fn merge_adjacent_intervals(
+    match_intervals: Vec<MatchInterval>,
+    a_len: usize,
+) -> Vec<MatchInterval> {
+    let mut merged: Vec<MatchInterval> = Vec::with_capacity(match_intervals.len());
+    for m in match_intervals {
+        if let Some(last) = merged.last_mut() {
+            if last.br() == m.lb
+                && last.la + last.len == m.la
+                && (last.la < a_len) == (m.la < a_len)
+            {
+                last.len += m.len;
+                continue;
+            }
+        }
+        merged.push(m);
+    }
+    merged
+}
+
+fn split_long_intervals(match_intervals: Vec<MatchInterval>, max_len: usize) -> Vec<MatchInterval> {
+    if max_len == 0 || max_len == usize::MAX {
+        return match_intervals;
+    }
+    let mut results = Vec::with_capacity(match_intervals.len());
+    for m in match_intervals {
+        let mut from = m.lb;
+        while from < m.br() {
+            let to = core::cmp::min(from + max_len, m.br());
+            results.push(m.slice(from, to));
+            from = to;
+        }
+    }
+    results
+}
+
+fn compressions_from_intervals<T>(
+    b: &[T],
+    match_intervals: Vec<MatchInterval>,
+) -> Vec<Compression<'_, T>> {
+    let mut results = Vec::with_capacity(match_intervals.len());
+    for_each_compression(b, match_intervals, |c| results.push(c));
+    results
+}
+
+// Passes the compressions `compressions_from_intervals` collects to `f` in order.
+fn for_each_compression<'a, T>(
+    b: &'a [T],
+    match_intervals: Vec<MatchInterval>,
+    mut f: impl FnMut(Compression<'a, T>),
+) {
+    use Compression::*;
+
+    if match_intervals.is_empty() {
+        return f(Raw(b));
+    }
+
+    let mut prev = 0;
+    for m in match_intervals {
+        // Intervals are selected in order of `lb` without overlap. Should one start
+        // before the end of the last, only its part after it is kept, so that no
+        // part of `b` is written twice.
+        if m.br() <= prev {
+            continue;
+        }
+        let MatchInterval { la, lb, len } = if m.lb < prev {
+            m.slice(prev, m.br())
+        } else {
+            m
+        };
+        if prev < lb {
+            f(Raw(&b[prev..lb]));
+        }
+        f(Match(la, len));
+        prev = lb + len;
+    }
+    if prev != b.len() {
+        f(Raw(&b[prev..]));
+    }
+}
+
+/// A region of `b` found in `a`: `b[target_offset..][..len] == a[source_offset..][..len]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Match {
+    pub source_offset: usize,
+    pub target_offset: usize,
+    pub len: usize,
+}
+
+impl From<MatchInterval> for Match {
+    fn from(m: MatchInterval) -> Self {
+        Self {
+            source_offset: m.la,
+            target_offset: m.lb,
+            len: m.len,
+        }
+    }
+}
+
+/// The matches `delta` would copy, before the gaps between them are filled with raw
+/// runs. They are sorted by `target_offset` and do not overlap in `b`.
+pub fn matches<T: Symbol>(a: &[T], b: &[T], min_match_len: usize) -> Vec<Match> {
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    find_match_intervals_with(a, b, &opts, hasher)
+        .into_iter()
+        .filter(|m| m.len >= min_match_len)
+        .map(Match::from)
+        .collect()
+}
+
+/// The longest region shared by `a` and `b`, if it is at least `min_match_len` long.
+/// Of several equally long ones, the one found first in `b` is returned.
+///
+/// Every window of `b` is looked up, as for `MatchStrategy::Longest`, so the region
+/// is found whenever it is at least `min_match_len` long.
+pub fn longest_common_substring<T: Symbol>(
+    a: &[T],
+    b: &[T],
+    min_match_len: usize,
+) -> Option<Match> {
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    if a.is_empty() || opts.longer_than(b) {
+        return None;
+    }
+    let hash_len = opts.hash_len();
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let source = SeedTable::sampled(a, &opts, hasher.clone());
+    let window_len = core::cmp::min(b.len(), hash_len);
+    let mut best = MatchInterval::empty();
+    let mut prev = MatchInterval::empty();
+    for (hb, ib) in RollingHash::with_hasher(b, hash_len, hasher) {
+        let m = source.best_match(b, &b[ib..ib + window_len], hb, ib, &prev);
+        if m.len > 0 {
+            prev = m;
+        }
+        if m.len > best.len {
+            best = m;
+        }
+    }
+    (best.len > 0 && best.len >= opts.min_match_len).then(|| Match::from(best))
+}
+
+/// Same as `delta`, but seeds matches with the 64-bit `Polynomial64Hasher`.
+pub fn delta64<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    min_match_len: usize,
+) -> Vec<Compression<'a, T>> {
+    delta_with_hasher(a, b, min_match_len, Polynomial64Hasher::default())
+}
+
+/// Same as `delta`, but fingerprints windows with a user-supplied `WindowHasher`.
+pub fn delta_with_hasher<'a, T: Symbol, H: WindowHasher<T> + Clone>(
+    a: &[T],
+    b: &'a [T],
+    min_match_len: usize,
+    hasher: H,
+) -> Vec<Compression<'a, T>> {
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let match_intervals = find_match_intervals_with(a, b, &opts, hasher);
+    finish_delta(a, b, match_intervals, &opts)
+}
+
+/// The delta that restores `a` from `b`, undoing `delta(a, b, min_match_len)`:
+/// `restore(b, &reverse_delta(a, b, n))` is `a`. Same as `delta(b, a, n)`.
+pub fn reverse_delta<'a, T: Symbol>(
+    a: &'a [T],
+    b: &[T],
+    min_match_len: usize,
+) -> Vec<Compression<'a, T>> {
+    delta(b, a, min_match_len)
+}
+
+/// Both `delta(a, b, n)` and a reverse delta restoring `a` from `b`, from a single
+/// pass over `a` and `b`.
+///
+/// The reverse delta copies the same regions as the forward one, seen from `a`. Parts
+/// of `a` that no forward match copies, or that several copy, are raw, so it may be
+/// larger than `reverse_delta(a, b, n)`.
+pub fn delta_pair<'a, 'b, T: Symbol>(
+    a: &'a [T],
+    b: &'b [T],
+    min_match_len: usize,
+) -> (Vec<Compression<'b, T>>, Vec<Compression<'a, T>>) {
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let mut match_intervals = find_match_intervals_with(a, b, &opts, hasher);
+    match_intervals.retain(|m| m.len >= min_match_len);
+    let mut reversed: Vec<MatchInterval> = match_intervals
+        .iter()
+        .map(|m| MatchInterval {
+            la: m.lb,
+            lb: m.la,
+            len: m.len,
+        })
+        .collect();
+    reversed.sort_by_key(|m| (m.lb, core::cmp::Reverse(m.len)));
+    let reversed = merge_adjacent_intervals(remove_overlaps(reversed), b.len());
+    (
+        finish_delta(a, b, match_intervals, &opts),
+        compressions_from_intervals(a, reversed),
+    )
+}
+
+/// The slices of `a` and of the raw runs that make up the output of `compressions`,
+/// in order.
+///
+/// # Panics
+///
+/// If a `Match` copies from outside of `a` and the output before it. Deltas from
+/// untrusted input should go through `try_restore_with_limit` or `apply` instead.
+pub fn restore<'a, T>(a: &'a [T], compressions: &[Compression<'a, T>]) -> Vec<&'a [T]> {
+    let mut results: Vec<&'a [T]> = Vec::new();
+    // ends[i] is the output length after results[i].
+    let mut ends: Vec<usize> = Vec::new();
+    fn push<'a, T>(results: &mut Vec<&'a [T]>, ends: &mut Vec<usize>, data: &'a [T]) {
+        ends.push(ends.last().unwrap_or(&0) + data.len());
+        results.push(data);
+    }
+    for c in compressions {
+        match *c {
+            Compression::Match(_, 0) => {}
+            Compression::Match(la, len) if la < a.len() => {
+                push(&mut results, &mut ends, &a[la..la + len]);
+            }
+            Compression::Match(la, len) => {
+                // A back-reference is resolved to the slices that produced that output.
+                let mut from = la - a.len();
+                let to = from + len;
+                while from < to {
+                    let i = ends.partition_point(|&end| end <= from);
+                    let data = results[i];
+                    let offset = from - (ends[i] - data.len());
+                    let n = core::cmp::min(to - from, data.len() - offset);
+                    push(&mut results, &mut ends, &data[offset..offset + n]);
+                    from += n;
+                }
+            }
+            Compression::Raw(data) => {
+                push(&mut results, &mut ends, data);
+            }
+        }
+    }
+    results
+}
+
+/// The number of elements `compressions` restores to, so that the output can be
+/// allocated up front, or `None` if that does not fit in a `usize`.
+pub fn reconstruct_len<T>(compressions: &[Compression<T>]) -> Option<usize> {
+    compressions
+        .iter()
+        .try_fold(0usize, |len, c| len.checked_add(c.output_len()))
+}
+
+// Reserves room in `out` for the output of `compressions`. A length that overflows
+// or cannot be allocated is not reserved, and the copies fail on their own instead.
+pub(crate) fn reserve_output<T, U>(out: &mut Vec<T>, compressions: &[Compression<U>]) {
+    if let Some(len) = reconstruct_len(compressions) {
+        let _ = out.try_reserve_exact(len);
+    }
+}
+
+/// Same as `restore`, with the output copied into one `Vec`.
+///
+/// # Panics
+///
+/// As `restore` does.
+pub fn patch<T: Copy>(a: &[T], compressions: &[Compression<T>]) -> Vec<T> {
+    let mut results = Vec::new();
+    restore_into(a, compressions, &mut results);
+    results
+}
+
+/// Same as `delta`, but the raw runs are copied out of `b`.
+pub fn delta_owned<T: Symbol>(a: &[T], b: &[T], min_match_len: usize) -> Vec<CompressionOwned<T>> {
+    delta_iter(a, b, min_match_len)
+        .map(CompressionOwned::from)
+        .collect()
+}
+
+/// Same as `restore`, for a delta of `CompressionOwned`.
+pub fn restore_owned<'a, T>(a: &'a [T], compressions: &'a [CompressionOwned<T>]) -> Vec<&'a [T]> {
+    let compressions: Vec<Compression<'a, T>> =
+        compressions.iter().map(Compression::from).collect();
+    restore(a, &compressions)
+}
+
+/// Same as `patch`, for a delta of `CompressionOwned`.
+pub fn patch_owned<T: Copy>(a: &[T], compressions: &[CompressionOwned<T>]) -> Vec<T> {
+    let compressions: Vec<Compression<T>> = compressions.iter().map(Compression::from).collect();
+    patch(a, &compressions)
+}
+
+/// Merges consecutive `Raw`s into one and joins `Match`es that continue each other,
+/// dropping empty compressions, so that the delta restores the same output with as
+/// few compressions as its matches allow.
+///
+/// `a_len` is the length of the `a` the delta applies to. A match into `a` is never
+/// joined with a back-reference, since the result would straddle the end of `a`.
+pub fn normalize<T>(
+    a_len: usize,
+    compressions: Vec<CompressionOwned<T>>,
+) -> Vec<CompressionOwned<T>> {
+    let mut results: Vec<CompressionOwned<T>> = Vec::with_capacity(compressions.len());
+    for c in compressions {
+        match (results.last_mut(), c) {
+            (_, CompressionOwned::Match(_, 0)) => {}
+            (_, CompressionOwned::Raw(data)) if data.is_empty() => {}
+            (Some(CompressionOwned::Raw(last)), CompressionOwned::Raw(data)) => last.extend(data),
+            (
+                Some(CompressionOwned::Match(la, len)),
+                CompressionOwned::Match(next_la, next_len),
+            ) if *la + *len == next_la && (*la < a_len) == (next_la < a_len) => {
+                *len += next_len;
+            }
+            (_, c) => results.push(c),
+        }
+    }
+    results
+}
+
+/// Same as `patch`, writing into `out`, which is cleared first, so that its
+/// allocation can be reused.
+///
+/// # Panics
+///
+/// As `restore` does.
+pub fn restore_into<T: Copy>(a: &[T], compressions: &[Compression<T>], out: &mut Vec<T>) {
+    out.clear();
+    reserve_output(out, compressions);
+    for c in compressions {
+        restore_one(a, c, out);
+    }
+}
+
+// Appends the output of `c` to `out`, the output of the compressions before it.
+pub(crate) fn restore_one<T: Copy>(a: &[T], c: &Compression<T>, out: &mut Vec<T>) {
+    match *c {
+        Compression::Match(_, 0) => {}
+        Compression::Match(la, len) if la < a.len() => {
+            out.extend_from_slice(&a[la..la + len]);
+        }
+        Compression::Match(la, len) => {
+            // The source may overlap the output being written, so it is copied in
+            // pieces that already exist.
+            let mut from = la - a.len();
+            let mut remaining = len;
+            assert!(
+                from < out.len(),
+                "back-reference past the end of the output"
+            );
+            while remaining > 0 {
+                let n = core::cmp::min(remaining, out.len() - from);
+                out.extend_from_within(from..from + n);
+                from += n;
+                remaining -= n;
+            }
+        }
+        Compression::Raw(data) => {
+            out.extend_from_slice(data);
+        }
+    }
+}
+
+/// Whether applying `compressions` to `a` reproduces `b`. Deltas that do not apply
+/// to `a` at all are reported as `false` rather than panicking, and so are deltas
+/// restoring more than `b.len()` elements, before anything is allocated.
+pub fn verify<T: Copy + PartialEq>(a: &[T], b: &[T], compressions: &[Compression<T>]) -> bool {
+    try_restore_with_limit(a, compressions, b.len()).is_ok_and(|out| out == b)
+}
+
+/// Same as `patch`, but every `Match` is checked against `a` and the output produced
+/// so far before copying, and the output may not be longer than a `Vec<T>` can be.
+///
+/// The output is still allocated at the length the delta asks for, which a few
+/// bytes of untrusted input can make as large as that. Such deltas should go
+/// through `try_restore_with_limit` or `apply` instead.
+pub fn try_restore<T: Copy>(
+    a: &[T],
+    compressions: &[Compression<T>],
+) -> Result<Vec<T>, RestoreError> {
+    let max_len = isize::MAX as usize / core::cmp::max(1, core::mem::size_of::<T>());
+    try_restore_with_limit(a, compressions, max_len)
+}
+
+/// Same as `try_restore`, but a delta restoring to more than `max_len` elements is
+/// refused with `RestoreError::OutputTooLong` before anything is allocated.
+pub fn try_restore_with_limit<T: Copy>(
+    a: &[T],
+    compressions: &[Compression<T>],
+    max_len: usize,
+) -> Result<Vec<T>, RestoreError> {
+    let mut out_len: usize = 0;
+    for (index, c) in compressions.iter().enumerate() {
+        if let Compression::Match(la, len) = *c {
+            let in_a = la.checked_add(len).is_some_and(|end| end <= a.len());
+            let in_out = la >= a.len() && (len == 0 || la - a.len() < out_len);
+            if !in_a && !in_out {
+                return Err(RestoreError::MatchOutOfBounds {
+                    index,
+                    la,
+                    len,
+                    a_len: a.len(),
+                });
+            }
+        }
+        out_len = out_len
+            .checked_add(c.output_len())
+            .filter(|&len| len <= max_len)
+            .ok_or(RestoreError::OutputTooLong {
+                index,
+                max: max_len,
+            })?;
+    }
+    Ok(patch(a, compressions))
+}
+
+fn find_match_intervals_with<T: Symbol, H: WindowHasher<T> + Clone>(
+    a: &[T],
+    b: &[T],
+    opts: &DeltaOptions,
+    hasher: H,
+) -> Vec<MatchInterval> {
+    if (a.is_empty() && !opts.allow_self_reference) || opts.longer_than(b) {
+        return Vec::new();
+    }
+    // The scan fills the table itself under `source_window`.
+    let source = match opts.source_window {
+        Some(_) => SeedTable::new(a, opts.hash_len(), 0),
+        None => SeedTable::sampled(a, opts, hasher.clone()),
+    };
+    find_match_intervals_in(&source, b, opts, hasher)
+}
+
+// `find_match_intervals_with`, against the table of `a` in `index` if there is one.
+fn find_match_intervals_indexed<T: Symbol, H: WindowHasher<T> + Clone>(
+    a: &[T],
+    b: &[T],
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+    hasher: H,
+) -> Vec<MatchInterval> {
+    let Some(index) = index else {
+        return find_match_intervals_with(a, b, opts, hasher);
+    };
+    if (a.is_empty() && !opts.allow_self_reference) || opts.longer_than(b) {
+        return Vec::new();
+    }
+    find_match_intervals_in(&SeedTable::indexed(a, index), b, opts, hasher)
+}
+
+// The scan of `find_match_intervals_with`, against an already built table of `a`.
+fn find_match_intervals_in<T: Symbol, H: WindowHasher<T> + Clone>(
+    source: &SeedTable<T>,
+    b: &[T],
+    opts: &DeltaOptions,
+    hasher: H,
+) -> Vec<MatchInterval> {
+    let hash_len = opts.hash_len();
+    // Windows of `b` are added as the scan passes them, addressed after the end of `a`.
+    let mut target = opts
+        .allow_self_reference
+        .then(|| SeedTable::new(b, hash_len, source.data.len()));
+    let window_len_b = core::cmp::min(b.len(), hash_len);
+    let mut sliding = opts
+        .source_window
+        .map(|source_window| SlidingTable::new(source.data, source_window, opts, hasher.clone()));
+
+    let greedy = opts.strategy == MatchStrategy::Greedy;
+    let mut candidates: Vec<MatchInterval> = Vec::new();
+    let mut state = 0;
+    // The candidates counted towards `max_matches`: those still `min_match_len` long
+    // once trimmed as `remove_overlaps` trims them, and not merged into the one
+    // before. Shorter ones are dropped from the delta, so they do not count.
+    let mut found = 0;
+    let mut last = MatchInterval::empty();
+    for (hb, ib) in RollingHash::with_hasher(b, hash_len, hasher.clone()) {
+        if let Some(sliding) = &mut sliding {
+            sliding.advance(ib, opts);
+        }
+        let source = sliding.as_ref().map_or(source, |sliding| &sliding.table);
+        if opts
+            .max_matches
+            .is_some_and(|max_matches| found >= max_matches)
+        {
+            break;
+        }
+        if !greedy || ib >= state {
+            let prev = match candidates.last() {
+                Some(&prev) if !greedy => prev,
+                _ => MatchInterval::empty(),
+            };
+            let window = &b[ib..ib + window_len_b];
+            let mut m = source.best_match(b, window, hb, ib, &prev);
+            if let Some(target) = &target {
+                let t = target.best_match(b, window, hb, ib, &prev);
+                if t.len > m.len {
+                    m = t;
+                }
+            }
+            if m.len > 0 {
+                state = m.br();
+                candidates.push(m);
+                let mut kept = m;
+                if greedy {
+                    kept.remove_overlap(&last);
+                }
+                if kept.len > 0 {
+                    let merged = last.br() == kept.lb && last.la + last.len == kept.la;
+                    if kept.len >= core::cmp::max(1, opts.min_match_len) && !merged {
+                        found += 1;
+                    }
+                    last = kept;
+                }
+            }
+        }
+        if let Some(target) = &mut target {
+            if opts.is_seed(ib) {
+                target.insert(hb, ib);
+            }
+        }
+    }
+
+    let match_intervals = match opts.strategy {
+        MatchStrategy::Greedy => remove_overlaps(candidates),
+        MatchStrategy::Longest => select_longest(candidates),
+        MatchStrategy::MinimalOps => select_fewest_ops(candidates, b.len(), opts.min_match_len),
+    };
+    extend_into_raws(match_intervals, source.data, b)
+}
+
+// How a cover of `b[p..]` goes on from `p` in `select_fewest_ops`.
+#[derive(Clone, Copy)]
+enum NextOp {
+    // A match of `candidates[i]` from `p` on.
+    Match(usize),
+    // Raw data up to all of `candidates[i]`.
+    RawThenMatch(usize),
+    // Raw data up to the end of `b`.
+    Raw,
+}
+
+// The selection from `candidates` with the least cost, counting `min_match_len` for
+// each compression plus one for each raw element. A candidate may be cut to start
+// anywhere inside it, and a cover only ever ends a match where its candidate ends,
+// so the positions between compressions are 0 and the ends of candidates.
+fn select_fewest_ops(
+    mut candidates: Vec<MatchInterval>,
+    b_len: usize,
+    min_match_len: usize,
+) -> Vec<MatchInterval> {
+    let op_cost = core::cmp::max(1, min_match_len);
+    candidates.retain(|m| m.len >= op_cost);
+    candidates.sort_by_key(|m| (m.lb, core::cmp::Reverse(m.br())));
+    // The candidate reaching furthest among the first `i + 1`.
+    let mut furthest: Vec<usize> = Vec::with_capacity(candidates.len());
+    for (i, m) in candidates.iter().enumerate() {
+        match furthest.last() {
+            Some(&j) if candidates[j].br() >= m.br() => furthest.push(j),
+            _ => furthest.push(i),
+        }
+    }
+    let mut positions: Vec<usize> = candidates.iter().map(MatchInterval::br).collect();
+    positions.push(0);
+    positions.sort_unstable();
+    positions.dedup();
+
+    // The least cost of covering `b[positions[k]..]`, and how it goes on.
+    let mut costs: Vec<(usize, NextOp)> = vec![(0, NextOp::Raw); positions.len()];
+    let cost_at = |costs: &[(usize, NextOp)], p: usize| match positions.binary_search(&p) {
+        Ok(k) => costs[k].0,
+        Err(_) => 0,
+    };
+    // The candidates starting after the position at hand with the cheapest
+    // `lb + cost(br)`, added as the positions go down.
+    let mut after = candidates.len();
+    let mut cheapest_after: Option<(usize, usize)> = None;
+    for k in (0..positions.len()).rev() {
+        let p = positions[k];
+        if p >= b_len {
+            continue;
+        }
+        while after > 0 && candidates[after - 1].lb > p {
+            after -= 1;
+            let m = candidates[after];
+            let cost = m.lb + cost_at(&costs, m.br());
+            if cheapest_after.is_none_or(|(best, _)| cost <= best) {
+                cheapest_after = Some((cost, after));
+            }
+        }
+        let mut best = (op_cost + b_len - p, NextOp::Raw);
+        if let Some(&i) = furthest[..after].last() {
+            let m = candidates[i];
+            if m.br() >= p + op_cost {
+                let cost = op_cost + cost_at(&costs, m.br());
+                if cost <= best.0 {
+                    best = (cost, NextOp::Match(i));
+                }
+            }
+        }
+        if let Some((cost, i)) = cheapest_after {
+            let cost = 2 * op_cost + cost - p;
+            if cost < best.0 {
+                best = (cost, NextOp::RawThenMatch(i));
+            }
+        }
+        costs[k] = best;
+    }
+
+    let mut results = Vec::new();
+    let mut p = 0;
+    while p < b_len {
+        let k = positions
+            .binary_search(&p)
+            .expect("covers end at candidate ends");
+        let m = match costs[k].1 {
+            NextOp::Match(i) => candidates[i].slice(p, candidates[i].br()),
+            NextOp::RawThenMatch(i) => candidates[i],
+            NextOp::Raw => break,
+        };
+        results.push(m);
+        p = m.br();
+    }
+    results
+}
+
+// The number of compressions `compressions_from_intervals` makes of the finished
+// `match_intervals` of a `b` that is `b_len` long.
+fn op_count(match_intervals: &[MatchInterval], b_len: usize) -> usize {
+    let mut count = 0;
+    let mut prev = 0;
+    for m in match_intervals {
+        count += 1 + usize::from(prev < m.lb);
+        prev = m.br();
+    }
+    count + usize::from(prev < b_len)
+}
+
+// Trims each greedy candidate against the last one kept, dropping any that vanish.
+fn remove_overlaps(candidates: Vec<MatchInterval>) -> Vec<MatchInterval> {
+    let mut acc = MatchInterval::empty();
+    candidates
+        .into_iter()
+        .filter_map(|mut m| {
+            m.remove_overlap(&acc);
+            if m.len > 0 {
+                acc = m;
+                Some(m)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Overlap removal may cut a match further than the match before it reaches, which
+// leaves a raw run that the match could still cover. Each match is grown back over
+// it, as far as its source agrees.
+fn extend_into_raws<T: PartialEq>(
+    mut match_intervals: Vec<MatchInterval>,
+    a: &[T],
+    b: &[T],
+) -> Vec<MatchInterval> {
+    let mut bound = 0;
+    for m in &mut match_intervals {
+        m.extend_left(a, b, bound);
+        bound = core::cmp::max(bound, m.br());
+    }
+    match_intervals
+}
+
+// Keys are already well-mixed window hashes, so with the `fast-hash` feature they
+// skip SipHash and only go through a cheap multiplicative mix. Without `std` there
+// is no SipHash to fall back to.
+#[cfg(any(feature = "fast-hash", not(feature = "std")))]
+type FingerprintMap<V> = HashMap<u64, V, core::hash::BuildHasherDefault<FxHasher>>;
+#[cfg(all(feature = "std", not(feature = "fast-hash")))]
+type FingerprintMap<V> = HashMap<u64, V>;
+
+// The rustc "Fx" hash. The multiply spreads small polynomial hashes over the high
+// bits, which `HashMap` uses to filter probes.
+#[cfg(any(feature = "fast-hash", not(feature = "std")))]
+#[derive(Default)]
+struct FxHasher {
+    hash: u64,
+}
+
+#[cfg(any(feature = "fast-hash", not(feature = "std")))]
+impl core::hash::Hasher for FxHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+        self.hash = (self.hash.rotate_left(5) ^ n).wrapping_mul(SEED);
+    }
+}
+
+// How many elements `SeedTable::best_match` may compare on candidates that lose,
+// per window of `b`, before it settles for the longest match found so far.
+const MAX_WASTED_EXTENSION: usize = 1 << 12;
+
+// Sampled windows of `data` keyed by hash. Matches found in it are reported with
+// `shift` added to their source offset.
+struct SeedTable<'s, T> {
+    data: &'s [T],
+    window_len: usize,
+    shift: usize,
+    // Borrowed from a `SourceIndex` when it was built beforehand.
+    positions: Cow<'s, FingerprintMap<Vec<usize>>>,
+    // `DeltaOptions::source_window`, for candidates that may still be in the table
+    // after falling out of it.
+    source_window: Option<usize>,
+    max_displacement: Option<usize>,
+}
+
+impl<'s, T: Symbol> SeedTable<'s, T> {
+    fn new(data: &'s [T], hash_len: usize, shift: usize) -> Self {
+        Self {
+            data,
+            window_len: core::cmp::min(data.len(), hash_len),
+            shift,
+            positions: Cow::Owned(FingerprintMap::default()),
+            source_window: None,
+            max_displacement: None,
+        }
+    }
+
+    // The windows of `data` that `index` was built from.
+    fn indexed(data: &'s [T], index: &'s SourceIndex) -> Self {
+        Self {
+            data,
+            window_len: core::cmp::min(data.len(), index.opts.hash_len()),
+            shift: 0,
+            positions: Cow::Borrowed(&index.positions),
+            source_window: None,
+            max_displacement: index.opts.max_displacement,
+        }
+    }
+
+    // The windows of `data` that `opts` samples, hashed with `hasher`.
+    fn sampled<H: WindowHasher<T>>(data: &'s [T], opts: &DeltaOptions, hasher: H) -> Self {
+        let hash_len = opts.hash_len();
+        let mut table = Self::new(data, hash_len, 0);
+        for (h, i) in RollingHash::with_hasher(data, hash_len, hasher) {
+            if opts.is_seed(i) {
+                table.insert(h, i);
+            }
+        }
+        table.max_displacement = opts.max_displacement;
+        table
+    }
+
+    // Whether a match seeded at `data[i..]` and `b[ib..]` is allowed by
+    // `source_window` and `max_displacement`. Both only depend on the diagonal.
+    fn in_reach(&self, i: usize, ib: usize) -> bool {
+        let in_window = self
+            .source_window
+            .is_none_or(|source_window| i <= ib && ib - i < source_window);
+        in_window
+            && self
+                .max_displacement
+                .is_none_or(|max_displacement| i.abs_diff(ib) <= max_displacement)
+    }
+
+    fn insert(&mut self, hash: u64, index: usize) {
+        self.positions.to_mut().entry(hash).or_default().push(index);
+    }
+
+    // Drops the windows before `start`.
+    fn evict_before(&mut self, start: usize) {
+        self.positions.to_mut().retain(|_, positions| {
+            positions.retain(|&i| i >= start);
+            !positions.is_empty()
+        });
+    }
+
+    // The longest match seeded at `b[ib..]`, whose window is `window` with hash `hb`.
+    // Candidates on the diagonal of `prev` while still inside it would only extend
+    // back to `prev`, so they are skipped.
+    fn best_match(
+        &self,
+        b: &[T],
+        window: &[T],
+        hb: u64,
+        ib: usize,
+        prev: &MatchInterval,
+    ) -> MatchInterval {
+        let Some(candidates) = self.positions.get(&hb) else {
+            return MatchInterval::empty();
+        };
+        // Different windows can share a hash, so the seed itself is compared.
+        // Among the remaining candidates the longest match wins, the first on ties.
+        let mut best = MatchInterval::empty();
+        // Elements compared extending candidates that did not win. On repetitive
+        // data every candidate can match a long way, so past `MAX_WASTED_EXTENSION`
+        // the rest are not tried.
+        let mut wasted = 0;
+        for &i in candidates {
+            if wasted > MAX_WASTED_EXTENSION {
+                break;
+            }
+            // A match is no longer than the room around its seed in both inputs.
+            let room = core::cmp::min(i, ib) + core::cmp::min(self.data.len() - i, b.len() - ib);
+            if room <= best.len
+                || !self.in_reach(i, ib)
+                || (ib < prev.br() && i + self.shift + prev.lb == ib + prev.la)
+                || self.data[i..i + self.window_len] != *window
+            {
+                continue;
+            }
+            let mut m = MatchInterval::new(self.data, b, i, ib);
+            m.la += self.shift;
+            if m.len > best.len {
+                wasted += best.len;
+                best = m;
+            } else {
+                wasted += m.len;
+            }
+        }
+        best
+    }
+}
+
+// The table of the windows of `a` that the scan of `b` can reach under
+// `DeltaOptions::source_window`: those up to its position and less than the window
+// behind it. Windows are added as the scan reaches them, and the ones it has passed
+// are dropped once per window length, so the table holds at most about two windows
+// of `a`.
+struct SlidingTable<'s, T: Symbol, H: WindowHasher<T>> {
+    table: SeedTable<'s, T>,
+    hashes: core::iter::Peekable<RollingHash<'s, T, H>>,
+    next_eviction: usize,
+}
+
+impl<'s, T: Symbol, H: WindowHasher<T>> SlidingTable<'s, T, H> {
+    fn new(data: &'s [T], source_window: usize, opts: &DeltaOptions, hasher: H) -> Self {
+        let source_window = core::cmp::max(1, source_window);
+        let hash_len = opts.hash_len();
+        let mut table = SeedTable::new(data, hash_len, 0);
+        table.source_window = Some(source_window);
+        table.max_displacement = opts.max_displacement;
+        Self {
+            table,
+            hashes: RollingHash::with_hasher(data, hash_len, hasher).peekable(),
+            next_eviction: source_window,
+        }
+    }
+
+    // Brings the table up to the scan being at `b[ib..]`.
+    fn advance(&mut self, ib: usize, opts: &DeltaOptions) {
+        while let Some((h, i)) = self.hashes.next_if(|&(_, i)| i <= ib) {
+            if opts.is_seed(i) {
+                self.table.insert(h, i);
+            }
+        }
+        if let Some(source_window) = self
+            .table
+            .source_window
+            .filter(|_| ib >= self.next_eviction)
+        {
+            self.table.evict_before(ib + 1 - source_window);
+            self.next_eviction = ib + source_window;
+        }
+    }
+}
+
+// Picks non-overlapping intervals from `candidates`, longest first. Parts of an
+// interval that overlap an already chosen one are cut off.
+fn select_longest(mut candidates: Vec<MatchInterval>) -> Vec<MatchInterval> {
+    candidates.sort_by(|x, y| {
+        y.len
+            .cmp(&x.len)
+            .then(x.la.cmp(&y.la))
+            .then(x.lb.cmp(&y.lb))
+    });
+    candidates.dedup();
+
+    let mut chosen: BTreeMap<usize, MatchInterval> = BTreeMap::new();
+    for m in candidates {
+        let mut pieces = Vec::new();
+        let mut start = m.lb;
+        if let Some((_, prev)) = chosen.range(..m.lb).next_back() {
+            start = core::cmp::max(start, prev.br());
+        }
+        for (_, c) in chosen.range(m.lb..m.br()) {
+            if start < c.lb {
+                pieces.push(m.slice(start, c.lb));
+            }
+            start = core::cmp::max(start, c.br());
+        }
+        if start < m.br() {
+            pieces.push(m.slice(start, m.br()));
+        }
+        for piece in pieces {
+            chosen.insert(piece.lb, piece);
+        }
+    }
+    chosen.into_values().collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MatchInterval {
+    la: usize,
+    lb: usize,
+    len: usize,
+}
+
+impl MatchInterval {
+    // Search the matching interval from a[ia] and b[ib].
+    // a[la..la+len] == b[lb..lb+len].
+    // Either seed may be in the last window of its side: each direction stops at the
+    // first end of `a` or `b` it reaches.
+    fn new<T: Symbol>(a: &[T], b: &[T], ia: usize, ib: usize) -> Self {
+        let r = T::common_prefix_len(&a[ia..], &b[ib..]);
+        let l = T::common_suffix_len(&a[..ia], &b[..ib]);
+
+        let la = ia - l;
+        let lb = ib - l;
+        let len = l + r;
+        Self { la, lb, len }
+    }
+
+    fn empty() -> Self {
+        static EMPTY: MatchInterval = MatchInterval {
+            la: 0,
+            lb: 0,
+            len: 0,
+        };
+        EMPTY
+    }
+
+    fn br(&self) -> usize {
+        self.lb + self.len
+    }
+
+    // The part of this interval covering b[from..to].
+    fn slice(&self, from: usize, to: usize) -> Self {
+        Self {
+            la: self.la + (from - self.lb),
+            lb: from,
+            len: to - from,
+        }
+    }
+
+    // Cuts the front of `self` so that it starts after `other`, the match emitted
+    // before it. `self` may have been extended left past `other.lb`, but only its part
+    // after `other` can still follow it in the output.
+    fn remove_overlap(&mut self, other: &Self) {
+        if other.br() <= self.lb {
+            return;
+        }
+        // From here on `self.lb < other.br()`, whichever of the two starts first.
+        if self.br() <= other.br() {
+            // Nothing of `self` reaches past `other`.
+            self.len = 0;
+            return;
+        }
+
+        // `self.br() > other.br()`, so `diff <= self.len`.
+        let diff = other.br() - self.lb + 1;
+        self.len -= diff;
+        self.la += diff;
+        self.lb += diff;
+    }
+
+    // Extends `self` to the left while its source still agrees with `b`, but not
+    // before `bound` in `b`, nor across the start of `a` or of the output.
+    fn extend_left<T: PartialEq>(&mut self, a: &[T], b: &[T], bound: usize) {
+        let (source, start) = if self.la < a.len() {
+            (a, 0)
+        } else {
+            (b, a.len())
+        };
+        while self.lb > bound && self.la > start && source[self.la - start - 1] == b[self.lb - 1] {
+            self.la -= 1;
+            self.lb -= 1;
+            self.len += 1;
+        }
+    }
+}
+
+// A xorshift64 generator from `seed`, so that tests on random inputs are the same on
+// every run.
+#[cfg(test)]
+pub(crate) fn xorshift(mut state: u64) -> impl Iterator<Item = u64> {
+    core::iter::repeat_with(move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    })
+}
+
+// The low bytes of the first `len` values of `xorshift(seed)`.
+#[cfg(test)]
+pub(crate) fn xorshift_bytes(len: usize, seed: u64) -> Vec<u8> {
+    xorshift(seed).take(len).map(|x| x as u8).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_match_interval(la: usize, lb: usize, len: usize) -> MatchInterval {
+        MatchInterval { la, lb, len }
+    }
+
+    fn find_match_intervals(a: &[u8], b: &[u8], min_match_len: usize) -> Vec<MatchInterval> {
+        let opts = DeltaOptions::default().min_match_len(min_match_len);
+        find_match_intervals_with(a, b, &opts, PolynomialHasher::default())
+    }
+
+    #[test]
+    fn extract_match_2345() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [2, 3, 4, 5];
+        let result = find_match_intervals(&a, &b, 4);
+        assert_eq!(result, vec![make_match_interval(2, 0, 4)]);
+    }
+
+    #[test]
+    fn extract_match_45() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [0, 4, 5, 0];
+        let result = find_match_intervals(&a, &b, 1);
+        assert_eq!(
+            result,
+            vec![
+                make_match_interval(0, 0, 1), // 0.
+                make_match_interval(4, 1, 2), // 4 5.
+                make_match_interval(0, 3, 1), // 0.
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_match_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let result = find_match_intervals(&a, &b, 1);
+        assert_eq!(
+            result,
+            vec![
+                make_match_interval(5, 0, 3), // 5 6 7.
+                make_match_interval(1, 5, 3), // 1 2 3.
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_match_whole_a() {
+        let a = [0, 1, 2];
+        let b = [9, 0, 1, 2];
+        let result = find_match_intervals(&a, &b, 4);
+        assert_eq!(result, vec![make_match_interval(0, 1, 3)]);
+    }
+
+    #[test]
+    fn extract_match_tail_of_a() {
+        let a = [9, 9, 9, 0, 1, 2];
+        let b = [0, 1, 2];
+        let result = find_match_intervals(&a, &b, 3);
+        assert_eq!(result, vec![make_match_interval(3, 0, 3)]);
+    }
+
+    #[test]
+    fn match_interval_new_at_tails() {
+        let a = [9, 9, 0, 1, 2, 3];
+        let b = [8, 0, 1, 2, 3];
+        assert_eq!(
+            MatchInterval::new(&a, &b, 4, 3),
+            make_match_interval(2, 1, 4)
+        );
+        assert_eq!(
+            MatchInterval::new(&a, &b, 2, 1),
+            make_match_interval(2, 1, 4)
+        );
+        // One side goes on past the end of the other.
+        let c = [8, 0, 1, 2, 3, 4, 5];
+        assert_eq!(
+            MatchInterval::new(&a, &c, 4, 3),
+            make_match_interval(2, 1, 4)
+        );
+        assert_eq!(
+            MatchInterval::new(&c, &a, 3, 4),
+            make_match_interval(1, 2, 4)
+        );
+        // Nothing but the seed itself matches.
+        let (x, y) = ([5, 2, 3], [6, 2, 3]);
+        assert_eq!(
+            MatchInterval::new(&x, &y, 1, 1),
+            make_match_interval(1, 1, 2)
+        );
+    }
+
+    #[test]
+    fn extract_match_tails_of_a_and_b() {
+        use Compression::*;
+        // Windows of 4 are hashed, and `a` is sampled at 0, 4 and 8.
+        let a = [9, 9, 9, 9, 9, 9, 9, 9, 0, 1, 2, 3];
+        let b = [8, 8, 8, 8, 8, 8, 0, 1, 2, 3];
+        assert_eq!(
+            find_match_intervals(&a, &b, 7),
+            vec![make_match_interval(8, 6, 4)]
+        );
+        assert_eq!(delta(&a, &b, 7), vec![Raw(&b[..])]);
+        let b = [8, 8, 8, 8, 9, 9, 0, 1, 2, 3];
+        assert_eq!(
+            find_match_intervals(&a, &b, 7),
+            vec![make_match_interval(6, 4, 6)]
+        );
+        assert_eq!(delta(&a, &b, 6), vec![Raw(&b[..4]), Match(6, 6)]);
+    }
+
+    #[test]
+    fn delta_match_of_hash_len_at_both_tails() {
+        use Compression::*;
+        let a = [5, 6, 7];
+        let b = [8, 9, 7];
+        assert_eq!(delta(&a, &b, 1), vec![Raw(&b[..2]), Match(2, 1)]);
+        assert_eq!(delta(&a, &b[2..], 1), vec![Match(2, 1)]);
+        assert_eq!(delta(&a, &b, 2), vec![Raw(&b[..])]);
+    }
+
+    #[test]
+    fn min_match_len_longer_than_b() {
+        use Compression::*;
+        let a = [9, 9, 9, 0, 1, 2];
+        let b = [0, 1, 2];
+        assert!(find_match_intervals(&a, &b, 4).is_empty());
+        assert_eq!(delta(&a, &b, 4), vec![Raw(&b[..])]);
+        assert_eq!(delta(&a, &b, 3), vec![Match(3, 3)]);
+        for n in [4, 5, 100, usize::MAX] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            assert_eq!(delta_with(&a, &b, &opts), vec![Raw(&b[..])]);
+            assert_eq!(delta_with(&a, &b[..0], &opts), vec![Raw(&b[..0])]);
+            assert_eq!(
+                delta_multi(&[&a], &b, n),
+                vec![CompressionMulti::Raw(&b[..])]
+            );
+        }
+    }
+
+    // Applies `d` over a buffer holding `a`, checking that no match reads what
+    // earlier output or the match itself writes.
+    fn patch_in_place(a: &[u8], d: &[Compression<u8>]) -> Vec<u8> {
+        let mut buf = a.to_vec();
+        let mut pos = 0;
+        for c in d {
+            match *c {
+                Compression::Match(la, len) => {
+                    let from = if la < a.len() {
+                        assert!(pos + len <= la, "Match({la}, {len}) at {pos}");
+                        la
+                    } else {
+                        assert!(la - a.len() + len <= pos, "Match({la}, {len}) at {pos}");
+                        la - a.len()
+                    };
+                    buf.resize(core::cmp::max(buf.len(), pos + len), 0);
+                    buf.copy_within(from..from + len, pos);
+                    pos += len;
+                }
+                Compression::Raw(raw) => {
+                    buf.resize(core::cmp::max(buf.len(), pos + raw.len()), 0);
+                    buf[pos..pos + raw.len()].copy_from_slice(raw);
+                    pos += raw.len();
+                }
+            }
+        }
+        buf.truncate(pos);
+        buf
+    }
+
+    #[test]
+    fn resync_window_keeps_matches_within_windows() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for opts in [
+            DeltaOptions::default().min_match_len(8),
+            DeltaOptions::default()
+                .min_match_len(8)
+                .allow_self_reference(true),
+        ] {
+            let opts = opts.resync_window(Some(100));
+            let d = delta_with(a, b, &opts);
+            assert_eq!(restore(a, &d).concat(), b);
+            let mut pos = 0;
+            for c in &d {
+                let len = c.output_len();
+                if len > 0 {
+                    assert_eq!(pos / 100, (pos + len - 1) / 100);
+                }
+                if let Compression::Match(la, _) = *c {
+                    if la >= a.len() {
+                        assert!(la - a.len() >= pos / 100 * 100);
+                    }
+                }
+                pos += len;
+            }
+        }
+    }
+
+    #[test]
+    fn normalize_matches_regardless_of_case() {
+        use Compression::*;
+        let a = b"The Quick Brown Fox";
+        let b = b"the quick brown fox!";
+        let opts = DeltaOptions::default().min_match_len(8);
+        assert_eq!(delta_with(a, b, &opts), vec![Raw(&b[..])]);
+        let opts = opts.normalize(Some(|x: u8| x.to_ascii_lowercase()));
+        let d = delta_with(a, b, &opts);
+        assert_eq!(
+            d,
+            vec![
+                Raw(b"t"),
+                Match(1, 3),
+                Raw(b"q"),
+                Match(5, 5),
+                Raw(b"b"),
+                Match(11, 5),
+                Raw(b"f"),
+                Match(17, 2),
+                Raw(b"!"),
+            ]
+        );
+        assert_eq!(restore(a, &d).concat(), b);
+    }
+
+    #[test]
+    fn normalize_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b: Vec<u8> = include_bytes!("../examples/b.txt")
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                if i % 40 < 20 {
+                    x.to_ascii_uppercase()
+                } else {
+                    *x
+                }
+            })
+            .collect();
+        let lowercase: Option<fn(u8) -> u8> = Some(|x| x.to_ascii_lowercase());
+        for opts in [
+            DeltaOptions::default().min_match_len(16),
+            DeltaOptions::default()
+                .min_match_len(16)
+                .allow_self_reference(true),
+        ] {
+            let plain = stats(&delta_with(a, &b, &opts)).matched_bytes;
+            let d = delta_with(a, &b, &opts.normalize(lowercase));
+            assert_eq!(restore(a, &d).concat(), b);
+            assert!(stats(&d).matched_bytes > plain);
+        }
+        // Elements other than bytes are not normalized.
+        let a: Vec<u32> = a.iter().map(|&x| x as u32).collect();
+        let b: Vec<u32> = b.iter().map(|&x| x as u32).collect();
+        let opts = DeltaOptions::default().min_match_len(16);
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().normalize(lowercase)),
+            delta_with(&a, &b, &opts)
+        );
+    }
+
+    #[test]
+    fn block_align_trims_to_blocks() {
+        use Compression::*;
+        let a: Vec<u8> = (0..64).map(|i| (i * 37 % 251) as u8).collect();
+        let opts = DeltaOptions::default()
+            .min_match_len(8)
+            .block_align(Some(8));
+        // a[10..50] at b[2..42] straddles blocks on both ends; blocks 1..5 of b remain.
+        let mut b = vec![99, 99];
+        b.extend_from_slice(&a[10..50]);
+        assert_eq!(delta(&a, &b, 8), vec![Raw(&b[..2]), Match(10, 40)]);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&b[..8]), Match(16, 32), Raw(&b[40..])]
+        );
+        // Offsets 10 and 6 are never both on a block boundary.
+        let mut b = vec![99; 6];
+        b.extend_from_slice(&a[10..50]);
+        assert_eq!(delta_with(&a, &b, &opts), vec![Raw(&b[..])]);
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().block_align(Some(2))),
+            vec![Raw(&b[..6]), Match(10, 40)]
+        );
+        assert_eq!(
+            delta_with(&a, &b, &opts.block_align(Some(0))),
+            delta(&a, &b, 8)
+        );
+    }
+
+    #[test]
+    fn block_align_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        // Windows of 37 start off the block boundaries, which count from the start
+        // of the output.
+        for (block, resync_window) in [2, 4, 16]
+            .into_iter()
+            .flat_map(|block| [(block, None), (block, Some(37))])
+        {
+            let opts = DeltaOptions::default()
+                .min_match_len(4)
+                .allow_self_reference(true)
+                .block_align(Some(block))
+                .resync_window(resync_window);
+            let d = delta_with(a, b, &opts);
+            assert_eq!(restore(a, &d).concat(), b);
+            let mut pos = 0;
+            for c in &d {
+                match *c {
+                    Compression::Match(la, len) => {
+                        let source = if la < a.len() { la } else { la - a.len() };
+                        assert_eq!((source % block, pos % block, len % block), (0, 0, 0));
+                        pos += len;
+                    }
+                    Compression::Raw(data) => pos += data.len(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_sources_shifted() {
+        use Compression::*;
+        let a: Vec<u8> = (0..40).collect();
+        // `b` is `a` moved 10 to the left, so each piece may copy at most 10.
+        let b = &a[10..];
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .disjoint_sources(true);
+        let d = delta_with(&a, b, &opts);
+        assert_eq!(d, vec![Match(10, 10), Match(20, 10), Match(30, 10)]);
+        assert_eq!(patch_in_place(&a, &d), b);
+        assert_eq!(delta(&a, b, 4), vec![Match(10, 30)]);
+
+        // Moved to the right, every match reads what the output already overwrote.
+        let mut b = vec![99; 10];
+        b.extend_from_slice(&a);
+        let d = delta_with(&a, &b, &opts);
+        assert_eq!(d, vec![Raw(&b[..])]);
+        assert_eq!(patch_in_place(&a, &d), b);
+    }
+
+    #[test]
+    fn disjoint_sources_resync_window() {
+        use Compression::*;
+        let a = xorshift_bytes(64, 1);
+        // The second window copies `a[16..32]` to output offset 32, over which the
+        // first window has already written.
+        let mut b = xorshift_bytes(32, 2);
+        b.extend_from_slice(&a[16..32]);
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .disjoint_sources(true)
+            .resync_window(Some(16));
+        let d = delta_with(&a, &b, &opts);
+        assert_eq!(d, vec![Raw(&b[..16]), Raw(&b[16..32]), Raw(&b[32..])]);
+        assert_eq!(patch_in_place(&a, &d), b);
+
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for window in [7, 100, 1000] {
+            let opts = opts.clone().min_match_len(8).resync_window(Some(window));
+            assert_eq!(patch_in_place(a, &delta_with(a, b, &opts)), b);
+            assert_eq!(patch_in_place(b, &delta_with(b, a, &opts)), a);
+        }
+    }
+
+    #[test]
+    fn disjoint_sources_partitions() {
+        use Compression::*;
+        let a = xorshift_bytes(64, 1);
+        let mut b = xorshift_bytes(32, 2);
+        b.extend_from_slice(&a[16..32]);
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .disjoint_sources(true)
+            .partition_for_parallel(Some(3));
+        let d = delta_with(&a, &b, &opts);
+        assert_eq!(d, vec![Raw(&b[..16]), Raw(&b[16..32]), Raw(&b[32..])]);
+        assert_eq!(patch_in_place(&a, &d), b);
+
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for count in [2, 7, 64] {
+            let opts = opts
+                .clone()
+                .min_match_len(8)
+                .partition_for_parallel(Some(count));
+            assert_eq!(patch_in_place(a, &delta_with(a, b, &opts)), b);
+            assert_eq!(patch_in_place(b, &delta_with(b, a, &opts)), a);
+        }
+    }
+
+    #[test]
+    fn disjoint_sources_self_reference() {
+        let a = b"xyz";
+        let b = b"abcdabcdabcdabcdabcd";
+        let opts = DeltaOptions::default()
+            .min_match_len(3)
+            .allow_self_reference(true);
+        let d = delta_with(a, b, &opts);
+        assert!(d
+            .iter()
+            .any(|c| matches!(c, Compression::Match(la, len) if *la >= a.len() && *len > 4)));
+        let d = delta_with(a, b, &opts.disjoint_sources(true));
+        assert_eq!(patch_in_place(a, &d), b);
+        assert_eq!(restore(a, &d).concat(), b);
+    }
+
+    #[test]
+    fn disjoint_sources_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let opts = DeltaOptions::default()
+                .min_match_len(n)
+                .disjoint_sources(true);
+            let d = delta_with(a, b, &opts);
+            assert_eq!(patch_in_place(a, &d), b);
+            assert_eq!(patch_in_place(b, &delta_with(b, a, &opts)), a);
+        }
+    }
+
+    #[test]
+    fn reverse_delta_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            assert_eq!(restore(b, &reverse_delta(a, b, n)).concat(), a);
+            assert_eq!(restore(a, &reverse_delta(b, a, n)).concat(), b);
+
+            let (forward, reverse) = delta_pair(a, b, n);
+            assert_eq!(forward, delta(a, b, n));
+            assert_eq!(restore(a, &forward).concat(), b);
+            assert_eq!(restore(b, &reverse).concat(), a);
+            let (forward, reverse) = delta_pair(b, a, n);
+            assert_eq!(restore(b, &forward).concat(), a);
+            assert_eq!(restore(a, &reverse).concat(), b);
+        }
+    }
+
+    #[test]
+    fn delta_pair_reordered_and_repeated() {
+        use Compression::*;
+        let a: Vec<u8> = (0..30).collect();
+        // Two copies of a[20..30], then a[0..10]; a[10..20] is deleted.
+        let mut b = a[20..].to_vec();
+        b.extend_from_slice(&a[20..]);
+        b.extend_from_slice(&a[..10]);
+        let (forward, reverse) = delta_pair(&a, &b, 4);
+        assert_eq!(forward, vec![Match(20, 10), Match(20, 10), Match(0, 10)]);
+        assert_eq!(reverse, vec![Match(20, 10), Raw(&a[10..20]), Match(0, 10)]);
+        assert_eq!(
+            delta_pair(&a, &[], 4),
+            (vec![Raw(&[][..])], vec![Raw(&a[..])])
+        );
+    }
+
+    #[test]
+    fn compressions_from_unordered_intervals() {
+        use Compression::*;
+        let b: Vec<u8> = (0..20).collect();
+        let a = b.clone();
+        // The second starts inside the first, the third inside the second and the
+        // fourth before both.
+        let intervals = vec![
+            make_match_interval(2, 2, 6),
+            make_match_interval(5, 5, 6),
+            make_match_interval(9, 9, 2),
+            make_match_interval(0, 0, 4),
+            make_match_interval(15, 15, 3),
+        ];
+        let d = compressions_from_intervals(&b, intervals);
+        assert_eq!(
+            d,
+            vec![
+                Raw(&b[..2]),
+                Match(2, 6),
+                Match(8, 3),
+                Raw(&b[11..15]),
+                Match(15, 3),
+                Raw(&b[18..]),
+            ]
+        );
+        assert_eq!(restore(&a, &d).concat(), b);
+    }
+
+    #[test]
+    fn delta_indexed_same_as_delta_with() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for opts in [
+            DeltaOptions::default(),
+            DeltaOptions::default().min_match_len(2),
+            DeltaOptions::default().hash_mode(HashMode::Double),
+            DeltaOptions::default().strategy(MatchStrategy::Longest),
+            DeltaOptions::default().allow_self_reference(true),
+            DeltaOptions::default().resync_window(Some(100)),
+            DeltaOptions::default()
+                .resync_window(Some(64))
+                .allow_self_reference(true),
+            DeltaOptions::default().partition_for_parallel(Some(3)),
+            DeltaOptions::default()
+                .partition_for_parallel(Some(4))
+                .allow_self_reference(true),
+        ] {
+            let index = SourceIndex::build(a, &opts);
+            // The index is reused for every target.
+            for target in [&b[..], &a[..], &b[100..300], &b[..0]] {
+                assert_eq!(
+                    delta_indexed(&index, a, target),
+                    delta_with(a, target, &opts)
+                );
+            }
+        }
+        let index = SourceIndex::build(&a[..0], &DeltaOptions::default());
+        assert_eq!(
+            delta_indexed(&index, &a[..0], b),
+            vec![Compression::Raw(&b[..])]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not the source")]
+    fn delta_indexed_other_source() {
+        let index = SourceIndex::build(b"abcdefgh", &DeltaOptions::default());
+        delta_indexed(&index, b"abc", b"abcdefgh");
+    }
+
+    #[test]
+    fn max_matches_caps_match_count() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let unbounded = stats(&delta(a, b, 4)).match_count;
+        for max_matches in [0, 1, 5, 50] {
+            for opts in [
+                DeltaOptions::default().min_match_len(4),
+                DeltaOptions::default().min_match_len(4).max_match_len(3),
+                DeltaOptions::default()
+                    .min_match_len(4)
+                    .strategy(MatchStrategy::Longest),
+            ] {
+                let opts = opts.max_matches(Some(max_matches));
+                let d = delta_with(a, b, &opts);
+                assert_eq!(restore(a, &d).concat(), b);
+                assert!(stats(&d).match_count <= max_matches);
+                assert!(stats(&d).match_count < unbounded);
+            }
+        }
+        let d = delta_with(a, b, &DeltaOptions::default().max_matches(Some(0)));
+        assert_eq!(d, vec![Compression::Raw(&b[..])]);
+    }
+
+    #[test]
+    fn max_matches_above_match_count() {
+        use Compression::*;
+        let (a, b) = (b"aca", b"aaacb");
+        let opts = DeltaOptions::default().min_match_len(2);
+        let unbounded = delta_with(a, b, &opts);
+        assert_eq!(unbounded, vec![Raw(b"aa"), Match(0, 2), Raw(b"b")]);
+        for max_matches in [1, 2, 3] {
+            let opts = opts.clone().max_matches(Some(max_matches));
+            assert_eq!(delta_with(a, b, &opts), unbounded);
+        }
+
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 4, 16] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            let unbounded = delta_with(a, b, &opts);
+            let match_count = stats(&unbounded).match_count;
+            for max_matches in [match_count, match_count + 1, 2 * match_count] {
+                let opts = opts.clone().max_matches(Some(max_matches));
+                assert_eq!(delta_with(a, b, &opts), unbounded, "n {n}");
+            }
+        }
+    }
+
+    #[test]
+    fn max_matches_raw_tail() {
+        use Compression::*;
+        let a: Vec<u8> = (0..100).collect();
+        let mut b = a[..20].to_vec();
+        b.push(200);
+        b.extend_from_slice(&a[50..80]);
+        let opts = DeltaOptions::default().min_match_len(8);
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().max_matches(Some(1))),
+            vec![Match(0, 20), Raw(&b[20..])]
+        );
+        assert_eq!(
+            delta_with(&a, &b, &opts.max_matches(Some(2))),
+            vec![Match(0, 20), Raw(&[200][..]), Match(50, 30)]
+        );
+    }
+
+    #[test]
+    fn extract_match_first_occurrence() {
+        // [1, 2] is sampled at both 0 and 6, and only the first extends to the full match.
+        let a = [1, 2, 3, 4, 5, 9, 1, 2, 9, 9];
+        let b = [1, 2, 3, 4, 5];
+        let result = find_match_intervals(&a, &b, 3);
+        assert_eq!(result, vec![make_match_interval(0, 0, 5)]);
+    }
+
+    #[test]
+    fn extract_match_longest_later_occurrence() {
+        let a = [1, 2, 9, 9, 9, 9, 1, 2, 3, 4];
+        let b = [1, 2, 3, 4];
+        let result = find_match_intervals(&a, &b, 3);
+        assert_eq!(result, vec![make_match_interval(6, 0, 4)]);
+    }
+
+    #[test]
+    fn extract_match_hash_collision() {
+        // [0, 0, 0, 0, 0] and [10, 0, 0, 0, 7] share a hash since 10 * B^4 + 7 == M.
+        let a = [5, 5, 5, 5, 5, 10, 0, 0, 0, 7];
+        let b = [5, 5, 0, 0, 0, 0, 0];
+        let ha = RollingHash::new(&a[5..], 5).next().unwrap();
+        let hb = RollingHash::new(&b[2..], 5).next().unwrap();
+        assert_eq!(ha.0, hb.0);
+
+        let result = find_match_intervals(&a, &b, 9);
+        assert_eq!(result, vec![]);
+    }
+
+    #[test]
+    fn delta_123_567() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let result = delta(&a, &b, 3);
+        assert_eq!(result, vec![Match(5, 3), Raw(&[9, 9]), Match(1, 3)]);
+    }
+
+    #[test]
+    fn delta_with_default_options() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let opts = DeltaOptions::default().min_match_len(3);
+        assert_eq!(delta_with(&a, &b, &opts), delta(&a, &b, 3));
+    }
+
+    #[test]
+    fn delta_with_step_factor() {
+        use Compression::*;
+        // hash_len is 2, so a is sampled at 0, 2, 4, 6 by default and at 0, 4 with a factor of 2.
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [9, 2, 3, 4, 9];
+        let opts = DeltaOptions::default().min_match_len(3);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[9]), Match(2, 3), Raw(&[9])]
+        );
+        let opts = opts.step_factor(2);
+        assert_eq!(delta_with(&a, &b, &opts), vec![Raw(&b)]);
+    }
+
+    #[test]
+    fn delta_drops_matches_shorter_than_min_match_len() {
+        use Compression::*;
+        // The seed [2, 3] is found, but the match does not extend to 3.
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [9, 2, 3, 9, 5, 6, 7];
+        assert_eq!(
+            find_match_intervals(&a, &b, 3),
+            vec![make_match_interval(2, 1, 2), make_match_interval(5, 4, 3)]
+        );
+        let expected = vec![Raw(&b[..4]), Match(5, 3)];
+        assert_eq!(delta(&a, &b, 3), expected);
+        let opts = DeltaOptions::default().min_match_len(3);
+        assert_eq!(delta_with(&a, &b, &opts), expected);
+        assert_eq!(
+            matches(&a, &b, 3),
+            vec![crate::Match::from(make_match_interval(5, 4, 3))]
+        );
+        assert_eq!(similarity(&a, &b, 3), 3.0 / 7.0);
+    }
+
+    #[test]
+    fn delta_with_sample_step() {
+        use Compression::*;
+        let a: Vec<u8> = (0..20).collect();
+        let b = [99, 4, 5, 6, 7, 8, 9, 99];
+        // Windows of length 3 are sampled at 0, 3, 6, ... by default, and at 0, 9, 18
+        // with a step of 9, none of them inside 4..10.
+        let opts = DeltaOptions::default().min_match_len(6);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[99]), Match(4, 6), Raw(&[99])]
+        );
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().sample_step(9)),
+            vec![Raw(&b[..])]
+        );
+        let opts = opts.sample_step(1);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[99]), Match(4, 6), Raw(&[99])]
+        );
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().sample_step(0)),
+            delta_with(&a, &b, &opts)
+        );
+    }
+
+    #[test]
+    fn delta_with_seed_phases() {
+        use Compression::*;
+        let a: Vec<u8> = (0..20).collect();
+        let b = [99, 4, 5, 6, 7, 8, 9, 99];
+        // With a step of 9, two phases sample at 0, 4, 9, 13, 18 and three at 0, 3, 6,
+        // 9, 12, 15, 18.
+        let opts = DeltaOptions::default().min_match_len(6).sample_step(9);
+        assert_eq!(delta_with(&a, &b, &opts), vec![Raw(&b[..])]);
+        for phases in [2, 3] {
+            assert_eq!(
+                delta_with(&a, &b, &opts.clone().seed_phases(phases)),
+                vec![Raw(&[99]), Match(4, 6), Raw(&[99])]
+            );
+        }
+        let seeds: Vec<usize> = (0..20)
+            .filter(|&i| opts.clone().seed_phases(2).is_seed(i))
+            .collect();
+        assert_eq!(seeds, [0, 4, 9, 13, 18]);
+    }
+
+    #[test]
+    fn delta_source_window_bounds_matches() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for source_window in [0, 1, 16, 100, 1000, usize::MAX] {
+            for strategy in [
+                MatchStrategy::Greedy,
+                MatchStrategy::Longest,
+                MatchStrategy::MinimalOps,
+            ] {
+                for self_reference in [false, true] {
+                    let opts = DeltaOptions::default()
+                        .min_match_len(4)
+                        .strategy(strategy)
+                        .allow_self_reference(self_reference)
+                        .source_window(Some(source_window));
+                    let d = delta_with(a, b, &opts);
+                    assert_eq!(patch(a, &d), b);
+                    let window = core::cmp::max(1, source_window);
+                    let mut lb = 0;
+                    for c in &d {
+                        if let Compression::Match(la, _) = *c {
+                            assert!(la >= a.len() || (la <= lb && lb - la < window));
+                        }
+                        lb += c.output_len();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn delta_source_window_small() {
+        use Compression::*;
+        let a: Vec<u8> = (0..20).collect();
+        let b: Vec<u8> = [99; 8].iter().chain(&a[..8]).copied().collect();
+        let opts = DeltaOptions::default().min_match_len(4);
+        let expected = vec![Raw(&b[..8]), Match(0, 8)];
+        assert_eq!(delta_with(&a, &b, &opts), expected);
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().source_window(Some(9))),
+            expected
+        );
+        assert_eq!(
+            delta_with(&a, &b, &opts.clone().source_window(Some(8))),
+            vec![Raw(&b[..])]
+        );
+        // `a[8..16]` lies ahead of where it would be copied to.
+        let opts = opts.source_window(Some(100));
+        assert_eq!(delta_with(&a, &a[8..16], &opts), vec![Raw(&a[8..16])]);
+        assert_eq!(delta_with(&a, &a, &opts), vec![Match(0, 20)]);
+    }
+
+    #[test]
+    fn hash_len_policy_adaptive() {
+        let policy = HashLenPolicy::Adaptive {
+            floor: 10,
+            divisor: 1000,
+        };
+        for len in [0, 999, 10_000, 10_999, 11_000, 1 << 20] {
+            assert_eq!(policy.min_match_len(len), core::cmp::max(10, len / 1000));
+        }
+        assert_eq!(HashLenPolicy::Fixed(7).min_match_len(1 << 20), 7);
+        let unbounded = HashLenPolicy::Adaptive {
+            floor: 1,
+            divisor: 0,
+        };
+        assert_eq!(unbounded.min_match_len(5), 5);
+    }
+
+    #[test]
+    fn delta_with_hash_len_policy() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let policy = HashLenPolicy::Adaptive {
+            floor: 4,
+            divisor: 64,
+        };
+        let opts = DeltaOptions::default().hash_len_policy(Some(policy));
+        let fixed = DeltaOptions::default().min_match_len(b.len() / 64);
+        assert_eq!(delta_with(a, b, &opts), delta_with(a, b, &fixed));
+        assert_eq!(delta_summary(a, b, &opts), delta_summary(a, b, &fixed));
+        let opts = opts.hash_len_policy(Some(HashLenPolicy::Fixed(12)));
+        assert_eq!(
+            delta_with(a, b, &opts),
+            delta_with(a, b, &DeltaOptions::default().min_match_len(12))
+        );
+        // The index has no `b` and goes by the length of `a`.
+        let index = SourceIndex::build(a, &opts.hash_len_policy(Some(policy)));
+        assert_eq!(index.options().min_match_len, a.len() / 64);
+        assert_eq!(index.options().hash_len_policy, None);
+    }
+
+    #[test]
+    fn delta_max_displacement_bounds_matches() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for max_displacement in [0, 10, 100, 1000] {
+            for strategy in [MatchStrategy::Greedy, MatchStrategy::Longest] {
+                let opts = DeltaOptions::default()
+                    .min_match_len(4)
+                    .strategy(strategy)
+                    .max_displacement(Some(max_displacement));
+                let d = delta_with(a, b, &opts);
+                assert_eq!(patch(a, &d), b);
+                let mut lb = 0;
+                for c in &d {
+                    if let Compression::Match(la, _) = *c {
+                        assert!(la.abs_diff(lb) <= max_displacement);
+                    }
+                    lb += c.output_len();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn delta_max_displacement_prefers_local_matches() {
+        use Compression::*;
+        let a: Vec<u8> = b"abcdefgh"
+            .iter()
+            .chain(&[0; 20])
+            .chain(b"abcdefghijkl")
+            .copied()
+            .collect();
+        let b = b"abcdefghijkl";
+        let opts = DeltaOptions::default().min_match_len(4);
+        assert_eq!(delta_with(&a, b, &opts), vec![Match(28, 12)]);
+        let opts = opts.max_displacement(Some(10));
+        assert_eq!(delta_with(&a, b, &opts), vec![Match(0, 8), Raw(b"ijkl")]);
+        let index = SourceIndex::build(&a, &opts);
+        assert_eq!(
+            delta_indexed(&index, &a, b),
+            vec![Match(0, 8), Raw(b"ijkl")]
+        );
+    }
+
+    #[test]
+    fn sliding_table_holds_reachable_windows() {
+        let a: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let opts = DeltaOptions::default().min_match_len(1);
+        let mut sliding = SlidingTable::new(&a, 50, &opts, PolynomialHasher::default());
+        for ib in 0..1000 {
+            sliding.advance(ib, &opts);
+            let positions: Vec<usize> = sliding
+                .table
+                .positions
+                .values()
+                .flatten()
+                .copied()
+                .collect();
+            assert!(positions.contains(&ib));
+            assert!(positions.iter().all(|&i| i <= ib && i + 100 > ib), "{ib}");
+        }
+    }
+
+    #[test]
+    fn seed_phases_same_as_smaller_sample_step() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        // hash_len and the step are 8.
+        let opts = DeltaOptions::default().min_match_len(16);
+        for (phases, step) in [(0, 8), (1, 8), (2, 4), (4, 2), (8, 1), (100, 1)] {
+            let phased = opts.clone().seed_phases(phases);
+            assert_eq!(
+                delta_with(a, b, &phased),
+                delta_with(a, b, &opts.clone().sample_step(step)),
+                "{phases} phases"
+            );
+            let d = delta_with(a, b, &phased.allow_self_reference(true));
+            assert_eq!(restore(a, &d).concat(), b);
+        }
+    }
+
+    #[test]
+    fn delta_with_hash_params() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let params = HashParams::new(257, 65521);
+        let opts = DeltaOptions::default().min_match_len(3).hash_params(params);
+        assert_eq!(delta_with(&a, &b, &opts), delta(&a, &b, 3));
+    }
+
+    #[test]
+    fn delta_u32_tokens() {
+        use Compression::*;
+        let a: [u32; 8] = [7, u32::MAX, 1 << 31, 3, 4, 5, 6, 7];
+        let b: [u32; 7] = [9, 7, u32::MAX, 1 << 31, 3, 9, 9];
+        let result = delta::<u32>(&a, &b, 3);
+        assert_eq!(result, vec![Raw(&[9]), Match(0, 4), Raw(&[9, 9])]);
+        assert_eq!(patch(&a, &result), b);
+    }
+
+    #[test]
+    fn delta_chars() {
+        let a: Vec<char> = "phantom".chars().collect();
+        let b: Vec<char> = "elephant".chars().collect();
+        let result = delta(&a, &b, 5);
+        assert_eq!(
+            result,
+            vec![Compression::Raw(&b[..3]), Compression::Match(0, 5)]
+        );
+    }
+
+    #[test]
+    fn delta_identical_inputs() {
+        use Compression::*;
+        let a = include_bytes!("../examples/a.txt");
+        for n in [1, 10, 32] {
+            assert_eq!(delta(a, a, n), vec![Match(0, a.len())]);
+            assert_eq!(delta_iter(a, a, n).collect::<Vec<_>>(), delta(a, a, n));
+            let opts = DeltaOptions::default().min_match_len(n);
+            assert_eq!(delta_with(a, a, &opts), vec![Match(0, a.len())]);
+        }
+        let opts = DeltaOptions::default().max_match_len(1000);
+        let d = delta_with(a, a, &opts);
+        assert_eq!(d.len(), a.len().div_ceil(1000));
+        assert_eq!(restore(a, &d).concat(), a);
+        // Too short for a match, or nothing to match.
+        assert_eq!(delta(b"abc", b"abc", 4), vec![Raw(b"abc")]);
+        assert_eq!(delta(b"", b"", 4), vec![Raw(b"")]);
+        let opts = DeltaOptions::default().max_matches(Some(0));
+        assert_eq!(delta_with(a, a, &opts), vec![Raw(&a[..])]);
+    }
+
+    #[test]
+    fn delta_repetitive_inputs() {
+        let same = vec![7u8; 1 << 16];
+        assert_eq!(
+            delta(&same, &same, 16),
+            vec![Compression::Match(0, same.len())]
+        );
+        let runs: Vec<u8> = (0..same.len())
+            .map(|i| if i % 64 == 63 { 8 } else { 7 })
+            .collect();
+        let d: Delta = delta(&same, &runs, 16).into();
+        assert_eq!(restore(&same, &d).concat(), runs);
+        assert_eq!(d.stats().matched_bytes, runs.len() / 64 * 63);
+    }
+
+    #[test]
+    fn delta_extends_match_into_raw_before_it() {
+        use Compression::*;
+        // [3, 4, 5, 6, 7] overlaps the first match, and trimming it against that match
+        // cut it to [6, 7], too short to keep. Extended back, it covers [5, 6, 7].
+        let a = [0, 1, 2, 3, 4, 9, 3, 4, 5, 6, 7];
+        let b = [0, 1, 2, 3, 4, 5, 6, 7];
+        let expected = vec![Match(0, 5), Match(8, 3)];
+        assert_eq!(delta(&a, &b, 3), expected);
+        let opts = DeltaOptions::default().min_match_len(3);
+        assert_eq!(delta_with(&a, &b, &opts), expected);
+        assert_eq!(similarity(&a, &b, 3), 1.0);
+    }
+
+    #[test]
+    fn delta_longest_beats_greedy() {
+        use Compression::*;
+        // Greedy takes [0, 1, 2, 3, 4] first, which cuts into the longer [3, 4, 5, 6, 7, 8].
+        let a = [0, 1, 2, 3, 4, 9, 9, 3, 4, 5, 6, 7, 8];
+        let b = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+        let greedy = DeltaOptions::default().min_match_len(3);
+        let longest = greedy.clone().strategy(MatchStrategy::Longest);
+        assert_eq!(delta_with(&a, &b, &greedy), vec![Match(0, 5), Match(9, 4)]);
+        assert_eq!(delta_with(&a, &b, &longest), vec![Match(0, 3), Match(7, 6)]);
+    }
+
+    #[test]
+    fn delta_longest_restores() {
+        let a = b"the quick brown fox jumps over the lazy dog, the quick red fox";
+        let b = b"a quick red fox jumps over the quick brown dog and the lazy fox";
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .strategy(MatchStrategy::Longest);
+        assert_eq!(patch(a, &delta_with(a, b, &opts)), b);
+    }
+
+    #[test]
+    fn delta_minimal_ops_never_more_than_greedy() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [1, 2, 4, 8, 16, 32] {
+            for self_reference in [false, true] {
+                let greedy = DeltaOptions::default()
+                    .min_match_len(n)
+                    .allow_self_reference(self_reference);
+                let minimal = greedy.clone().strategy(MatchStrategy::MinimalOps);
+                let greedy = stats(&delta_with(a, b, &greedy));
+                let d = delta_with(a, b, &minimal);
+                assert_eq!(patch(a, &d), b);
+                let minimal = stats(&d);
+                assert!(
+                    minimal.match_count + minimal.raw_count
+                        <= greedy.match_count + greedy.raw_count,
+                    "{n}: {minimal:?} {greedy:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delta_minimal_ops_never_more_than_greedy_after_shaping() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let ops = |d: &[Compression]| {
+            let stats = stats(d);
+            stats.match_count + stats.raw_count
+        };
+        let lowercase: Option<fn(u8) -> u8> = Some(|x| x.to_ascii_lowercase());
+        for n in [2, 4, 8] {
+            for match_cost in [MatchCost::Free, MatchCost::Encoded, MatchCost::Fixed(12)] {
+                for block_align in [None, Some(4)] {
+                    for max_match_len in [usize::MAX, 7] {
+                        for normalize in [None, lowercase] {
+                            let greedy = DeltaOptions::default()
+                                .min_match_len(n)
+                                .match_cost(match_cost)
+                                .block_align(block_align)
+                                .max_match_len(max_match_len)
+                                .normalize(normalize);
+                            let minimal = greedy.clone().strategy(MatchStrategy::MinimalOps);
+                            let d = delta_with(a, b, &minimal);
+                            assert_eq!(patch(a, &d), b);
+                            assert!(
+                                ops(&d) <= ops(&delta_with(a, b, &greedy)),
+                                "{n} {match_cost:?} {block_align:?} {max_match_len}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        // Matches of the normalized inputs are cut into more runs than greedy's.
+        let greedy = DeltaOptions::default()
+            .min_match_len(0)
+            .normalize(Some(|x| x & !1));
+        let minimal = greedy.clone().strategy(MatchStrategy::MinimalOps);
+        let (a, b) = (b"cbaa", b"bcb");
+        assert!(ops(&delta_with(a, b, &minimal)) <= ops(&delta_with(a, b, &greedy)));
+        for seed in 1..=300 {
+            let a: Vec<u8> = xorshift_bytes(12, seed)
+                .iter()
+                .map(|x| b'a' + x % 4)
+                .collect();
+            let b: Vec<u8> = xorshift_bytes(8, seed + 1000)
+                .iter()
+                .map(|x| b'a' + x % 4)
+                .collect();
+            for n in [0, 1, 2, 3] {
+                let greedy = greedy.clone().min_match_len(n);
+                let minimal = minimal.clone().min_match_len(n);
+                let d = delta_with(&a, &b, &minimal);
+                assert_eq!(patch(&a, &d), b);
+                assert!(
+                    ops(&d) <= ops(&delta_with(&a, &b, &greedy)),
+                    "{a:?} {b:?} {n}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn delta_minimal_ops_restores() {
+        let a = b"the quick brown fox jumps over the lazy dog, the quick red fox";
+        let b = b"a quick red fox jumps over the quick brown dog and the lazy fox";
+        for n in [1, 3, 4, 8] {
+            let opts = DeltaOptions::default()
+                .min_match_len(n)
+                .strategy(MatchStrategy::MinimalOps);
+            assert_eq!(patch(a, &delta_with(a, b, &opts)), b);
+        }
+        let opts = DeltaOptions::default().strategy(MatchStrategy::MinimalOps);
+        assert!(patch(a, &delta_with(a, b"", &opts)).is_empty());
+        assert_eq!(patch(b"", &delta_with(b"", b, &opts)), b);
+    }
+
+    #[test]
+    fn delta_max_match_len() {
+        use Compression::*;
+        let a: Vec<u8> = (0..20).collect();
+        let b: Vec<u8> = (2..13).collect();
+        let opts = DeltaOptions::default().min_match_len(3).max_match_len(4);
+        let result = delta_with(&a, &b, &opts);
+        assert_eq!(result, vec![Match(2, 4), Match(6, 4), Match(10, 3)]);
+        assert_eq!(patch(&a, &result), b);
+    }
+
+    #[test]
+    fn delta_max_match_len_unbounded() {
+        let a: Vec<u8> = (0..20).collect();
+        let b: Vec<u8> = (2..13).collect();
+        let expected = vec![Compression::Match(2, 11)];
+        for max_match_len in [0, usize::MAX] {
+            let opts = DeltaOptions::default()
+                .min_match_len(3)
+                .max_match_len(max_match_len);
+            assert_eq!(delta_with(&a, &b, &opts), expected);
+        }
+    }
+
+    #[test]
+    fn delta_match_cost_drops_short_matches() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [9, 5, 6, 9, 1, 2, 3, 4, 9];
+        let opts = DeltaOptions::default().min_match_len(2);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[9]), Match(5, 2), Raw(&[9]), Match(1, 4), Raw(&[9])]
+        );
+        // `Match(5, 2)` takes 3 bytes to encode, so it is not worth it; the raw runs
+        // around it become one.
+        let opts = opts.match_cost(MatchCost::Encoded);
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[9, 5, 6, 9]), Match(1, 4), Raw(&[9])]
+        );
+        assert_eq!(patch(&a, &delta_with(&a, &b, &opts)), b);
+    }
+
+    #[test]
+    fn delta_match_cost_fixed() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 9, 1, 2, 3, 4];
+        let opts = DeltaOptions::default()
+            .min_match_len(2)
+            .match_cost(MatchCost::Fixed(4));
+        assert_eq!(delta_with(&a, &b, &opts), vec![Raw(&b[..])]);
+        let opts = opts.match_cost(MatchCost::Fixed(3));
+        assert_eq!(
+            delta_with(&a, &b, &opts),
+            vec![Raw(&[5, 6, 9]), Match(1, 4)]
+        );
+    }
+
+    #[test]
+    fn delta_self_reference() {
+        use Compression::*;
+        let b = b"abcdefgh-abcdefgh-abcdefgh";
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .allow_self_reference(true);
+        let result = delta_with(&[], b, &opts);
+        assert_eq!(result, vec![Raw(&b[..9]), Match(0, 17)]);
+        assert_eq!(patch(&[], &result), b);
+        assert_eq!(restore(&[], &result).concat(), b);
+    }
+
+    #[test]
+    fn delta_self_reference_with_a() {
+        let a = b"0123456789";
+        let b = b"xyz-0123456789-xyzxyzxyzxyz-0123";
+        let opts = DeltaOptions::default()
+            .min_match_len(3)
+            .allow_self_reference(true);
+        let result = delta_with(a, b, &opts);
+        assert!(result
+            .iter()
+            .any(|c| matches!(c, Compression::Match(la, _) if *la >= a.len())));
+        assert_eq!(patch(a, &result), b);
+        assert_eq!(restore(a, &result).concat(), b);
+        assert_eq!(try_restore(a, &result).unwrap(), b);
+        let opts = opts.allow_self_reference(false);
+        let back_references = delta_with(a, b, &opts)
+            .into_iter()
+            .filter(|c| matches!(c, Compression::Match(la, _) if *la >= a.len()))
+            .count();
+        assert_eq!(back_references, 0);
+    }
+
+    #[test]
+    fn patch_overlapping_back_reference() {
+        use Compression::*;
+        let delta = [Raw(&[1, 2]), Match(1, 5), Match(0, 1)];
+        let expected = vec![1, 2, 1, 2, 1, 2, 1, 9];
+        assert_eq!(patch(&[9], &delta), expected);
+        assert_eq!(restore(&[9], &delta).concat(), expected);
+    }
+
+    #[test]
+    fn try_restore_back_reference_out_of_bounds() {
+        use Compression::*;
+        let delta = [Raw(&[1, 2]), Match(3, 1)];
+        assert_eq!(
+            try_restore(&[9], &delta),
+            Err(RestoreError::MatchOutOfBounds {
+                index: 1,
+                la: 3,
+                len: 1,
+                a_len: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn delta_no_match() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5];
+        let b = [9, 9, 9, 9, 9, 9];
+        let result = delta(&a, &b, 3);
+        assert_eq!(result, vec![Raw(&b[..])]);
+    }
+
+    #[test]
+    fn delta_ends_with_raw() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5];
+        let b = [9, 9, 9, 3, 4, 5, 9];
+        let result = delta(&a, &b, 3);
+        assert_eq!(result, vec![Raw(&[9, 9, 9]), Match(3, 3), Raw(&[9])]);
+    }
+
+    #[test]
+    fn delta_min_match_len_zero() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        // Used to panic in `step_by(0)`.
+        let d = delta(&a, &b, 0);
+        assert_eq!(d, delta(&a, &b, 1));
+        assert_eq!(patch(&a, &d), b);
+        assert_eq!(delta64(&a, &b, 0), delta64(&a, &b, 1));
+        let opts = DeltaOptions::default().min_match_len(0);
+        assert_eq!(delta_with(&a, &b, &opts), d);
+    }
+
+    #[test]
+    fn delta_empty_a() {
+        use Compression::*;
+        let b = [1, 2, 3, 4, 5, 6, 7, 8];
+        for n in [1, 2, 3, 10] {
+            assert_eq!(delta(&[], &b, n), vec![Raw(&b[..])]);
+            assert_eq!(delta64(&[], &b, n), vec![Raw(&b[..])]);
+            let opts = DeltaOptions::default()
+                .min_match_len(n)
+                .strategy(MatchStrategy::Longest);
+            assert_eq!(delta_with(&[], &b, &opts), vec![Raw(&b[..])]);
+        }
+        assert_eq!(patch(&[], &delta(&[], &b, 3)), b);
+        assert_eq!(restore(&[], &delta(&[], &b, 3)), vec![&b[..]]);
+    }
+
+    #[test]
+    fn delta_empty_a_self_reference() {
+        use Compression::*;
+        let b = [1, 2, 3, 4, 1, 2, 3, 4];
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .allow_self_reference(true);
+        let d = delta_with(&[], &b, &opts);
+        assert_eq!(d, vec![Raw(&b[..4]), Match(0, 4)]);
+        assert_eq!(patch(&[], &d), b);
+    }
+
+    #[test]
+    fn delta_empty_b() {
+        use Compression::*;
+        let a = [1, 2, 3, 4, 5, 6, 7, 8];
+        let empty: &[u8] = &[];
+        assert_eq!(delta(&a, empty, 3), vec![Raw(empty)]);
+        assert_eq!(delta64(&a, empty, 3), vec![Raw(empty)]);
+        assert!(patch(&a, &delta(&a, empty, 3)).is_empty());
+    }
+
+    #[test]
+    fn delta_both_empty() {
+        use Compression::*;
+        let empty: &[u8] = &[];
+        let d = delta(empty, empty, 3);
+        assert_eq!(d, vec![Raw(empty)]);
+        assert!(patch(empty, &d).is_empty());
+        assert_eq!(try_restore(empty, &d), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn try_restore_empty_a_out_of_bounds() {
+        use Compression::*;
+        let empty: &[u8] = &[];
+        // With empty `a` every match refers back into the output.
+        assert_eq!(
+            try_restore(empty, &[Raw(&[1, 2][..]), Match(0, 3)]),
+            Ok(vec![1, 2, 1, 2, 1])
+        );
+        assert!(try_restore(empty, &[Raw(&[1, 2][..]), Match(2, 3)]).is_err());
+    }
+
+    #[test]
+    fn matches_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        assert_eq!(
+            matches(&a, &b, 3),
+            vec![
+                Match {
+                    source_offset: 5,
+                    target_offset: 0,
+                    len: 3
+                },
+                Match {
+                    source_offset: 1,
+                    target_offset: 5,
+                    len: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn longest_common_substring_abcdefg() {
+        let a = b"xabcdefgy-lmnop";
+        let b = b"zzlmnopqq abcdefg";
+        let expected = Match {
+            source_offset: 1,
+            target_offset: 10,
+            len: 7,
+        };
+        assert_eq!(longest_common_substring(a, b, 3), Some(expected));
+        assert_eq!(longest_common_substring(a, b, 7), Some(expected));
+        assert_eq!(longest_common_substring(a, b, 8), None);
+    }
+
+    #[test]
+    fn longest_common_substring_none() {
+        assert_eq!(longest_common_substring(b"abcd", b"wxyz", 1), None);
+        assert_eq!(longest_common_substring(b"", b"abcd", 1), None);
+        assert_eq!(longest_common_substring(b"abcd", b"", 1), None);
+    }
+
+    #[test]
+    fn longest_common_substring_same_as_brute_force() {
+        for seed in 1..=50 {
+            let a: Vec<u8> = xorshift_bytes(60, seed).iter().map(|x| x % 3).collect();
+            let b: Vec<u8> = xorshift_bytes(40, seed + 100)
+                .iter()
+                .map(|x| x % 3)
+                .collect();
+            // lengths[j] is the length of the common suffix of a[..i] and b[..j].
+            let mut lengths = vec![0; b.len() + 1];
+            let mut longest = 0;
+            for i in 1..=a.len() {
+                for j in (1..=b.len()).rev() {
+                    lengths[j] = if a[i - 1] == b[j - 1] {
+                        lengths[j - 1] + 1
+                    } else {
+                        0
+                    };
+                    longest = longest.max(lengths[j]);
+                }
+            }
+            let m = longest_common_substring(&a, &b, 4).unwrap();
+            assert_eq!(m.len, longest);
+            assert_eq!(
+                a[m.source_offset..m.source_offset + m.len],
+                b[m.target_offset..m.target_offset + m.len]
+            );
+        }
+    }
+
+    #[test]
+    fn matches_agree_with_delta() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let found = matches(a, b, 10);
+        assert!(found
+            .windows(2)
+            .all(|w| w[0].target_offset + w[0].len <= w[1].target_offset));
+        let copies: Vec<_> = delta(a, b, 10)
+            .into_iter()
+            .filter_map(|c| match c {
+                Compression::Match(la, len) => Some((la, len)),
+                Compression::Raw(_) => None,
+            })
+            .collect();
+        let expected: Vec<_> = found.iter().map(|m| (m.source_offset, m.len)).collect();
+        assert_eq!(copies, expected);
+        for m in found {
+            assert_eq!(a[m.source_offset..][..m.len], b[m.target_offset..][..m.len]);
+        }
+    }
+
+    #[test]
+    fn delta_iter_same_as_delta_with() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [1, 2, 5, 10, 32] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            let lazy: Vec<_> = delta_iter(a, b, n).collect();
+            assert_eq!(lazy, delta_with(a, b, &opts));
+            let reversed: Vec<_> = delta_iter(b, a, n).collect();
+            assert_eq!(reversed, delta_with(b, a, &opts));
+        }
+    }
+
+    #[test]
+    fn delta_iter_yields_raw_before_match() {
+        use Compression::*;
+        let a = [0, 1, 2, 3, 4, 5];
+        let b = [9, 9, 9, 3, 4, 5, 9];
+        let mut it = delta_iter(&a, &b, 3);
+        assert_eq!(it.next(), Some(Raw(&[9, 9, 9][..])));
+        assert_eq!(it.next(), Some(Match(3, 3)));
+        assert_eq!(it.next(), Some(Raw(&[9][..])));
+        assert_eq!(it.next(), None);
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn owned_roundtrip_same_as_borrowed() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [1, 4, 10, 32] {
+            let borrowed = delta(a, b, n);
+            let owned = delta_owned(a, b, n);
+            let converted: Vec<_> = borrowed.iter().map(Compression::to_owned).collect();
+            assert_eq!(owned, converted);
+            assert_eq!(restore_owned(a, &owned), restore(a, &borrowed));
+            assert_eq!(patch_owned(a, &owned), b);
+        }
+    }
+
+    #[test]
+    fn owned_delta_outlives_b() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let owned = {
+            let b = vec![5, 6, 7, 9, 9, 1, 2, 3];
+            delta_owned(&a, &b, 3)
+        };
+        assert_eq!(patch_owned(&a, &owned), [5, 6, 7, 9, 9, 1, 2, 3]);
+        let back_reference = [
+            CompressionOwned::Raw(vec![1, 2]),
+            CompressionOwned::Match(8, 3),
+        ];
+        assert_eq!(patch_owned(&a, &back_reference), [1, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn normalize_merges_raws_and_matches() {
+        use CompressionOwned::*;
+        let a: Vec<u8> = (0..8).collect();
+        let d = vec![
+            Raw(vec![9]),
+            Match(3, 0),
+            Raw(vec![]),
+            Raw(vec![9, 9]),
+            Match(0, 2),
+            Match(2, 3),
+            Match(6, 2),
+            // Continues the match before it, but copies from the output.
+            Match(8, 2),
+            Match(10, 1),
+        ];
+        let normalized = normalize(a.len(), d.clone());
+        assert_eq!(
+            normalized,
+            vec![Raw(vec![9, 9, 9]), Match(0, 5), Match(6, 2), Match(8, 3)]
+        );
+        assert_eq!(patch_owned(&a, &normalized), patch_owned(&a, &d));
+        assert_eq!(normalize(a.len(), normalized.clone()), normalized);
+        assert!(normalize::<u8>(0, vec![Raw(vec![]), Match(0, 0)]).is_empty());
+    }
+
+    #[test]
+    fn normalize_split_delta() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let opts = DeltaOptions::default().min_match_len(8).max_match_len(8);
+        let d: Vec<CompressionOwned> = delta_with(a, b, &opts)
+            .iter()
+            .map(Compression::to_owned)
+            .collect();
+        let normalized = normalize(a.len(), d.clone());
+        assert!(normalized.len() < d.len());
+        assert_eq!(patch_owned(a, &normalized), b);
+        assert_eq!(normalize(a.len(), normalized.clone()), normalized);
+        let merged: Vec<CompressionOwned> = delta_with(a, b, &opts.max_match_len(0))
+            .iter()
+            .map(Compression::to_owned)
+            .collect();
+        assert_eq!(normalized, merged);
+    }
+
+    #[test]
+    fn delta_does_not_borrow_a() {
+        use Compression::*;
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        // `a` is dropped before the deltas, which only borrow raw runs from `b`.
+        let (d, d64, with) = {
+            let a = vec![0, 1, 2, 3, 4, 5, 6, 7];
+            let opts = DeltaOptions::default().min_match_len(3);
+            (
+                delta(&a, &b, 3),
+                delta64(&a, &b, 3),
+                delta_with(&a, &b, &opts),
+            )
+        };
+        let expected = [Match(5, 3), Raw(&[9, 9][..]), Match(1, 3)];
+        assert_eq!(d, expected);
+        assert_eq!(d64, expected);
+        assert_eq!(with, expected);
     }
 
-    let mut results = Vec::with_capacity(match_intervals.len());
-    let mut prev = 0;
-    for MatchInterval { la, lb, len } in match_intervals {
-        if prev < lb {
-            results.push(Raw(&b[prev..lb]));
-        }
-        results.push(Match(la, len));
-        prev = lb + len;
+    #[test]
+    fn restore_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let delta = delta(&a, &b, 3);
+        let result = restore(&a, &delta);
+        assert_eq!(result, vec![&b[0..3], &b[3..5], &b[5..]]);
     }
-    if prev != b.len() {
-        results.push(Raw(&b[prev..]));
+
+    #[test]
+    fn patch_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let delta = delta(&a, &b, 3);
+        let result = patch(&a, &delta);
+        assert_eq!(result, b);
+        assert_eq!(result.capacity(), b.len());
     }
-    results
-}
 
-pub fn restore<'a>(a: &'a [u8], compressions: &[Compression<'a>]) -> Vec<&'a [u8]> {
-    let mut results = Vec::new();
-    for c in compressions {
-        match c {
-            Compression::Match(la, len) => {
-                results.push(&a[*la..*la + *len]);
-            }
-            Compression::Raw(data) => {
-                results.push(*data);
+    #[test]
+    fn reconstruct_len_same_as_restored() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let all = [
+            DeltaOptions::default(),
+            DeltaOptions::default()
+                .min_match_len(4)
+                .allow_self_reference(true),
+            DeltaOptions::default().max_match_len(8),
+            DeltaOptions::default().resync_window(Some(100)),
+            DeltaOptions::default().partition_for_parallel(Some(3)),
+        ];
+        for opts in all {
+            for (a, b) in [(&a[..], &b[..]), (b, a), (a, b""), (b"", b)] {
+                let d = delta_with(a, b, &opts);
+                assert_eq!(reconstruct_len(&d), Some(patch(a, &d).len()));
+                assert_eq!(reconstruct_len(&d), Some(b.len()));
             }
         }
+        assert_eq!(reconstruct_len::<u8>(&[]), Some(0));
+        let overflowing = [
+            Compression::Raw(&b"x"[..]),
+            Compression::Match(1, usize::MAX),
+        ];
+        assert_eq!(reconstruct_len(&overflowing), None);
     }
-    results.into_iter().collect()
-}
-
-fn find_match_intervals(a: &[u8], b: &[u8], min_match_len: usize) -> Vec<MatchInterval> {
-    let hash_len = (min_match_len + 1) / 2;
-    let hashes: HashMap<usize, usize> = RollingHash::new(a, hash_len).step_by(hash_len).collect();
 
-    let matches = RollingHash::new(b, hash_len)
-        .scan(0, |state, (hb, ib)| {
-            if ib < *state {
-                return Some(MatchInterval::empty());
-            }
-            if let Some(&ia) = hashes.get(&hb) {
-                let m = MatchInterval::new(a, b, ia, ib);
-                *state = m.br();
-                Some(m)
-            } else {
-                Some(MatchInterval::empty())
-            }
-        })
-        .scan(MatchInterval::empty(), |acc, mut m| {
-            m.remove_overlap(acc);
-            if m.len > 0 {
-                *acc = m;
-            }
-            Some(m)
-        })
-        .filter(|m| m.len > 0);
-
-    matches.collect()
-}
-
-pub struct RollingHash<'a> {
-    data: &'a [u8],
-    hash_len: usize,
-    index: usize,
-    hash: Option<usize>,
-    base_pow: usize,
-}
+    #[test]
+    fn restore_into_reuses_buffer() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let delta = delta(&a, &b, 3);
+        let mut out = Vec::new();
+        restore_into(&a, &delta, &mut out);
+        assert_eq!(out, b);
 
-impl<'a> RollingHash<'a> {
-    pub fn new(data: &'a [u8], hash_len: usize) -> Self {
-        let hash_len = std::cmp::min(data.len(), hash_len);
-        let base_pow = modpow(B, hash_len);
-        Self {
-            data,
-            hash_len,
-            index: 0,
-            hash: None,
-            base_pow,
-        }
+        let (ptr, capacity) = (out.as_ptr(), out.capacity());
+        restore_into(&a, &delta, &mut out);
+        assert_eq!(out, b);
+        assert_eq!(out.as_ptr(), ptr);
+        assert_eq!(out.capacity(), capacity);
     }
 
-    fn initial_hash(data: &[u8], hash_len: usize) -> usize {
-        data.iter()
-            .take(hash_len)
-            .fold(0, |hash, &byte| (hash * B + Self::to_usize(byte)) % M)
+    #[test]
+    fn try_restore_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let delta = delta(&a, &b, 3);
+        assert_eq!(try_restore(&a, &delta), Ok(b.to_vec()));
     }
 
-    fn to_usize(x: u8) -> usize {
-        x as usize + 1
+    #[test]
+    fn try_restore_out_of_bounds() {
+        use Compression::*;
+        let a = [0, 1, 2, 3];
+        let delta = [Raw(&[9]), Match(0, 2), Match(3, 2)];
+        assert_eq!(
+            try_restore(&a, &delta),
+            Err(RestoreError::MatchOutOfBounds {
+                index: 2,
+                la: 3,
+                len: 2,
+                a_len: 4,
+            })
+        );
     }
-}
 
-impl<'a> Iterator for RollingHash<'a> {
-    type Item = (usize, usize);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.index + self.hash_len >= self.data.len() {
-            return None;
-        }
+    #[test]
+    fn try_restore_len_overflow() {
+        use Compression::*;
+        let a = [0, 1, 2, 3];
+        let delta = [Match(1, usize::MAX)];
+        assert!(try_restore(&a, &delta).is_err());
+        // A back-reference is not bounded by `a`, only by the total output length.
+        let delta = [Raw(&[9]), Match(4, usize::MAX)];
+        assert_eq!(
+            try_restore(&a, &delta),
+            Err(RestoreError::OutputTooLong {
+                index: 1,
+                max: isize::MAX as usize / 4,
+            })
+        );
+        assert!(!verify(&a, &[9, 9], &delta));
+    }
 
-        if self.hash.is_none() {
-            let hash = Self::initial_hash(self.data, self.hash_len);
-            self.hash = Some(hash);
-            return Some((hash, 0));
-        }
+    #[test]
+    fn try_restore_with_limit_refuses_long_output() {
+        use Compression::*;
+        let a = [0, 1, 2, 3];
+        let delta = [Raw(&[9]), Match(4, 3), Match(0, 4)];
+        assert_eq!(
+            try_restore_with_limit(&a, &delta, 8),
+            Ok(vec![9, 9, 9, 9, 0, 1, 2, 3])
+        );
+        assert_eq!(
+            try_restore_with_limit(&a, &delta, 7),
+            Err(RestoreError::OutputTooLong { index: 2, max: 7 })
+        );
+        assert!(verify(&a, &[9, 9, 9, 9, 0, 1, 2, 3], &delta));
+        assert!(!verify(&a, &[9, 9, 9, 9], &delta));
+    }
 
-        let v1 = B * self.hash.unwrap() % M;
-        let v2 = Self::to_usize(self.data[self.index + self.hash_len]);
-        let v3 = self.base_pow * Self::to_usize(self.data[self.index]) % M;
-        let hash = (v1 + v2 + M - v3) % M; // v1 + v2 - v3
+    #[test]
+    fn compressions_deduplicate_in_hash_set() {
+        use std::collections::HashSet;
+        let set: HashSet<Compression> = [
+            Compression::Match(5, 3),
+            Compression::Raw(b"ab"),
+            Compression::Match(5, 3),
+            Compression::Match(3, 5),
+            Compression::Raw(b"ab"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&Compression::Match(5, 3)));
+        let owned: HashSet<CompressionOwned> = set.iter().map(Compression::to_owned).collect();
+        assert!(owned.contains(&CompressionOwned::Raw(b"ab".to_vec())));
+        assert_eq!(owned.len(), 3);
+    }
 
-        self.index += 1;
-        self.hash = Some(hash);
-        Some((hash, self.index))
+    #[test]
+    fn compression_source_range() {
+        let a = b"0123456789";
+        let m: Compression = Compression::Match(3, 4);
+        assert_eq!(m.source_range(), Some(3..7));
+        assert_eq!(&a[m.source_range().unwrap()], b"3456");
+        assert_eq!(Compression::<u8>::Match(5, 0).source_range(), Some(5..5));
+        assert_eq!(Compression::Raw(b"abc").source_range(), None);
+        assert_eq!(Compression::<u8>::Match(usize::MAX, 1).source_range(), None);
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct MatchInterval {
-    la: usize,
-    lb: usize,
-    len: usize,
-}
+    #[test]
+    fn compression_output_len() {
+        let m: Compression = Compression::Match(3, 5);
+        assert_eq!(m.output_len(), 5);
+        assert!(m.is_match());
+        let r = Compression::Raw(&[9, 9]);
+        assert_eq!(r.output_len(), 2);
+        assert!(!r.is_match());
+        assert_eq!(Compression::<u8>::Raw(&[]).output_len(), 0);
+    }
 
-impl MatchInterval {
-    // Search the matching interval from a[ia] and b[ib].
-    // a[la..la+len] == b[lb..lb+len].
-    fn new(a: &[u8], b: &[u8], ia: usize, ib: usize) -> Self {
-        let r = a[ia..]
-            .iter()
-            .zip(&b[ib..])
-            .take_while(|(va, vb)| va == vb)
-            .count();
+    #[test]
+    fn stats_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        let s = stats(&delta(&a, &b, 3));
+        assert_eq!(
+            s,
+            DeltaStats {
+                total_output: 8,
+                matched_bytes: 6,
+                raw_bytes: 2,
+                match_count: 2,
+                raw_count: 1,
+            }
+        );
+        assert_eq!(s.matching_ratio(), 0.75);
+    }
 
-        let l = a[..ia]
-            .iter()
-            .rev()
-            .zip(b[..ib].iter().rev())
-            .take_while(|(va, vb)| va == vb)
-            .count();
+    #[test]
+    fn stats_empty() {
+        let s = stats::<u8>(&[]);
+        assert_eq!(s, DeltaStats::default());
+        assert_eq!(s.matching_ratio(), 0.0);
+    }
 
-        let la = ia - l;
-        let lb = ib - l;
-        let len = l + r;
-        Self { la, lb, len }
+    #[test]
+    fn delta_summary_same_as_stats() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let inputs: [(&[u8], &[u8]); 5] = [(a, b), (b, a), (a, a), (b"", b"abc"), (b"abc", b"")];
+        for opts in [
+            DeltaOptions::default().min_match_len(2),
+            DeltaOptions::default().min_match_len(10),
+            DeltaOptions::default()
+                .max_match_len(7)
+                .max_matches(Some(5)),
+            DeltaOptions::default().hash_mode(HashMode::Double),
+            DeltaOptions::default().allow_self_reference(true),
+            DeltaOptions::default().normalize(Some(|x| x.to_ascii_lowercase())),
+            DeltaOptions::default().resync_window(Some(100)),
+            DeltaOptions::default().partition_for_parallel(Some(3)),
+        ] {
+            for (a, b) in inputs {
+                assert_eq!(delta_summary(a, b, &opts), stats(&delta_with(a, b, &opts)));
+            }
+        }
     }
 
-    fn empty() -> Self {
-        static EMPTY: MatchInterval = MatchInterval {
-            la: 0,
-            lb: 0,
-            len: 0,
-        };
-        EMPTY
+    #[test]
+    fn coverage_counts_matched_bytes() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let d = delta(a, b, n);
+            let covered = coverage(b.len(), &d);
+            assert_eq!(covered.len(), b.len());
+            let matched = covered.iter().filter(|&&c| c).count();
+            assert_eq!(matched, stats(&d).matched_bytes);
+            let ranges = coverage_ranges(&d);
+            assert_eq!(
+                ranges.iter().map(|(start, end)| end - start).sum::<usize>(),
+                matched
+            );
+            for (start, end) in ranges {
+                assert!(covered[start..end].iter().all(|&c| c));
+            }
+        }
     }
 
-    fn br(&self) -> usize {
-        self.lb + self.len
+    #[test]
+    fn coverage_ranges_joins_consecutive_matches() {
+        use Compression::*;
+        let d = [
+            Raw(&[1, 2][..]),
+            Match(0, 3),
+            Match(9, 1),
+            Raw(&[3]),
+            Match(4, 0),
+            Match(5, 2),
+        ];
+        assert_eq!(coverage_ranges(&d), vec![(2, 6), (7, 9)]);
+        let expected = [false, false, true, true, true, true, false, true];
+        assert_eq!(coverage(8, &d), expected);
+        assert_eq!(coverage(10, &d)[..9], [&expected[..], &[true]].concat());
+        assert!(coverage::<u8>(0, &[]).is_empty());
     }
 
-    fn remove_overlap(&mut self, other: &Self) {
-        if other.br() <= self.lb {
-            return;
-        }
-        if other.lb <= self.lb && self.br() <= other.br() {
-            self.len = 0;
-            return;
+    #[test]
+    fn similarity_same_as_matching_ratio() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let ratio = stats(&delta(a, b, n)).matching_ratio();
+            assert_eq!(similarity(a, b, n), ratio);
+            assert!(0.0 < ratio && ratio < 1.0);
         }
+        assert_eq!(similarity(a, a, 10), 1.0);
+        assert_eq!(similarity(a, b"", 10), 0.0);
+        assert_eq!(similarity(b"", b, 10), 0.0);
+    }
 
-        let diff = other.br() - self.lb + 1;
-        self.len = self.len.saturating_sub(diff);
-        self.la += diff;
-        self.lb += diff;
+    #[test]
+    #[cfg(feature = "diagnostics")]
+    fn collision_report_weak_params() {
+        let a = include_bytes!("../examples/a.txt");
+        let opts = DeltaOptions::default().min_match_len(8);
+        let report = collision_report(a, &opts);
+        // Every 4th window of length 4.
+        assert_eq!(report.windows, (a.len() - 3).div_ceil(4));
+        assert_eq!(report.colliding_buckets, 0);
+        assert_eq!(report.largest_bucket, 1);
+        assert!(report.buckets < report.windows);
+
+        let weak = opts.hash_params(HashParams::new(257, 13));
+        let report = collision_report(a, &weak);
+        assert!(report.buckets <= 13);
+        assert!(report.colliding_buckets > 0);
+        assert!(report.largest_bucket > 10);
+        let report = collision_report(a, &weak.hash_mode(HashMode::Double));
+        assert_eq!(report.colliding_buckets, 0);
     }
-}
 
-fn modpow(base: usize, exponent: usize) -> usize {
-    let mut result = 1;
-    let mut base = base;
-    let mut exponent = exponent;
-    while exponent > 0 {
-        if exponent % 2 == 1 {
-            result = (result * base) % M;
+    #[test]
+    #[cfg(feature = "diagnostics")]
+    fn collision_report_empty() {
+        let report = collision_report::<u8>(&[], &DeltaOptions::default());
+        assert_eq!(report, CollisionReport::default());
+    }
+
+    #[test]
+    fn delta_collects_with_stats() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let d: Delta = delta_iter(a, b, n).collect();
+            let plain = delta(a, b, n);
+            assert_eq!(d.stats(), stats(&plain));
+            assert_eq!(*d, plain[..]);
+            assert_eq!(restore(a, &d).concat(), b);
+            assert_eq!(encode_delta(&d), encode_delta(&plain));
+            assert_eq!(d.iter().count(), (&d).into_iter().count());
+            assert_eq!(Delta::from(plain), d);
+            assert_eq!(d.into_vec(), delta(a, b, n));
         }
-        base = (base * base) % M;
-        exponent /= 2;
+        let empty: Delta = core::iter::empty().collect();
+        assert_eq!(empty.stats(), DeltaStats::default());
+        assert!(empty.is_empty());
     }
-    result
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn match_density_falls_with_length() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let density = match_density(a, b);
+        let lengths: Vec<usize> = density.iter().map(|&(n, _)| n).collect();
+        assert_eq!(lengths, MATCH_DENSITY_LENGTHS);
+        assert!(density.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(density[0].1, stats(&delta(a, b, 4)).matching_ratio());
+        assert_eq!(density.last().unwrap().1, 0.0);
+    }
 
-    fn make_match_interval(la: usize, lb: usize, len: usize) -> MatchInterval {
-        MatchInterval { la, lb, len }
+    #[test]
+    fn match_density_identical_and_empty() {
+        let a: Vec<u8> = (0..3000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        assert!(match_density(&a, &a).iter().all(|&(_, ratio)| ratio == 1.0));
+        assert!(match_density(&a, &[])
+            .iter()
+            .all(|&(_, ratio)| ratio == 0.0));
+        assert!(match_density(&[], &a)
+            .iter()
+            .all(|&(_, ratio)| ratio == 0.0));
     }
 
     #[test]
-    fn extract_match_2345() {
+    fn verify_123_567() {
+        use Compression::*;
         let a = [0, 1, 2, 3, 4, 5, 6, 7];
-        let b = [2, 3, 4, 5];
-        let result = find_match_intervals(&a, &b, 4);
-        assert_eq!(result, vec![make_match_interval(2, 0, 4)]);
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        assert!(verify(&a, &b, &delta(&a, &b, 3)));
+        assert!(!verify(&a, &b[1..], &delta(&a, &b, 3)));
+        assert!(!verify(&a, &b, &[Match(5, 4)]));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn verify_delta_roundtrip(
+            a in proptest::collection::vec(0u8..4, 0..200),
+            b in proptest::collection::vec(0u8..4, 0..200),
+            n in 0usize..16,
+        ) {
+            proptest::prop_assert!(verify(&a, &b, &delta(&a, &b, n)));
+        }
     }
 
     #[test]
-    fn extract_match_45() {
-        let a = [0, 1, 2, 3, 4, 5, 6, 7];
-        let b = [0, 4, 5, 0];
-        let result = find_match_intervals(&a, &b, 1);
+    fn compression_display() {
+        use Compression::*;
+        assert_eq!(Match(12, 8).to_string(), "COPY a[12..20] (8 bytes)");
+        assert_eq!(Raw(b"hello").to_string(), "ADD 5 bytes: 68 65 6c 6c 6f");
         assert_eq!(
-            result,
-            vec![
-                make_match_interval(0, 0, 1), // 0.
-                make_match_interval(4, 1, 2), // 4 5.
-                make_match_interval(0, 3, 1), // 0.
-            ]
+            Raw(b"hello world").to_string(),
+            "ADD 11 bytes: 68 65 6c 6c 6f 20 77 6f ..."
+        );
+        assert_eq!(Raw(&[]).to_string(), "ADD 0 bytes");
+        assert_eq!(
+            CompressionOwned::Raw(vec![0xAB]).to_string(),
+            "ADD 1 bytes: ab"
         );
     }
 
     #[test]
-    fn extract_match_123_567() {
-        let a = [0, 1, 2, 3, 4, 5, 6, 7];
-        let b = [5, 6, 7, 9, 9, 1, 2, 3];
-        let result = find_match_intervals(&a, &b, 1);
+    fn compression_owned_conversion() {
+        use Compression::*;
+        let b = [9, 9];
+        let delta = [Match(5, 3), Raw(&b)];
+        let owned: Vec<CompressionOwned> = delta.iter().map(Into::into).collect();
         assert_eq!(
-            result,
+            owned,
             vec![
-                make_match_interval(5, 0, 3), // 5 6 7.
-                make_match_interval(1, 5, 3), // 1 2 3.
+                CompressionOwned::Match(5, 3),
+                CompressionOwned::Raw(vec![9, 9])
             ]
         );
+        let borrowed: Vec<Compression> = owned.iter().map(Into::into).collect();
+        assert_eq!(borrowed, delta);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn delta_123_567() {
-        use Compression::*;
+    fn compression_serde_json_roundtrip() {
         let a = [0, 1, 2, 3, 4, 5, 6, 7];
         let b = [5, 6, 7, 9, 9, 1, 2, 3];
-        let result = delta(&a, &b, 3);
-        assert_eq!(result, vec![Match(5, 3), Raw(&[9, 9]), Match(1, 3)]);
+        let delta = delta(&a, &b, 3);
+        let json = serde_json::to_string(&delta).unwrap();
+        assert_eq!(json, r#"[{"Match":[5,3]},{"Raw":[9,9]},{"Match":[1,3]}]"#);
+
+        let owned: Vec<CompressionOwned> = serde_json::from_str(&json).unwrap();
+        let result: Vec<Compression> = owned.iter().map(Into::into).collect();
+        assert_eq!(result, delta);
     }
 
     #[test]
-    fn delta_no_match() {
-        use Compression::*;
-        let a = [0, 1, 2, 3, 4, 5];
-        let b = [9, 9, 9, 9, 9, 9];
-        let result = delta(&a, &b, 3);
-        assert_eq!(result, vec![Raw(&b[..])]);
+    fn merge_adjacent_intervals_joins_contiguous() {
+        let intervals = vec![
+            make_match_interval(2, 0, 3),
+            make_match_interval(5, 3, 4),
+            make_match_interval(9, 7, 1),
+            make_match_interval(0, 8, 2),
+        ];
+        assert_eq!(
+            merge_adjacent_intervals(intervals, 20),
+            vec![make_match_interval(2, 0, 8), make_match_interval(0, 8, 2)]
+        );
     }
 
     #[test]
-    fn delta_ends_with_raw() {
-        use Compression::*;
-        let a = [0, 1, 2, 3, 4, 5];
-        let b = [9, 9, 9, 3, 4, 5, 9];
-        let result = delta(&a, &b, 3);
-        assert_eq!(result, vec![Raw(&[9, 9, 9]), Match(3, 3), Raw(&[9])]);
+    fn merge_adjacent_intervals_keeps_gaps() {
+        // Contiguous in `a` only, then in `b` only.
+        let intervals = vec![
+            make_match_interval(2, 0, 3),
+            make_match_interval(5, 4, 4),
+            make_match_interval(10, 8, 1),
+        ];
+        assert_eq!(merge_adjacent_intervals(intervals.clone(), 20), intervals);
     }
 
     #[test]
-    fn restore_123_567() {
-        let a = [0, 1, 2, 3, 4, 5, 6, 7];
-        let b = [5, 6, 7, 9, 9, 1, 2, 3];
-        let delta = delta(&a, &b, 3);
-        let result = restore(&a, &delta);
-        assert_eq!(result, vec![&b[0..3], &b[3..5], &b[5..]]);
+    fn merge_adjacent_intervals_not_across_end_of_a() {
+        let intervals = vec![make_match_interval(3, 0, 2), make_match_interval(5, 2, 2)];
+        assert_eq!(merge_adjacent_intervals(intervals.clone(), 5), intervals);
+        // Two back-references continue each other.
+        assert_eq!(
+            merge_adjacent_intervals(intervals, 2),
+            vec![make_match_interval(3, 0, 4)]
+        );
+    }
+
+    #[test]
+    fn delta_self_reference_boundary_not_merged() {
+        use Compression::*;
+        // The tail of `a`, continued by a back-reference to the start of `b`.
+        let a = [5, 6, 7];
+        let b = [6, 7, 6, 7, 6, 7, 6, 7];
+        let opts = DeltaOptions::default()
+            .min_match_len(2)
+            .allow_self_reference(true);
+        let d = delta_with(&a, &b, &opts);
+        assert_eq!(d, [Match(1, 2), Match(3, 6)]);
+        assert_eq!(patch(&a, &d), b);
     }
 
     #[test]
@@ -295,6 +4296,41 @@ mod tests {
         assert_eq!(result, make_match_interval(2, 0, 3));
     }
 
+    #[test]
+    fn select_longest_trims_overlap() {
+        let candidates = vec![
+            make_match_interval(0, 0, 4),
+            make_match_interval(10, 2, 6),
+            make_match_interval(20, 7, 3),
+        ];
+        assert_eq!(
+            select_longest(candidates),
+            vec![
+                make_match_interval(0, 0, 2),
+                make_match_interval(10, 2, 6),
+                make_match_interval(21, 8, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_longest_drops_covered() {
+        let candidates = vec![
+            make_match_interval(40, 0, 2),
+            make_match_interval(0, 0, 10),
+            make_match_interval(20, 3, 12),
+            make_match_interval(30, 4, 12),
+        ];
+        assert_eq!(
+            select_longest(candidates),
+            vec![
+                make_match_interval(0, 0, 3),
+                make_match_interval(20, 3, 12),
+                make_match_interval(41, 15, 1),
+            ]
+        );
+    }
+
     #[test]
     fn match_interval_remove_overlap_partial() {
         // m1 : |--------|
@@ -351,46 +4387,100 @@ mod tests {
     }
 
     #[test]
-    fn modpow_31_41() {
-        let result = modpow(31, 41);
-        assert_eq!(result, 411956758);
+    fn match_interval_remove_overlap_starts_before() {
+        // m1 :     |----|
+        // m2 :  |-----------|
+        // m2':            |-|
+        let m1 = make_match_interval(10, 5, 5);
+        let mut m2 = make_match_interval(0, 2, 12);
+        m2.remove_overlap(&m1);
+        assert_eq!(m2, make_match_interval(9, 11, 3));
+    }
+
+    #[test]
+    fn match_interval_remove_overlap_ends_inside() {
+        // m1 :     |----|
+        // m2 :  |------|
+        // m2':  ||
+        let m1 = make_match_interval(10, 5, 5);
+        for (lb, len) in [(2, 6), (2, 8), (0, 3), (4, 6)] {
+            let mut m2 = make_match_interval(0, lb, len);
+            m2.remove_overlap(&m1);
+            assert_eq!(m2.len, 0, "lb {lb} len {len}");
+        }
+        // Ending one past `m1` leaves nothing either, as the cut skips one more.
+        let mut m2 = make_match_interval(0, 2, 9);
+        m2.remove_overlap(&m1);
+        assert_eq!(m2.len, 0);
+    }
+
+    #[test]
+    fn remove_overlaps_left_extension() {
+        // The second candidate was extended left past the start of the first.
+        let candidates = vec![
+            make_match_interval(50, 10, 5),
+            make_match_interval(0, 4, 20),
+            make_match_interval(30, 30, 4),
+        ];
+        assert_eq!(
+            remove_overlaps(candidates),
+            vec![
+                make_match_interval(50, 10, 5),
+                make_match_interval(12, 16, 8),
+                make_match_interval(30, 30, 4),
+            ]
+        );
+    }
+
+    #[derive(Clone)]
+    struct SumHasher(u64);
+
+    impl WindowHasher for SumHasher {
+        fn init(&mut self, window: &[u8]) -> u64 {
+            self.0 = window.iter().map(|&x| x as u64).sum();
+            self.0
+        }
+
+        fn roll(&mut self, entering: u8, leaving: u8) -> u64 {
+            self.0 = self.0 + entering as u64 - leaving as u64;
+            self.0
+        }
     }
 
     #[test]
-    fn rolling_hash_0101x() {
-        let mut hashes = RollingHash::new(&[0, 1, 0, 1], 3);
-        assert_eq!(hashes.next(), Some((10201, 0)));
-        assert_eq!(hashes.next(), Some((20102, 1)));
-        assert_eq!(hashes.next(), None);
+    fn rolling_hash_custom_hasher() {
+        let hashes: Vec<_> = RollingHash::with_hasher(&[1, 2, 3, 4], 2, SumHasher(0)).collect();
+        assert_eq!(hashes, vec![(3, 0), (5, 1), (7, 2)]);
     }
 
     #[test]
-    fn rolling_hash_010101() {
-        let mut hashes = RollingHash::new(&[0, 1, 0, 1, 0, 1], 3);
-        assert_eq!(hashes.next(), Some((10201, 0)));
-        assert_eq!(hashes.next(), Some((20102, 1)));
-        assert_eq!(hashes.next(), Some((10201, 2)));
-        assert_eq!(hashes.next(), Some((20102, 3)));
-        assert_eq!(hashes.next(), None);
+    fn delta_with_custom_hasher() {
+        use Compression::*;
+        // [3, 2] and [2, 3] collide under SumHasher, which the seed check rejects.
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 3, 2, 1];
+        let result = delta_with_hasher(&a, &b, 3, SumHasher(0));
+        assert_eq!(result, vec![Match(5, 3), Raw(&b[3..])]);
     }
 
     #[test]
-    fn rolling_hash_abcdefg() {
-        let mut hashes = RollingHash::new("abcdefg".as_ref(), 4).step_by(2);
-        let expected = [99000101, 100010202, 101020303, 102030404];
-        assert_eq!(hashes.next(), Some((expected[0], 0)));
-        assert_eq!(hashes.next(), Some((expected[2], 2)));
-        assert_eq!(hashes.next(), None);
+    fn delta_with_double_hash_same_result() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let weak = HashParams::new(31, 101);
+        for n in [4, 10, 24] {
+            for params in [HashParams::default(), weak] {
+                let opts = DeltaOptions::default().min_match_len(n).hash_params(params);
+                let double = opts.clone().hash_mode(HashMode::Double);
+                assert_eq!(delta_with(a, b, &opts), delta_with(a, b, &double));
+            }
+        }
     }
 
     #[test]
-    fn rolling_hash_exceeds_mod() {
-        let data = vec![255u8; 20];
-        let mut hashes = RollingHash::new(&data, 10).step_by(3);
-        assert_eq!(hashes.next(), Some((757588431, 0)));
-        assert_eq!(hashes.next(), Some((757588431, 3)));
-        assert_eq!(hashes.next(), Some((757588431, 6)));
-        assert_eq!(hashes.next(), Some((757588431, 9)));
-        assert_eq!(hashes.next(), None);
+    fn delta64_123_567() {
+        let a = [0, 1, 2, 3, 4, 5, 6, 7];
+        let b = [5, 6, 7, 9, 9, 1, 2, 3];
+        assert_eq!(delta64(&a, &b, 3), delta(&a, &b, 3));
     }
 }
@@ -0,0 +1,192 @@
+use crate::{DeltaOptions, MatchInterval, PolynomialHasher, RollingHash, SeedTable, Symbol};
+use alloc::{vec, vec::Vec};
+
+/// One step of a delta against several sources: copy
+/// `sources[source][la..la + len]` or insert raw elements of `b`.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "&'a [T]: serde::Deserialize<'de>"))
+)]
+pub enum CompressionMulti<'a, T = u8> {
+    Match(usize, usize, usize),
+    Raw(#[cfg_attr(feature = "serde", serde(borrow))] &'a [T]),
+}
+
+/// Same as `delta`, but matches may come from any of `sources`. Where several
+/// sources match equally well, the first one listed is used.
+pub fn delta_multi<'a, T: Symbol>(
+    sources: &[&'a [T]],
+    b: &'a [T],
+    min_match_len: usize,
+) -> Vec<CompressionMulti<'a, T>> {
+    use CompressionMulti::*;
+
+    let opts = DeltaOptions::default().min_match_len(min_match_len);
+    let match_intervals = find_multi_match_intervals(sources, b, &opts);
+    if match_intervals.is_empty() {
+        return vec![Raw(b)];
+    }
+
+    let mut results = Vec::with_capacity(match_intervals.len());
+    let mut prev = 0;
+    for (source, MatchInterval { la, lb, len }) in match_intervals {
+        if prev < lb {
+            results.push(Raw(&b[prev..lb]));
+        }
+        results.push(Match(source, la, len));
+        prev = lb + len;
+    }
+    if prev != b.len() {
+        results.push(Raw(&b[prev..]));
+    }
+    results
+}
+
+/// Same as `restore`, for deltas produced by `delta_multi`.
+///
+/// # Panics
+///
+/// If a `Match` names a source that is not in `sources`, or copies from outside of it.
+pub fn restore_multi<'a, T>(
+    sources: &[&'a [T]],
+    compressions: &[CompressionMulti<'a, T>],
+) -> Vec<&'a [T]> {
+    compressions
+        .iter()
+        .map(|c| match *c {
+            CompressionMulti::Match(source, la, len) => &sources[source][la..la + len],
+            CompressionMulti::Raw(data) => data,
+        })
+        .collect()
+}
+
+// The greedy scan of `find_match_intervals_with`, with one seed table per source.
+// Each interval is paired with the index of the source it was found in.
+fn find_multi_match_intervals<T: Symbol>(
+    sources: &[&[T]],
+    b: &[T],
+    opts: &DeltaOptions,
+) -> Vec<(usize, MatchInterval)> {
+    if opts.longer_than(b) {
+        return Vec::new();
+    }
+    let hash_len = opts.hash_len();
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let tables: Vec<(usize, SeedTable<T>)> = sources
+        .iter()
+        .enumerate()
+        .filter(|(_, source)| !source.is_empty())
+        .map(|(i, source)| {
+            let table = SeedTable::sampled(source, opts, hasher.clone());
+            (i, table)
+        })
+        .collect();
+    if tables.is_empty() {
+        return Vec::new();
+    }
+    let window_len = core::cmp::min(b.len(), hash_len);
+
+    let mut candidates = Vec::new();
+    let mut state = 0;
+    for (hb, ib) in RollingHash::with_hasher(b, hash_len, hasher) {
+        if ib < state {
+            continue;
+        }
+        let window = &b[ib..ib + window_len];
+        let mut best = (0, MatchInterval::empty());
+        for (source, table) in &tables {
+            let m = table.best_match(b, window, hb, ib, &MatchInterval::empty());
+            if m.len > best.1.len {
+                best = (*source, m);
+            }
+        }
+        if best.1.len > 0 {
+            state = best.1.br();
+            candidates.push(best);
+        }
+    }
+
+    let mut acc = MatchInterval::empty();
+    candidates
+        .into_iter()
+        .filter_map(|(source, mut m)| {
+            m.remove_overlap(&acc);
+            if m.len > 0 {
+                m.extend_left(sources[source], b, acc.br());
+                acc = m;
+            }
+            (m.len > 0 && m.len >= opts.min_match_len).then_some((source, m))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use CompressionMulti::*;
+
+    fn concat(slices: Vec<&[u8]>) -> Vec<u8> {
+        slices.concat()
+    }
+
+    #[test]
+    fn delta_multi_two_sources() {
+        let x = [0, 1, 2, 3, 4, 5, 6, 7];
+        let y = [20, 21, 22, 23, 24, 25];
+        let b = [21, 22, 23, 24, 9, 1, 2, 3, 4, 5];
+        let d = delta_multi(&[&x, &y], &b, 4);
+        assert_eq!(d, vec![Match(1, 1, 4), Raw(&[9]), Match(0, 1, 5)]);
+        assert_eq!(concat(restore_multi(&[&x, &y], &d)), b);
+    }
+
+    #[test]
+    fn delta_multi_prefers_first_source_on_ties() {
+        let x = [0, 1, 2, 3, 4, 5];
+        let b = [1, 2, 3, 4];
+        let d = delta_multi(&[&x, &x], &b, 4);
+        assert_eq!(d, vec![Match(0, 1, 4)]);
+    }
+
+    #[test]
+    fn delta_multi_takes_longest_source() {
+        let x = [0, 1, 2, 3, 9, 9];
+        let y = [0, 1, 2, 3, 4, 5];
+        let b = [0, 1, 2, 3, 4, 5, 8];
+        let d = delta_multi(&[&x, &y], &b, 4);
+        assert_eq!(d, vec![Match(1, 0, 6), Raw(&[8])]);
+    }
+
+    #[test]
+    fn delta_multi_same_as_delta_for_one_source() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            let single: Vec<_> = crate::delta(a, b, n)
+                .into_iter()
+                .map(|c| match c {
+                    crate::Compression::Match(la, len) => Match(0, la, len),
+                    crate::Compression::Raw(data) => Raw(data),
+                })
+                .collect();
+            assert_eq!(delta_multi(&[a], b, n), single);
+        }
+    }
+
+    #[test]
+    fn delta_multi_no_sources() {
+        let b = [1, 2, 3];
+        assert_eq!(delta_multi(&[], &b, 2), vec![Raw(&b[..])]);
+        assert_eq!(delta_multi(&[&[]], &b, 2), vec![Raw(&b[..])]);
+    }
+
+    #[test]
+    fn delta_multi_roundtrip_split_target() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let (x, y) = a.split_at(a.len() / 2);
+        let d = delta_multi(&[y, x], b, 10);
+        assert_eq!(concat(restore_multi(&[y, x], &d)), b);
+    }
+}
@@ -0,0 +1,272 @@
+use std::ops::Range;
+
+use rayon::prelude::*;
+
+use crate::{
+    delta_with, extend_into_raws, find_match_intervals_with, finish_delta, remove_overlaps,
+    Compression, DeltaOptions, DoubleHasher, HashMode, MatchInterval, MatchStrategy,
+    PolynomialHasher, RollingHash, SeedTable, Symbol, WindowHasher,
+};
+
+// Below this many windows per chunk the fork/join overhead outweighs the scan.
+const MIN_CHUNK_LEN: usize = 1 << 16;
+
+/// Same as `delta_with`, but scans `b` on the rayon thread pool.
+///
+/// `b` is cut into chunks which are matched against the shared index of `a` in
+/// parallel. The per-chunk matches are then stitched together serially, so the
+/// result is identical to `delta_with`. Only `MatchStrategy::Greedy` without
+/// `allow_self_reference`, `source_window` or `max_matches` is parallelized; other
+/// options use the sequential scan, and `resync_window`, `partition_for_parallel`,
+/// `normalize` and `MatchStrategy::MinimalOps` fall back to `delta_with`.
+pub fn par_delta_with<'a, T: Symbol + Sync>(
+    a: &[T],
+    b: &'a [T],
+    opts: &DeltaOptions,
+) -> Vec<Compression<'a, T>> {
+    let opts = &*opts.resolved(b.len());
+    if opts.resync_window.is_some()
+        || opts.partition_for_parallel.is_some()
+        || opts.normalize.is_some()
+        || opts.strategy == MatchStrategy::MinimalOps
+    {
+        return delta_with(a, b, opts);
+    }
+    let chunk_len = std::cmp::max(
+        MIN_CHUNK_LEN,
+        b.len().div_ceil(rayon::current_num_threads() * 4),
+    );
+    let match_intervals = match opts.hash_mode {
+        HashMode::Single => {
+            let hasher = PolynomialHasher::new(opts.hash_params);
+            find_match_intervals_par(a, b, opts, hasher, chunk_len)
+        }
+        HashMode::Double => {
+            let hasher = DoubleHasher::with_params(opts.hash_params);
+            find_match_intervals_par(a, b, opts, hasher, chunk_len)
+        }
+    };
+    finish_delta(a, b, match_intervals, opts)
+}
+
+fn find_match_intervals_par<T, H>(
+    a: &[T],
+    b: &[T],
+    opts: &DeltaOptions,
+    hasher: H,
+    chunk_len: usize,
+) -> Vec<MatchInterval>
+where
+    T: Symbol + Sync,
+    H: WindowHasher<T> + Clone + Sync,
+{
+    if opts.strategy != MatchStrategy::Greedy
+        || opts.allow_self_reference
+        || opts.source_window.is_some()
+        || opts.max_matches.is_some()
+        || opts.longer_than(b)
+    {
+        return find_match_intervals_with(a, b, opts, hasher);
+    }
+    let hash_len = opts.hash_len();
+    let source = SeedTable::sampled(a, opts, hasher.clone());
+    let window_len = std::cmp::min(b.len(), hash_len);
+    let windows = if window_len == 0 {
+        0
+    } else {
+        b.len() - window_len + 1
+    };
+
+    let ranges: Vec<Range<usize>> = (0..windows)
+        .step_by(chunk_len)
+        .map(|start| start..std::cmp::min(start + chunk_len, windows))
+        .collect();
+    let scans: Vec<Vec<(usize, MatchInterval)>> = ranges
+        .par_iter()
+        .map(|range| scan_chunk(&source, b, window_len, hasher.clone(), range.clone()))
+        .collect();
+
+    // Each chunk was scanned as if nothing matched before it. Until the sequential
+    // scan reaches a window the chunk scan also looked at, redo those windows here;
+    // from that window on both scans make the same choices.
+    let mut candidates = Vec::new();
+    let mut state = 0;
+    for (range, scan) in ranges.iter().zip(scans) {
+        let mut ib = std::cmp::max(state, range.start);
+        while ib < range.end {
+            let before = scan.partition_point(|&(i, _)| i < ib);
+            if before == 0 || scan[before - 1].1.br() <= ib {
+                if let Some(&(_, last)) = scan[before..].last() {
+                    state = last.br();
+                }
+                candidates.extend(scan[before..].iter().map(|&(_, m)| m));
+                break;
+            }
+            let window = &b[ib..ib + window_len];
+            let hb = hasher.clone().init(window);
+            let m = source.best_match(b, window, hb, ib, &MatchInterval::empty());
+            if m.len > 0 {
+                state = m.br();
+                candidates.push(m);
+                ib = state;
+            } else {
+                ib += 1;
+            }
+        }
+    }
+    extend_into_raws(remove_overlaps(candidates), a, b)
+}
+
+// Greedy scan of the windows of `b` starting in `range`, paired with the window
+// each match was found at.
+fn scan_chunk<T: Symbol, H: WindowHasher<T>>(
+    source: &SeedTable<T>,
+    b: &[T],
+    window_len: usize,
+    hasher: H,
+    range: Range<usize>,
+) -> Vec<(usize, MatchInterval)> {
+    let data = &b[range.start..range.end + window_len - 1];
+    let mut found = Vec::new();
+    let mut state = range.start;
+    for (hb, i) in RollingHash::with_hasher(data, window_len, hasher) {
+        let ib = range.start + i;
+        if ib < state {
+            continue;
+        }
+        let window = &b[ib..ib + window_len];
+        let m = source.best_match(b, window, hb, ib, &MatchInterval::empty());
+        if m.len > 0 {
+            state = m.br();
+            found.push((ib, m));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta_with, xorshift_bytes, Polynomial64Hasher};
+
+    // `len` random symbols of `b"\0\x01.."[..alphabet]`.
+    fn random_symbols(len: usize, seed: u64, alphabet: u8) -> Vec<u8> {
+        xorshift_bytes(len, seed)
+            .iter()
+            .map(|x| x % alphabet)
+            .collect()
+    }
+
+    // `b` is `a` with a few chunks rearranged and some noise spliced in.
+    fn edited(a: &[u8], seed: u64) -> Vec<u8> {
+        let noise = xorshift_bytes(64, seed);
+        let third = a.len() / 3;
+        let mut b = Vec::new();
+        b.extend_from_slice(&a[third..2 * third]);
+        b.extend_from_slice(&noise[..17]);
+        b.extend_from_slice(&a[..third]);
+        b.extend_from_slice(&a[third / 2..third]);
+        b.extend_from_slice(&noise[17..]);
+        b.extend_from_slice(&a[2 * third..]);
+        b
+    }
+
+    #[test]
+    fn test_par_matches_sequential_for_any_chunk_len() {
+        for seed in 1..6 {
+            for a in [random_symbols(2000, seed, 4), xorshift_bytes(2000, seed)] {
+                let b = edited(&a, seed + 100);
+                for n in [3, 5, 10, 24] {
+                    let opts = DeltaOptions::default().min_match_len(n);
+                    let hasher = PolynomialHasher::default();
+                    let expected = find_match_intervals_with(&a, &b, &opts, hasher.clone());
+                    for chunk_len in [1, 2, 3, 7, 64, 1000, 10_000] {
+                        let actual =
+                            find_match_intervals_par(&a, &b, &opts, hasher.clone(), chunk_len);
+                        assert_eq!(expected, actual, "seed {seed} n {n} chunk {chunk_len}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_par_matches_sequential_with_other_hasher() {
+        let a = random_symbols(3000, 7, 3);
+        let b = edited(&a, 8);
+        let opts = DeltaOptions::default().min_match_len(12).step_factor(2);
+        let hasher = Polynomial64Hasher::default();
+        let expected = find_match_intervals_with(&a, &b, &opts, hasher.clone());
+        for chunk_len in [1, 5, 33] {
+            let actual = find_match_intervals_par(&a, &b, &opts, hasher.clone(), chunk_len);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_par_matches_sequential_with_max_matches() {
+        let a = random_symbols(2000, 5, 4);
+        let b = edited(&a, 6);
+        for max_matches in [0, 1, 2, 10, 100] {
+            let opts = DeltaOptions::default()
+                .min_match_len(5)
+                .max_matches(Some(max_matches));
+            let hasher = PolynomialHasher::default();
+            let expected = find_match_intervals_with(&a, &b, &opts, hasher.clone());
+            for chunk_len in [1, 7, 64] {
+                let actual = find_match_intervals_par(&a, &b, &opts, hasher.clone(), chunk_len);
+                assert_eq!(expected, actual, "max {max_matches} chunk {chunk_len}");
+            }
+        }
+        let opts = DeltaOptions::default()
+            .min_match_len(2)
+            .max_matches(Some(1));
+        assert_eq!(
+            par_delta_with(b"aca", b"aaacb", &opts),
+            delta_with(b"aca", b"aaacb", &opts)
+        );
+    }
+
+    #[test]
+    fn test_par_delta_with_normalize() {
+        let a = b"The Quick Brown Fox jumps over the lazy dog";
+        let b = b"the quick brown fox JUMPS OVER THE LAZY DOG";
+        let opts = DeltaOptions::default()
+            .min_match_len(2)
+            .normalize(Some(|x| x.to_ascii_lowercase()));
+        let d = par_delta_with(a, b, &opts);
+        assert_eq!(d, delta_with(a, b, &opts));
+        assert!(d.iter().any(Compression::is_match));
+    }
+
+    #[test]
+    fn test_par_delta_with_short_inputs() {
+        for (a, b) in [
+            (&b""[..], &b""[..]),
+            (b"abcdefghij", b""),
+            (b"", b"abcdefghij"),
+            (b"abcdefghij", b"abc"),
+            (b"abcdefghijklmnopqrstuvwxyz", b"xyzabcdefghij"),
+        ] {
+            for n in [0, 1, 4, 10, 40] {
+                let opts = DeltaOptions::default().min_match_len(n);
+                assert_eq!(delta_with(a, b, &opts), par_delta_with(a, b, &opts));
+            }
+        }
+    }
+
+    #[test]
+    fn test_par_delta_with_other_options() {
+        let a = random_symbols(1500, 3, 4);
+        let b = edited(&a, 4);
+        for opts in [
+            DeltaOptions::default().hash_mode(HashMode::Double),
+            DeltaOptions::default().strategy(MatchStrategy::Longest),
+            DeltaOptions::default().strategy(MatchStrategy::MinimalOps),
+            DeltaOptions::default().allow_self_reference(true),
+            DeltaOptions::default().max_matches(Some(3)),
+        ] {
+            assert_eq!(delta_with(&a, &b, &opts), par_delta_with(&a, &b, &opts));
+        }
+    }
+}
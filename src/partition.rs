@@ -0,0 +1,217 @@
+use crate::{
+    compressions_from_intervals, delta_intervals, patch, Compression, DeltaOptions, MatchInterval,
+    SourceIndex, Symbol,
+};
+use alloc::vec::Vec;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// One of the parts `delta_partitions` cuts a delta into: the compressions that
+/// write `b[output_offset..]` up to the next partition.
+///
+/// A partition restores on its own from `a`: its back-references address its own
+/// output, starting at `a.len()`, and no other partition reads the parts of `a` its
+/// matches copy.
+#[derive(Debug, PartialEq)]
+pub struct Partition<'a, T = u8> {
+    pub output_offset: usize,
+    pub compressions: Vec<Compression<'a, T>>,
+}
+
+/// Same as `delta_with`, cut into `DeltaOptions::partition_for_parallel` partitions
+/// of `b` that `restore_parallel` restores concurrently.
+///
+/// Each partition is diffed against `a` on its own. Its matches are then cut so
+/// that they copy only from parts of `a` no earlier partition copies from, and the
+/// pieces shorter than `min_match_len` become raw data. `None` is one partition.
+pub fn delta_partitions<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    opts: &DeltaOptions,
+) -> Vec<Partition<'a, T>> {
+    partition_intervals(a, b, 0, &opts.resolved(b.len()), None)
+        .into_iter()
+        .map(|(output_offset, part, match_intervals)| Partition {
+            output_offset,
+            compressions: compressions_from_intervals(part, match_intervals),
+        })
+        .collect()
+}
+
+/// Restores `b` from `a` and the partitions of `delta_partitions`, each on its own
+/// thread of the rayon pool with the `rayon` feature, or one after another without.
+///
+/// # Panics
+///
+/// As `restore` does, for any partition.
+pub fn restore_parallel<T: Copy + Send + Sync>(a: &[T], partitions: &[Partition<T>]) -> Vec<T> {
+    let restore = |p: &Partition<T>| patch(a, &p.compressions);
+    #[cfg(feature = "rayon")]
+    let parts: Vec<Vec<T>> = partitions.par_iter().map(restore).collect();
+    #[cfg(not(feature = "rayon"))]
+    let parts: Vec<Vec<T>> = partitions.iter().map(restore).collect();
+    parts.concat()
+}
+
+// The partitions of `b` with their offsets and the shaped intervals of each, in
+// the coordinates of the partition, scanned against the table of `a` in `index` if
+// there is one. The intervals are shaped for where the output holds them, with `b`
+// itself at `start`.
+pub(crate) fn partition_intervals<'a, T: Symbol>(
+    a: &[T],
+    b: &'a [T],
+    start: usize,
+    opts: &DeltaOptions,
+    index: Option<&SourceIndex>,
+) -> Vec<(usize, &'a [T], Vec<MatchInterval>)> {
+    let count = core::cmp::max(1, opts.partition_for_parallel.unwrap_or(1));
+    let part_len = core::cmp::max(1, b.len().div_ceil(count));
+    let inner = opts
+        .clone()
+        .partition_for_parallel(None)
+        .resync_window(None);
+    // The ranges of `a` earlier partitions copy from, sorted and disjoint.
+    let mut claimed: Vec<(usize, usize)> = Vec::new();
+    let mut partitions = Vec::with_capacity(count);
+    for (i, part) in b.chunks(part_len).enumerate() {
+        let mut match_intervals = Vec::new();
+        let output_offset = i * part_len;
+        for m in delta_intervals(a, part, start + output_offset, &inner, index) {
+            if m.la >= a.len() {
+                match_intervals.push(m);
+                continue;
+            }
+            unclaimed_pieces(m, &claimed, |piece| {
+                if piece.len >= opts.min_match_len {
+                    match_intervals.push(piece);
+                }
+            });
+        }
+        for m in match_intervals.iter().filter(|m| m.la < a.len()) {
+            claimed.push((m.la, m.la + m.len));
+        }
+        claimed = merged(claimed);
+        partitions.push((output_offset, part, match_intervals));
+    }
+    partitions
+}
+
+// Calls `f` with the parts of `m` that copy from outside of `claimed`, in order.
+fn unclaimed_pieces(
+    m: MatchInterval,
+    claimed: &[(usize, usize)],
+    mut f: impl FnMut(MatchInterval),
+) {
+    let end = m.la + m.len;
+    let mut from = m.la;
+    let first = claimed.partition_point(|&(_, claimed_end)| claimed_end <= m.la);
+    for &(start, claimed_end) in &claimed[first..] {
+        if start >= end {
+            break;
+        }
+        if from < start {
+            f(m.slice(m.lb + from - m.la, m.lb + start - m.la));
+        }
+        from = core::cmp::max(from, claimed_end);
+    }
+    if from < end {
+        f(m.slice(m.lb + from - m.la, m.lb + m.len));
+    }
+}
+
+// `ranges` sorted, with overlapping and touching ones joined.
+fn merged(mut ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    ranges.sort_unstable();
+    let mut results: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match results.last_mut() {
+            Some(last) if start <= last.1 => last.1 = core::cmp::max(last.1, end),
+            _ => results.push((start, end)),
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta_with, restore};
+
+    fn check_disjoint(a_len: usize, partitions: &[Partition]) {
+        let mut reads: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, p) in partitions.iter().enumerate() {
+            for c in &p.compressions {
+                if let Some(range) = c.source_range().filter(|r| r.start < a_len) {
+                    reads.push((range.start, range.end, i));
+                }
+            }
+        }
+        reads.sort_unstable();
+        for pair in reads.windows(2) {
+            let ((_, end, i), (start, _, j)) = (pair[0], pair[1]);
+            assert!(end <= start || i == j, "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn restore_parallel_same_as_serial() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for count in [1, 2, 7, 64] {
+            for self_reference in [false, true] {
+                let opts = DeltaOptions::default()
+                    .min_match_len(8)
+                    .allow_self_reference(self_reference)
+                    .partition_for_parallel(Some(count));
+                let partitions = delta_partitions(a, b, &opts);
+                assert!(partitions.len() <= count);
+                check_disjoint(a.len(), &partitions);
+                let d = delta_with(a, b, &opts);
+                assert_eq!(restore_parallel(a, &partitions), restore(a, &d).concat());
+                assert_eq!(restore_parallel(a, &partitions), b);
+                let mut offset = 0;
+                for p in &partitions {
+                    assert_eq!(p.output_offset, offset);
+                    offset += crate::stats(&p.compressions).total_output;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn partitions_copy_disjoint_parts_of_a() {
+        use Compression::*;
+        let a: Vec<u8> = (0..16).collect();
+        let b: Vec<u8> = a.iter().chain(&a).copied().collect();
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .partition_for_parallel(Some(2));
+        let partitions = delta_partitions(&a, &b, &opts);
+        assert_eq!(partitions[0].compressions, vec![Match(0, 16)]);
+        assert_eq!(partitions[1].compressions, vec![Raw(&b[16..])]);
+        assert_eq!(restore_parallel(&a, &partitions), b);
+        // The first partition copies `a[..8]` twice, and the second only what is left.
+        let b: Vec<u8> = a[..8].iter().chain(&a).copied().collect();
+        let partitions = delta_partitions(&a, &b, &opts);
+        assert_eq!(partitions[0].compressions, vec![Match(0, 8), Match(0, 4)]);
+        assert_eq!(
+            partitions[1],
+            Partition {
+                output_offset: 12,
+                compressions: vec![Raw(&b[12..16]), Match(8, 8)]
+            }
+        );
+        assert_eq!(restore_parallel(&a, &partitions), b);
+        check_disjoint(a.len(), &partitions);
+    }
+
+    #[test]
+    fn delta_partitions_empty_inputs() {
+        let opts = DeltaOptions::default().partition_for_parallel(Some(4));
+        assert!(delta_partitions(b"abc", b"", &opts).is_empty());
+        assert!(restore_parallel::<u8>(b"abc", &[]).is_empty());
+        let partitions = delta_partitions(b"", b"abcdef", &opts);
+        assert_eq!(partitions.len(), 3);
+        assert_eq!(restore_parallel(b"", &partitions), b"abcdef");
+    }
+}
@@ -0,0 +1,488 @@
+#[cfg(feature = "std")]
+use crate::encode::{
+    apply_with, invalid_data, read_varint_io, stated_output_len, write_delta, write_varint,
+};
+#[cfg(feature = "std")]
+use crate::reconstruct_len;
+use crate::{reserve_output, restore_one, Compression};
+#[cfg(feature = "std")]
+use crate::{ApplyError, ApplyOptions};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
+use std::io::{self, BufReader, Read, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+const MAGIC: [u8; 4] = *b"SHDP";
+#[cfg(feature = "std")]
+const VERSION: u8 = 1;
+
+// CRC-32 (IEEE 802.3) lookup table for the reflected polynomial.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 checksum of `data`, as stored in patch files by `write_patch_file`.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+// The CRC-32 of some bytes followed by `data`, from the CRC-32 `crc` of those bytes.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Same as `patch`, also returning the `crc32` of the output. Each compression is
+/// checksummed as soon as it is copied, rather than in a second pass over the
+/// whole output.
+///
+/// # Panics
+///
+/// As `restore` does.
+pub fn patch_checked(a: &[u8], compressions: &[Compression]) -> (Vec<u8>, u32) {
+    let mut out = Vec::new();
+    reserve_output(&mut out, compressions);
+    let mut checksum = 0;
+    for c in compressions {
+        let start = out.len();
+        restore_one(a, c, &mut out);
+        checksum = crc32_update(checksum, &out[start..]);
+    }
+    (out, checksum)
+}
+
+/// Why `read_patch_file` refused a patch.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum PatchError {
+    /// The input does not start with the patch file magic number.
+    #[error("not a patch file")]
+    BadMagic,
+    #[error("unsupported patch file version {0}")]
+    UnsupportedVersion(u8),
+    /// The patch was made against a different source than the `a` it is applied to.
+    #[error(
+        "patch expects a source of {expected_len} bytes with CRC-32 {expected_checksum:08x}, \
+         got {actual_len} bytes with CRC-32 {actual_checksum:08x}"
+    )]
+    SourceMismatch {
+        expected_len: usize,
+        expected_checksum: u32,
+        actual_len: usize,
+        actual_checksum: u32,
+    },
+    /// The delta restores to a different length than the header promises.
+    #[error("patch restores {actual} bytes instead of {expected}")]
+    OutputLengthMismatch { expected: usize, actual: usize },
+}
+
+/// Writes a self-describing patch file: a header identifying the source the delta
+/// was made against, followed by the delta in the `encode_delta` format.
+///
+/// ```text
+/// "SHDP", version, varint(a_len), a_checksum as u32 LE, varint(output_len), delta
+/// ```
+///
+/// `a_checksum` is `crc32(a)`. Passing the length and checksum rather than `a`
+/// itself lets them be computed once and stored alongside the source.
+#[cfg(feature = "std")]
+pub fn write_patch_file<W: Write>(
+    w: &mut W,
+    a_len: usize,
+    a_checksum: u32,
+    compressions: &[Compression],
+) -> io::Result<()> {
+    let output_len = reconstruct_len(compressions).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "delta restores to more than usize::MAX bytes",
+        )
+    })?;
+    let mut header = Vec::with_capacity(32);
+    header.extend_from_slice(&MAGIC);
+    header.push(VERSION);
+    write_varint(&mut header, a_len);
+    header.extend_from_slice(&a_checksum.to_le_bytes());
+    write_varint(&mut header, output_len);
+    w.write_all(&header)?;
+    write_delta(compressions, w)
+}
+
+/// Reconstructs `b` from `a` and a patch file written by `write_patch_file`.
+///
+/// The length and CRC-32 of `a` are checked against the header before anything is
+/// reconstructed, so a patch applied to the wrong source is refused. The output
+/// length is checked as the delta is decoded, so a patch restoring more than its
+/// header states is refused before anything is restored. Malformed or
+/// mismatched input is reported as `InvalidData` wrapping a `PatchError`, or a
+/// `DecodeError` or `RestoreError` from the delta itself.
+#[cfg(feature = "std")]
+pub fn read_patch_file<R: Read>(a: &[u8], r: &mut R) -> io::Result<Vec<u8>> {
+    read_patch_file_with(a, r, &ApplyOptions::default())
+}
+
+/// Same as `read_patch_file`, with the output also bounded by
+/// `opts.max_output_len`. The header's output length takes the place of
+/// `opts.expected_output_len`.
+#[cfg(feature = "std")]
+pub fn read_patch_file_with<R: Read>(
+    a: &[u8],
+    r: &mut R,
+    opts: &ApplyOptions,
+) -> io::Result<Vec<u8>> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data(PatchError::BadMagic));
+    }
+    let mut version = [0];
+    r.read_exact(&mut version)?;
+    if version[0] != VERSION {
+        return Err(invalid_data(PatchError::UnsupportedVersion(version[0])));
+    }
+    let mut pos = MAGIC.len() + 1;
+    let expected_len = read_varint_io(r, &mut pos)?;
+    let mut checksum = [0; 4];
+    r.read_exact(&mut checksum)?;
+    pos += checksum.len();
+    let expected_checksum = u32::from_le_bytes(checksum);
+    let output_len = read_varint_io(r, &mut pos)?;
+
+    let actual_checksum = crc32(a);
+    if a.len() != expected_len || actual_checksum != expected_checksum {
+        return Err(invalid_data(PatchError::SourceMismatch {
+            expected_len,
+            expected_checksum,
+            actual_len: a.len(),
+            actual_checksum,
+        }));
+    }
+
+    let mut encoded = Vec::new();
+    r.read_to_end(&mut encoded)?;
+    let opts = opts.clone().expected_output_len(Some(output_len));
+    apply_with(a, &encoded, &opts).map_err(|e| match e {
+        ApplyError::OutputTooShort { expected, actual } => {
+            invalid_data(PatchError::OutputLengthMismatch { expected, actual })
+        }
+        ApplyError::OutputTooLong { expected, .. } => {
+            invalid_data(PatchError::OutputLengthMismatch {
+                expected,
+                actual: stated_output_len(&encoded),
+            })
+        }
+        e => invalid_data(e),
+    })
+}
+
+/// Applies the patch file at `patch` to the file at `source` and writes the result
+/// to `dest`.
+///
+/// The patch is checked against `source` as `read_patch_file` does, and nothing is
+/// written if it does not apply. The output goes to a new temporary file next to
+/// `dest` that is then renamed over it, so `dest` is either left as it was or
+/// replaced whole, keeping its permissions. `dest` may be `source` itself.
+#[cfg(feature = "std")]
+pub fn apply_file(source: &Path, patch: &Path, dest: &Path) -> io::Result<()> {
+    let a = fs::read(source)?;
+    let b = read_patch_file(&a, &mut BufReader::new(File::open(patch)?))?;
+
+    let (temp, mut file) = create_temp_file(dest)?;
+    let written = (|| {
+        file.write_all(&b)?;
+        match fs::metadata(dest) {
+            Ok(metadata) => file.set_permissions(metadata.permissions())?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp, dest)
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+// Creates a file named `.{name}.{pid}.{n}.tmp` next to `path`, with `n` counted per
+// call, so that concurrent calls never write to the same file.
+#[cfg(feature = "std")]
+fn create_temp_file(path: &Path) -> io::Result<(std::path::PathBuf, File)> {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "destination is not a file path",
+        ));
+    };
+    loop {
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.{n}.tmp", std::process::id()));
+        let temp = path.with_file_name(temp_name);
+        match File::options().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414F_A339
+        );
+    }
+
+    #[test]
+    fn patch_checked_crc_of_output() {
+        use crate::{delta_with, DeltaOptions};
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for self_reference in [false, true] {
+            for n in [1, 4, 32] {
+                let opts = DeltaOptions::default()
+                    .min_match_len(n)
+                    .allow_self_reference(self_reference);
+                let (out, checksum) = patch_checked(a, &delta_with(a, b, &opts));
+                assert_eq!(out, b);
+                assert_eq!(checksum, crc32(b));
+            }
+        }
+        let pieces = [
+            Compression::Raw(b"1234"),
+            Compression::Match(0, 3),
+            Compression::Match(3, 0),
+            Compression::Raw(b"89"),
+        ];
+        assert_eq!(
+            patch_checked(b"567", &pieces),
+            (b"123456789".to_vec(), 0xCBF4_3926)
+        );
+        assert_eq!(patch_checked(b"abc", &[]), (Vec::new(), 0));
+    }
+
+    #[cfg(feature = "std")]
+    fn patch_file(a: &[u8], b: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_patch_file(&mut out, a.len(), crc32(a), &crate::delta(a, b, 4)).unwrap();
+        out
+    }
+
+    #[cfg(feature = "std")]
+    fn patch_error(e: io::Error) -> PatchError {
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let inner = e.into_inner().unwrap();
+        *inner.downcast::<PatchError>().unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn patch_file_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let patch = patch_file(a, b);
+        assert_eq!(&patch[..5], b"SHDP\x01");
+        assert_eq!(read_patch_file(a, &mut &patch[..]).unwrap(), b);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn patch_file_refuses_other_source() {
+        let a = b"the quick brown fox";
+        let patch = patch_file(a, b"the quick red fox");
+        let other = b"the quick brown cat";
+        assert_eq!(
+            patch_error(read_patch_file(other, &mut &patch[..]).unwrap_err()),
+            PatchError::SourceMismatch {
+                expected_len: a.len(),
+                expected_checksum: crc32(a),
+                actual_len: other.len(),
+                actual_checksum: crc32(other),
+            }
+        );
+        let e = read_patch_file(&a[1..], &mut &patch[..]).unwrap_err();
+        assert!(matches!(
+            patch_error(e),
+            PatchError::SourceMismatch { actual_len: 18, .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn patch_file_bad_header() {
+        let a = b"abcdefgh";
+        let mut patch = patch_file(a, b"abcdxyz");
+        patch[4] = 9;
+        assert_eq!(
+            patch_error(read_patch_file(a, &mut &patch[..]).unwrap_err()),
+            PatchError::UnsupportedVersion(9)
+        );
+        patch[0] = b'X';
+        assert_eq!(
+            patch_error(read_patch_file(a, &mut &patch[..]).unwrap_err()),
+            PatchError::BadMagic
+        );
+        let e = read_patch_file(a, &mut &b"SHDP\x01\x08"[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "std")]
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("shd-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_file_writes_dest() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let dir = temp_dir("apply-file");
+        let (source, patch, dest) = (dir.join("a"), dir.join("patch"), dir.join("b"));
+        fs::write(&source, a).unwrap();
+        fs::write(&patch, patch_file(a, b)).unwrap();
+        fs::write(&dest, b"old contents").unwrap();
+        apply_file(&source, &patch, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b);
+        // Patching the source in place.
+        apply_file(&source, &patch, &source).unwrap();
+        assert_eq!(fs::read(&source).unwrap(), b);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "std", unix))]
+    fn apply_file_keeps_dest_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("apply-file-mode");
+        let (source, patch, dest) = (dir.join("a"), dir.join("patch"), dir.join("b"));
+        fs::write(&source, b"the quick brown fox").unwrap();
+        fs::write(
+            &patch,
+            patch_file(b"the quick brown fox", b"the quick red fox"),
+        )
+        .unwrap();
+        fs::write(&dest, b"old contents").unwrap();
+        fs::set_permissions(&dest, fs::Permissions::from_mode(0o751)).unwrap();
+        apply_file(&source, &patch, &dest).unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"the quick red fox");
+        let mode = fs::metadata(&dest).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_file_concurrent_calls() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let dir = temp_dir("apply-file-concurrent");
+        let (source, patch, dest) = (dir.join("a"), dir.join("patch"), dir.join("b"));
+        fs::write(&source, a).unwrap();
+        fs::write(&patch, patch_file(a, b)).unwrap();
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| apply_file(&source, &patch, &dest).unwrap());
+            }
+        });
+        assert_eq!(fs::read(&dest).unwrap(), b);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn apply_file_refuses_other_source() {
+        let dir = temp_dir("apply-file-other");
+        let (source, patch, dest) = (dir.join("a"), dir.join("patch"), dir.join("b"));
+        fs::write(&source, b"the quick brown cat").unwrap();
+        let patch_bytes = patch_file(b"the quick brown fox", b"the quick red fox");
+        fs::write(&patch, patch_bytes).unwrap();
+        let e = apply_file(&source, &patch, &dest).unwrap_err();
+        assert!(matches!(patch_error(e), PatchError::SourceMismatch { .. }));
+        assert!(!dest.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn patch_file_output_length_mismatch() {
+        let a = b"abcdefgh";
+        let mut patch = patch_file(a, b"abcdxyz");
+        // The output length is the byte after the checksum.
+        patch[10] += 1;
+        assert_eq!(
+            patch_error(read_patch_file(a, &mut &patch[..]).unwrap_err()),
+            PatchError::OutputLengthMismatch {
+                expected: 8,
+                actual: 7
+            }
+        );
+        patch[10] -= 2;
+        assert_eq!(
+            patch_error(read_patch_file(a, &mut &patch[..]).unwrap_err()),
+            PatchError::OutputLengthMismatch {
+                expected: 6,
+                actual: 7
+            }
+        );
+    }
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_patch_file_with_max_output_len() {
+        use crate::RestoreError;
+        let a = b"abcdefgh";
+        let patch = patch_file(a, b"abcdxyz");
+        let opts = ApplyOptions::default().max_output_len(Some(7));
+        assert_eq!(
+            read_patch_file_with(a, &mut &patch[..], &opts).unwrap(),
+            b"abcdxyz"
+        );
+        let opts = opts.max_output_len(Some(6));
+        let e = read_patch_file_with(a, &mut &patch[..], &opts).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            e.get_ref().unwrap().downcast_ref::<ApplyError>(),
+            Some(ApplyError::Restore(RestoreError::OutputTooLong {
+                max: 6,
+                ..
+            }))
+        ));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{delta_with, Compression, DeltaOptions, Symbol};
+use alloc::vec::Vec;
+
+/// A run of `b` as `segments` yields it, borrowing the elements it stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'s, T = u8> {
+    /// The part of `a` a match copies. For a back-reference, the earlier part of `b`
+    /// it repeats.
+    Match(&'s [T]),
+    /// Raw elements of `b`.
+    Literal(&'s [T]),
+}
+
+impl<'s, T> Segment<'s, T> {
+    /// The elements this segment adds to the output.
+    pub fn as_slice(&self) -> &'s [T] {
+        match *self {
+            Segment::Match(data) | Segment::Literal(data) => data,
+        }
+    }
+}
+
+/// `delta_with(a, b, opts)` as the slices each compression copies or inserts, so
+/// that concatenating them gives `b`.
+pub fn segments<'s, T: Symbol>(a: &'s [T], b: &'s [T], opts: &DeltaOptions) -> Vec<Segment<'s, T>> {
+    delta_with(a, b, opts)
+        .into_iter()
+        .filter(|c| c.output_len() > 0)
+        .map(|c| match c {
+            Compression::Match(la, len) if la < a.len() => Segment::Match(&a[la..la + len]),
+            // The output is `b`, so what a back-reference copies is in `b` as well.
+            Compression::Match(la, len) => {
+                let from = la - a.len();
+                Segment::Match(&b[from..from + len])
+            }
+            Compression::Raw(data) => Segment::Literal(data),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_concatenate_to_b() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for self_reference in [false, true] {
+            for n in [1, 8, 32] {
+                let opts = DeltaOptions::default()
+                    .min_match_len(n)
+                    .allow_self_reference(self_reference);
+                let segments = segments(a, b, &opts);
+                let concatenated: Vec<u8> = segments
+                    .iter()
+                    .flat_map(|s| s.as_slice())
+                    .copied()
+                    .collect();
+                assert_eq!(concatenated, b);
+                assert_eq!(segments.len(), delta_with(a, b, &opts).len());
+            }
+        }
+    }
+
+    #[test]
+    fn segments_borrow_a_and_b() {
+        let a = b"0123456789";
+        let b = b"xx2345xx2345";
+        let opts = DeltaOptions::default()
+            .min_match_len(4)
+            .allow_self_reference(true);
+        assert_eq!(
+            segments(a, b, &opts),
+            vec![
+                Segment::Literal(&b"xx"[..]),
+                Segment::Match(&a[2..6]),
+                Segment::Match(&b[..6]),
+            ]
+        );
+        assert!(segments(a, b"", &opts).is_empty());
+    }
+}
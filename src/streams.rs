@@ -0,0 +1,303 @@
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use crate::chunked::{scan_chunks, ChunkSource};
+use crate::{
+    CompressionOwned, DeltaOptions, DoubleHasher, FingerprintMap, HashMode, MatchInterval,
+    PolynomialHasher, StreamingRollingHash, WindowHasher, MAX_WASTED_EXTENSION,
+};
+
+// The length of the chunks `b` is read in.
+const CHUNK_LEN: usize = 1 << 16;
+// Bytes of `a` read at once when comparing it to `b`. The first read is short, as
+// most candidates are told apart within their window.
+const FIRST_READ_LEN: usize = 64;
+const MAX_READ_LEN: usize = 1 << 13;
+
+/// Same as `delta_chunked`, but reads `a` from `a_reader` as well, so that neither
+/// input is held in memory: only the hashes and offsets of the sampled windows of
+/// `a`, and about one chunk of `b`.
+///
+/// `a_reader` is hashed from its start in one pass, whatever its position, and then
+/// seeked into to compare each candidate match with `b`. The matches found are the
+/// ones `delta_chunked` finds reading `b` in chunks of 64 KiB, so they are the same
+/// as `delta_with`'s for a `b` shorter than that. The same options are ignored,
+/// except `hash_len_policy`, which picks `min_match_len` from the length of `a`.
+pub fn delta_streams<R1: Read + Seek, R2: Read>(
+    a_reader: &mut R1,
+    b_reader: &mut R2,
+    opts: &DeltaOptions,
+) -> io::Result<Vec<CompressionOwned>> {
+    delta_streams_chunked(a_reader, b_reader, CHUNK_LEN, opts)
+}
+
+fn delta_streams_chunked<R1: Read + Seek, R2: Read>(
+    a_reader: &mut R1,
+    b_reader: &mut R2,
+    chunk_size: usize,
+    opts: &DeltaOptions,
+) -> io::Result<Vec<CompressionOwned>> {
+    let a_len = a_reader.seek(SeekFrom::End(0))?;
+    let a_len =
+        usize::try_from(a_len).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let opts = opts.resolved(a_len);
+    match opts.hash_mode {
+        HashMode::Single => {
+            let hasher = PolynomialHasher::new(opts.hash_params);
+            let source = StreamSource::index(a_reader, a_len, &opts, hasher.clone())?;
+            scan_chunks(source, b_reader, chunk_size, &opts, hasher)
+        }
+        HashMode::Double => {
+            let hasher = DoubleHasher::with_params(opts.hash_params);
+            let source = StreamSource::index(a_reader, a_len, &opts, hasher.clone())?;
+            scan_chunks(source, b_reader, chunk_size, &opts, hasher)
+        }
+    }
+}
+
+// The sampled windows of `a` by hash, as `SeedTable::sampled` keeps them, with `a`
+// itself left in the reader.
+struct StreamSource<'r, R> {
+    a: SeekedBytes<'r, R>,
+    window_len: usize,
+    positions: FingerprintMap<Vec<usize>>,
+}
+
+impl<'r, R: Read + Seek> StreamSource<'r, R> {
+    fn index<H: WindowHasher>(
+        reader: &'r mut R,
+        len: usize,
+        opts: &DeltaOptions,
+        hasher: H,
+    ) -> io::Result<Self> {
+        let hash_len = opts.hash_len();
+        reader.seek(SeekFrom::Start(0))?;
+        let mut positions: FingerprintMap<Vec<usize>> = FingerprintMap::default();
+        let hashes =
+            StreamingRollingHash::with_hasher(BufReader::new(&mut *reader), hash_len, hasher);
+        for hash in hashes {
+            let (h, i) = hash?;
+            if opts.is_seed(i) {
+                positions.entry(h).or_default().push(i);
+            }
+        }
+        Ok(Self {
+            a: SeekedBytes {
+                reader,
+                len,
+                block: Vec::new(),
+            },
+            window_len: std::cmp::min(len, hash_len),
+            positions,
+        })
+    }
+}
+
+impl<R: Read + Seek> ChunkSource for StreamSource<'_, R> {
+    fn a_len(&self) -> usize {
+        self.a.len
+    }
+
+    fn best_match(
+        &mut self,
+        buf: &[u8],
+        window: &[u8],
+        hb: u64,
+        ib: usize,
+    ) -> io::Result<MatchInterval> {
+        let Some(candidates) = self.positions.get(&hb) else {
+            return Ok(MatchInterval::empty());
+        };
+        let mut best = MatchInterval::empty();
+        let mut wasted = 0;
+        for &i in candidates {
+            if wasted > MAX_WASTED_EXTENSION {
+                break;
+            }
+            let room = std::cmp::min(i, ib) + std::cmp::min(self.a.len - i, buf.len() - ib);
+            if room <= best.len || window.len() != self.window_len {
+                continue;
+            }
+            // The window is compared as part of the extension to the right.
+            let r = self.a.common_prefix(i, &buf[ib..])?;
+            if r < self.window_len {
+                continue;
+            }
+            let l = self.a.common_suffix(i, &buf[..ib])?;
+            let m = MatchInterval {
+                la: i - l,
+                lb: ib - l,
+                len: l + r,
+            };
+            if m.len > best.len {
+                wasted += best.len;
+                best = m;
+            } else {
+                wasted += m.len;
+            }
+        }
+        Ok(best)
+    }
+
+    fn common_prefix(&mut self, from: usize, data: &[u8]) -> io::Result<usize> {
+        self.a.common_prefix(from, data)
+    }
+
+    fn extend_left(&mut self, m: &mut MatchInterval, buf: &[u8], bound: usize) -> io::Result<()> {
+        if m.lb > bound {
+            let l = self.a.common_suffix(m.la, &buf[bound..m.lb])?;
+            m.la -= l;
+            m.lb -= l;
+            m.len += l;
+        }
+        Ok(())
+    }
+
+    fn copy_to(&mut self, start: usize, end: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        out.extend_from_slice(self.a.read_at(start, end - start)?);
+        Ok(())
+    }
+}
+
+// `a` as the `len` bytes of `reader`, read a block at a time.
+struct SeekedBytes<'r, R> {
+    reader: &'r mut R,
+    len: usize,
+    block: Vec<u8>,
+}
+
+impl<R: Read + Seek> SeekedBytes<'_, R> {
+    fn read_at(&mut self, start: usize, len: usize) -> io::Result<&[u8]> {
+        self.reader.seek(SeekFrom::Start(start as u64))?;
+        self.block.resize(len, 0);
+        self.reader.read_exact(&mut self.block)?;
+        Ok(&self.block)
+    }
+
+    // The number of leading bytes of `data` that `a[from..]` starts with.
+    fn common_prefix(&mut self, from: usize, data: &[u8]) -> io::Result<usize> {
+        let max = std::cmp::min(self.len - from, data.len());
+        let mut len = 0;
+        let mut read_len = FIRST_READ_LEN;
+        while len < max {
+            let n = std::cmp::min(read_len, max - len);
+            let block = self.read_at(from + len, n)?;
+            let common = block
+                .iter()
+                .zip(&data[len..])
+                .take_while(|(x, y)| x == y)
+                .count();
+            len += common;
+            if common < n {
+                break;
+            }
+            read_len = std::cmp::min(2 * read_len, MAX_READ_LEN);
+        }
+        Ok(len)
+    }
+
+    // The number of trailing bytes of `data` that `a[..to]` ends with.
+    fn common_suffix(&mut self, to: usize, data: &[u8]) -> io::Result<usize> {
+        let max = std::cmp::min(to, data.len());
+        let mut len = 0;
+        let mut read_len = FIRST_READ_LEN;
+        while len < max {
+            let n = std::cmp::min(read_len, max - len);
+            let block = self.read_at(to - len - n, n)?;
+            let common = block
+                .iter()
+                .rev()
+                .zip(data[..data.len() - len].iter().rev())
+                .take_while(|(x, y)| x == y)
+                .count();
+            len += common;
+            if common < n {
+                break;
+            }
+            read_len = std::cmp::min(2 * read_len, MAX_READ_LEN);
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta_chunked, delta_with, restore, xorshift_bytes, Compression, HashLenPolicy};
+    use std::io::Cursor;
+
+    // A moderate input: pseudo-random bytes with some text of `b` changed, moved and
+    // repeated.
+    fn inputs() -> (Vec<u8>, Vec<u8>) {
+        let a = xorshift_bytes(40_000, 0x2545F4914F6CDD1D);
+        let mut b = Vec::new();
+        b.extend_from_slice(&a[20_000..30_000]);
+        b.extend_from_slice(b"inserted");
+        b.extend_from_slice(&a[..12_000]);
+        b.extend_from_slice(&a[5_000..5_100]);
+        b.extend(
+            a[31_000..]
+                .iter()
+                .map(|x| x.wrapping_add(u8::from(x % 97 == 0))),
+        );
+        (a, b)
+    }
+
+    fn restored(a: &[u8], d: &[CompressionOwned]) -> Vec<u8> {
+        let d: Vec<Compression> = d.iter().map(Compression::from).collect();
+        restore(a, &d).concat()
+    }
+
+    #[test]
+    fn delta_streams_same_as_delta_with() {
+        let (a, b) = inputs();
+        assert!(b.len() < CHUNK_LEN);
+        for opts in [
+            DeltaOptions::default(),
+            DeltaOptions::default().min_match_len(8),
+            DeltaOptions::default().max_match_len(1000),
+            DeltaOptions::default().hash_mode(HashMode::Double),
+        ] {
+            let d = delta_streams(&mut Cursor::new(&a), &mut &b[..], &opts).unwrap();
+            let expected: Vec<CompressionOwned> = delta_with(&a, &b, &opts)
+                .into_iter()
+                .map(CompressionOwned::from)
+                .collect();
+            assert_eq!(d, expected);
+            assert_eq!(restored(&a, &d), b);
+        }
+    }
+
+    #[test]
+    fn delta_streams_same_as_delta_chunked() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [1, 4, 32] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            for chunk_size in [1, 7, 64, 1000] {
+                let d = delta_streams_chunked(&mut Cursor::new(a), &mut &b[..], chunk_size, &opts)
+                    .unwrap();
+                assert_eq!(d, delta_chunked(a, &mut &b[..], chunk_size, &opts).unwrap());
+                assert_eq!(restored(a, &d), b, "n {n} chunk {chunk_size}");
+            }
+        }
+    }
+
+    #[test]
+    fn delta_streams_reads_a_from_its_start() {
+        let (a, b) = inputs();
+        let opts = DeltaOptions::default().hash_len_policy(Some(HashLenPolicy::Fixed(16)));
+        let mut a_reader = Cursor::new(&a);
+        a_reader.set_position(1234);
+        let d = delta_streams(&mut a_reader, &mut &b[..], &opts).unwrap();
+        assert_eq!(restored(&a, &d), b);
+    }
+
+    #[test]
+    fn delta_streams_empty_inputs() {
+        let opts = DeltaOptions::default();
+        let d = delta_streams(&mut Cursor::new(b"abc"), &mut &b""[..], &opts).unwrap();
+        assert!(d.is_empty());
+        let d = delta_streams(&mut Cursor::new(b""), &mut &b"hello"[..], &opts).unwrap();
+        assert_eq!(d, vec![CompressionOwned::Raw(b"hello".to_vec())]);
+    }
+}
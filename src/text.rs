@@ -0,0 +1,284 @@
+use crate::{reconstruct_len, Compression};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+// Unchanged lines kept around each change, as `diff -u` does.
+const CONTEXT_LINES: usize = 3;
+// Bytes per row of the hex dump of raw data.
+const HEX_ROW_LEN: usize = 16;
+
+// A line of the rendered diff, with its line ending.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Line<'t> {
+    Context(&'t str),
+    Removed(&'t str),
+    Added(&'t str),
+}
+
+/// Renders the delta `compressions` from `a` to `b` as a unified diff, for people
+/// to read rather than for `patch` to apply.
+///
+/// When `a` and `b` are both valid UTF-8, a line of `b` is context if a match copies
+/// it from a whole line of `a` past the previous context line. The lines of `a`
+/// passed over are removed, and the other lines of `b`, raw or pieced together from
+/// elsewhere, are added. Changes are grouped into `@@` hunks with three lines of
+/// context around them. Otherwise every compression is listed on its own, with raw
+/// data as a hex dump.
+///
+/// # Panics
+///
+/// If `compressions` does not restore to as many bytes as `b` has.
+pub fn render_unified(a: &[u8], b: &[u8], compressions: &[Compression]) -> String {
+    assert_eq!(
+        reconstruct_len(compressions),
+        Some(b.len()),
+        "`compressions` does not restore `b`"
+    );
+    let mut out = String::from("--- a\n+++ b\n");
+    let written = match (core::str::from_utf8(a), core::str::from_utf8(b)) {
+        (Ok(a_text), Ok(b_text)) => {
+            write_hunks(&mut out, &diff_lines(a_text, b_text, compressions))
+        }
+        _ => write_hex(&mut out, a.len(), compressions),
+    };
+    written.expect("writing to a `String` does not fail");
+    out
+}
+
+// The lines of `b_text` as context or added, with the lines of `a_text` they pass
+// over removed before them.
+fn diff_lines<'t>(a_text: &'t str, b_text: &'t str, compressions: &[Compression]) -> Vec<Line<'t>> {
+    let a = a_text.as_bytes();
+    // Where each compression starts in `b`.
+    let mut starts = Vec::with_capacity(compressions.len());
+    let mut lb = 0;
+    for c in compressions {
+        starts.push(lb);
+        lb += c.output_len();
+    }
+    // Where in `a` the line `b[lb..lb + len]` would start for each of the matches
+    // copying part of it from `a`. A line equal to one of `a` may be pieced together
+    // from several places, and only some of them line up with it.
+    let starts = &starts[..];
+    let sources = move |lb: usize, len: usize| {
+        let first = starts
+            .partition_point(|&start| start <= lb)
+            .saturating_sub(1);
+        let last = starts.partition_point(|&start| start < lb + len);
+        (first..last).filter_map(move |i| match compressions[i] {
+            Compression::Match(la, _) if la < a.len() => {
+                let from = core::cmp::max(lb, starts[i]);
+                (la + from - starts[i]).checked_sub(from - lb)
+            }
+            _ => None,
+        })
+    };
+
+    let mut lines = Vec::new();
+    let mut added = Vec::new();
+    // The start of the first line of `a` not yet shown.
+    let mut a_pos = 0;
+    let mut lb = 0;
+    for line in b_text.split_inclusive('\n') {
+        let copied = sources(lb, line.len()).find(|&la| {
+            la >= a_pos
+                && (la == 0 || a[la - 1] == b'\n')
+                && a_text[la..].split_inclusive('\n').next() == Some(line)
+        });
+        lb += line.len();
+        let Some(la) = copied else {
+            added.push(Line::Added(line));
+            continue;
+        };
+        lines.extend(a_text[a_pos..la].split_inclusive('\n').map(Line::Removed));
+        lines.append(&mut added);
+        lines.push(Line::Context(line));
+        a_pos = la + line.len();
+    }
+    lines.extend(a_text[a_pos..].split_inclusive('\n').map(Line::Removed));
+    lines.append(&mut added);
+    lines
+}
+
+// Writes the changed lines with their context, in hunks headed by the lines they
+// cover in `a` and `b`.
+fn write_hunks(out: &mut String, lines: &[Line]) -> fmt::Result {
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&i| !matches!(lines[i], Line::Context(_)))
+        .collect();
+    // Lines of `a` and `b` before `lines[shown]`.
+    let (mut a_line, mut b_line, mut shown) = (0, 0, 0);
+    let mut i = 0;
+    while i < changed.len() {
+        // Changes with no more context between them than two hunks would show share
+        // a hunk.
+        let mut j = i;
+        while j + 1 < changed.len() && changed[j + 1] - changed[j] <= 2 * CONTEXT_LINES + 1 {
+            j += 1;
+        }
+        let start = changed[i].saturating_sub(CONTEXT_LINES);
+        let end = core::cmp::min(lines.len(), changed[j] + CONTEXT_LINES + 1);
+        for line in &lines[shown..start] {
+            a_line += usize::from(!matches!(line, Line::Added(_)));
+            b_line += usize::from(!matches!(line, Line::Removed(_)));
+        }
+        let hunk = &lines[start..end];
+        let a_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let b_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        // An empty range is numbered by the line before it.
+        writeln!(
+            out,
+            "@@ -{},{a_count} +{},{b_count} @@",
+            a_line + usize::from(a_count > 0),
+            b_line + usize::from(b_count > 0)
+        )?;
+        for line in hunk {
+            let (prefix, text) = match *line {
+                Line::Context(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            out.push(prefix);
+            out.push_str(text);
+            if !text.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        a_line += a_count;
+        b_line += b_count;
+        shown = end;
+        i = j + 1;
+    }
+    Ok(())
+}
+
+// Writes each compression as a line, with raw data dumped in rows of hex and ASCII
+// at their offsets in `b`.
+fn write_hex(out: &mut String, a_len: usize, compressions: &[Compression]) -> fmt::Result {
+    let mut lb = 0;
+    for c in compressions {
+        match *c {
+            Compression::Match(_, 0) => {}
+            Compression::Match(la, len) if la < a_len => {
+                writeln!(out, " {lb:08x}  copy {len} bytes from a at {la:08x}")?;
+            }
+            Compression::Match(la, len) => {
+                let from = la - a_len;
+                writeln!(out, " {lb:08x}  copy {len} bytes from b at {from:08x}")?;
+            }
+            Compression::Raw(data) => {
+                for (row, bytes) in data.chunks(HEX_ROW_LEN).enumerate() {
+                    write!(out, "+{:08x} ", lb + row * HEX_ROW_LEN)?;
+                    for byte in bytes {
+                        write!(out, " {byte:02x}")?;
+                    }
+                    for _ in bytes.len()..HEX_ROW_LEN {
+                        out.push_str("   ");
+                    }
+                    out.push_str("  |");
+                    for &byte in bytes {
+                        let shown = if byte.is_ascii_graphic() || byte == b' ' {
+                            byte as char
+                        } else {
+                            '.'
+                        };
+                        out.push(shown);
+                    }
+                    out.push_str("|\n");
+                }
+            }
+        }
+        lb += c.output_len();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta_with, DeltaOptions};
+    use Compression::*;
+
+    #[test]
+    fn render_unified_small_text_change() {
+        let a = b"one\ntwo\nthree\nfour\n";
+        let b = b"one\n2\nthree\nfour\nfive\n";
+        let compressions = [Match(0, 4), Raw(b"2"), Match(7, 12), Raw(b"five\n")];
+        let expected = "--- a\n+++ b\n@@ -1,4 +1,5 @@\n one\n-two\n+2\n three\n four\n+five\n";
+        assert_eq!(render_unified(a, b, &compressions), expected);
+        let opts = DeltaOptions::default().min_match_len(2);
+        assert_eq!(render_unified(a, b, &delta_with(a, b, &opts)), expected);
+        assert_eq!(render_unified(a, a, &[Match(0, a.len())]), "--- a\n+++ b\n");
+    }
+
+    #[test]
+    fn render_unified_separate_hunks() {
+        let a: String = (1..=20).map(|i| alloc::format!("line {i}\n")).collect();
+        let b = a.replace("line 2\n", "line two\n").replace("line 18\n", "");
+        let opts = DeltaOptions::default().min_match_len(4);
+        let rendered = render_unified(
+            a.as_bytes(),
+            b.as_bytes(),
+            &delta_with(a.as_bytes(), b.as_bytes(), &opts),
+        );
+        let expected = "--- a\n+++ b\n\
+            @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+            @@ -15,6 +15,5 @@\n line 15\n line 16\n line 17\n-line 18\n line 19\n line 20\n";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn diff_lines_cover_a_and_b() {
+        let a = include_str!("../examples/a.txt");
+        let b = include_str!("../examples/b.txt");
+        for n in [2, 8, 32] {
+            let opts = DeltaOptions::default().min_match_len(n);
+            let lines = diff_lines(a, b, &delta_with(a.as_bytes(), b.as_bytes(), &opts));
+            let old: String = lines
+                .iter()
+                .filter_map(|line| match *line {
+                    Line::Context(text) | Line::Removed(text) => Some(text),
+                    Line::Added(_) => None,
+                })
+                .collect();
+            let new: String = lines
+                .iter()
+                .filter_map(|line| match *line {
+                    Line::Context(text) | Line::Added(text) => Some(text),
+                    Line::Removed(_) => None,
+                })
+                .collect();
+            assert_eq!((old.as_str(), new.as_str()), (a, b));
+        }
+    }
+
+    #[test]
+    fn render_unified_missing_newline_and_empty() {
+        let rendered = render_unified(b"", b"new", &[Raw(b"new")]);
+        assert_eq!(
+            rendered,
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+new\n\\ No newline at end of file\n"
+        );
+        let rendered = render_unified(b"old\n", b"", &[]);
+        assert_eq!(rendered, "--- a\n+++ b\n@@ -1,1 +0,0 @@\n-old\n");
+    }
+
+    #[test]
+    fn render_unified_hex_dump_for_binary() {
+        let a = [0xFF, 0, 1, 2, 3];
+        let b = b"\x00\x01\x02\x03hi\xFE\x00\x01";
+        let rendered = render_unified(&a, b, &[Match(1, 4), Raw(b"hi\xFE"), Match(5, 2)]);
+        let expected = "--- a\n+++ b\n \
+            00000000  copy 4 bytes from a at 00000001\n\
+            +00000004  68 69 fe                                         |hi.|\n \
+            00000007  copy 2 bytes from b at 00000000\n";
+        assert_eq!(rendered, expected);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    delta_indexed, Compression, Delta, DeltaIter, DeltaOptions, MatchInterval, PolynomialHasher,
+    RollingHash, SeedTable, SourceIndex, Symbol,
+};
+use alloc::vec::Vec;
+
+/// A change to `b`: `removed` elements at `start` replaced by `inserted` new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+impl Edit {
+    /// `len` elements inserted at `at`.
+    pub fn insert(at: usize, len: usize) -> Self {
+        Self {
+            start: at,
+            removed: 0,
+            inserted: len,
+        }
+    }
+
+    /// `len` elements deleted at `start`.
+    pub fn delete(start: usize, len: usize) -> Self {
+        Self {
+            start,
+            removed: len,
+            inserted: 0,
+        }
+    }
+}
+
+/// Turns `prev`, the delta `delta(a, old_b, n)`, into the delta from `a` to `new_b`,
+/// which is `old_b` changed by `edit`, without diffing all of `new_b`. `index` is
+/// `SourceIndex::build(a, &DeltaOptions::default().min_match_len(n))`, built once
+/// and reused for every edit, so that `a` is not hashed again.
+///
+/// Matches ending before the edit are kept, and `new_b` is scanned from the last of
+/// them until the scan finds a match of `prev` again past the edit. From there on the
+/// rest of `prev` is reused with its raw runs taken from `new_b`. The result restores
+/// `new_b`, and is the same as `delta(a, new_b, n)` unless a seed past the edit would
+/// pick a different candidate for how far it extends back into the edit.
+///
+/// # Panics
+///
+/// If `edit` does not fit `old_b`, or `new_b` is not as long as `old_b` after `edit`.
+/// As `delta_indexed`, if `a` is not as long as the source of `index`, and if `index`
+/// was built with options other than `min_match_len`.
+pub fn update_delta<'b, T: Symbol>(
+    index: &SourceIndex,
+    prev: &Delta<'_, T>,
+    a: &[T],
+    old_b: &[T],
+    new_b: &'b [T],
+    edit: Edit,
+) -> Delta<'b, T> {
+    let Edit {
+        start,
+        removed,
+        inserted,
+    } = edit;
+    assert!(
+        start + removed <= old_b.len(),
+        "`edit` does not fit `old_b`"
+    );
+    assert_eq!(
+        old_b.len() - removed + inserted,
+        new_b.len(),
+        "`new_b` is not `old_b` changed by `edit`"
+    );
+    assert_eq!(
+        a.len(),
+        index.a_len,
+        "`a` is not the source the index was built from"
+    );
+    let opts = index.options();
+    let min_match_len = opts.min_match_len;
+    assert!(
+        *opts == DeltaOptions::default().min_match_len(min_match_len),
+        "`index` was built with options other than `min_match_len`"
+    );
+    let back_reference = prev
+        .iter()
+        .any(|c| matches!(*c, Compression::Match(la, _) if la >= a.len()));
+    if a.is_empty() || opts.longer_than(old_b) || opts.longer_than(new_b) || back_reference {
+        return delta_indexed(index, a, new_b).into();
+    }
+
+    // The matches of `prev` with where they start in `old_b`.
+    let mut matches = Vec::new();
+    let mut pos = 0;
+    for (i, c) in prev.iter().enumerate() {
+        if let Compression::Match(la, len) = *c {
+            matches.push((i, MatchInterval { la, lb: pos, len }));
+        }
+        pos += c.output_len();
+    }
+    // The scan resumes after the last match that ends before the edit. The element
+    // that stopped it is not edited, so the scan up to there is unchanged.
+    let kept = matches.partition_point(|(_, m)| m.br() < start);
+    let (kept_len, acc) = match kept.checked_sub(1) {
+        Some(last) => (matches[last].0 + 1, matches[last].1),
+        None => (0, MatchInterval::empty()),
+    };
+
+    let mut compressions: Vec<Compression<'b, T>> = Vec::with_capacity(prev.len());
+    let mut pos = 0;
+    for c in &prev[..kept_len] {
+        compressions.push(match *c {
+            Compression::Match(la, len) => Compression::Match(la, len),
+            Compression::Raw(data) => Compression::Raw(&new_b[pos..pos + data.len()]),
+        });
+        pos += c.output_len();
+    }
+
+    let hash_len = opts.hash_len();
+    let hasher = PolynomialHasher::new(opts.hash_params);
+    let mut scan = DeltaIter {
+        b: new_b,
+        source: SeedTable::indexed(a, index),
+        hashes: RollingHash::with_hasher(new_b, hash_len, hasher).starting_at(acc.br()),
+        window_len: core::cmp::min(new_b.len(), hash_len),
+        state: acc.br(),
+        acc,
+        prev: acc.br(),
+        min_match_len,
+        pending: None,
+        matched: kept_len > 0,
+        done: false,
+    };
+    // Once the scan is past the edit and finds the same match as `prev` did, it would
+    // go on to find the rest of `prev` as well.
+    let edit_end = start + inserted;
+    for c in scan.by_ref() {
+        let lb = pos;
+        pos += c.output_len();
+        let matched = match c {
+            Compression::Match(la, len) => Some((la, len)),
+            Compression::Raw(_) => None,
+        };
+        compressions.push(c);
+        let Some((la, len)) = matched.filter(|_| lb >= edit_end) else {
+            continue;
+        };
+        let old_lb = lb - inserted + removed;
+        let found = matches[kept..].binary_search_by_key(&old_lb, |(_, m)| m.lb);
+        if let Ok(found) = found {
+            let (i, m) = matches[kept + found];
+            if (m.la, m.len) != (la, len) {
+                continue;
+            }
+            for c in &prev[i + 1..] {
+                compressions.push(match *c {
+                    Compression::Match(la, len) => Compression::Match(la, len),
+                    Compression::Raw(data) => Compression::Raw(&new_b[pos..pos + data.len()]),
+                });
+                pos += c.output_len();
+            }
+            break;
+        }
+    }
+    compressions.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta, restore};
+
+    fn edited(b: &[u8], edit: Edit, inserted: &[u8]) -> Vec<u8> {
+        let mut new_b = b[..edit.start].to_vec();
+        new_b.extend_from_slice(inserted);
+        new_b.extend_from_slice(&b[edit.start + edit.removed..]);
+        new_b
+    }
+
+    fn check(a: &[u8], old_b: &[u8], edit: Edit, inserted: &[u8], min_match_len: usize) {
+        let index = SourceIndex::build(a, &DeltaOptions::default().min_match_len(min_match_len));
+        let prev: Delta = delta(a, old_b, min_match_len).into();
+        let new_b = edited(old_b, edit, inserted);
+        let updated = update_delta(&index, &prev, a, old_b, &new_b, edit);
+        assert_eq!(restore(a, &updated).concat(), new_b);
+        assert_eq!(*updated, delta(a, &new_b, min_match_len), "{edit:?}");
+        assert_eq!(updated.stats(), crate::stats(&updated));
+    }
+
+    #[test]
+    fn update_delta_insertion_same_as_delta() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [4, 10, 32] {
+            for at in [0, 1, 200, b.len() / 2, b.len() - 1, b.len()] {
+                check(a, b, Edit::insert(at, 11), b"hello world", n);
+                check(a, b, Edit::insert(at, 30), &a[100..130], n);
+            }
+        }
+    }
+
+    #[test]
+    fn update_delta_deletion_same_as_delta() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [4, 10, 32] {
+            for start in [0, 1, 200, b.len() / 2, b.len() - 20] {
+                check(a, b, Edit::delete(start, 1), b"", n);
+                check(a, b, Edit::delete(start, 20), b"", n);
+            }
+        }
+    }
+
+    #[test]
+    fn update_delta_replacement() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        let edit = Edit {
+            start: 300,
+            removed: 40,
+            inserted: 5,
+        };
+        check(a, b, edit, b"xyzzy", 10);
+    }
+
+    #[test]
+    fn update_delta_small_inputs() {
+        check(b"abcdefgh", b"abcd", Edit::insert(4, 4), b"efgh", 4);
+        check(b"abcdefgh", b"abcdefgh", Edit::delete(2, 5), b"", 4);
+        check(b"", b"abc", Edit::insert(1, 1), b"x", 2);
+    }
+}
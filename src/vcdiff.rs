@@ -0,0 +1,188 @@
+use crate::Compression;
+use alloc::vec::Vec;
+
+const MAGIC: [u8; 4] = [0xd6, 0xc3, 0xc4, 0x00];
+const VCD_SOURCE: u8 = 0x01;
+
+// Instruction codes of the default code table (RFC 3284, section 5.6). Codes with a
+// size of 0 are followed by the size in the instruction section.
+const ADD_0: u8 = 1;
+const ADD_MAX_INLINE: usize = 17;
+const COPY_SELF_0: u8 = 19;
+const COPY_MIN_INLINE: usize = 4;
+const COPY_MAX_INLINE: usize = 18;
+
+/// Encodes a delta against `a` as a VCDIFF (RFC 3284) file, so that it can be applied
+/// by standard tools such as `xdelta3 -d -s a`.
+///
+/// The whole of `a` is the source segment of a single window. `Raw` becomes an ADD
+/// and `Match(la, len)` a COPY of address `la`, which in VCDIFF's address space also
+/// covers back-references into the output. Only the default code table is used,
+/// without secondary compression.
+pub fn encode_vcdiff(a: &[u8], compressions: &[Compression]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut instructions = Vec::new();
+    let mut addresses = Vec::new();
+    let mut target_len = 0;
+    for c in compressions {
+        match *c {
+            Compression::Match(_, 0) => {}
+            Compression::Match(la, len) => {
+                if (COPY_MIN_INLINE..=COPY_MAX_INLINE).contains(&len) {
+                    instructions.push(COPY_SELF_0 + (len - COPY_MIN_INLINE + 1) as u8);
+                } else {
+                    instructions.push(COPY_SELF_0);
+                    write_integer(&mut instructions, len);
+                }
+                write_integer(&mut addresses, la);
+                target_len += len;
+            }
+            Compression::Raw(raw) => {
+                if raw.is_empty() {
+                    continue;
+                }
+                if raw.len() <= ADD_MAX_INLINE {
+                    instructions.push(ADD_0 + raw.len() as u8);
+                } else {
+                    instructions.push(ADD_0);
+                    write_integer(&mut instructions, raw.len());
+                }
+                data.extend_from_slice(raw);
+                target_len += raw.len();
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    out.push(0); // Hdr_Indicator: no secondary compressor or custom code table
+    if target_len == 0 {
+        // A file without windows decodes to an empty target.
+        return out;
+    }
+
+    if a.is_empty() {
+        out.push(0);
+    } else {
+        out.push(VCD_SOURCE);
+        write_integer(&mut out, a.len());
+        write_integer(&mut out, 0); // source segment position
+    }
+    let sections = [&data, &instructions, &addresses];
+    let delta_len = integer_len(target_len)
+        + 1
+        + sections
+            .iter()
+            .map(|s| integer_len(s.len()) + s.len())
+            .sum::<usize>();
+    write_integer(&mut out, delta_len);
+    write_integer(&mut out, target_len);
+    out.push(0); // Delta_Indicator: sections are not compressed
+    for section in sections {
+        write_integer(&mut out, section.len());
+    }
+    for section in sections {
+        out.extend_from_slice(section);
+    }
+    out
+}
+
+// VCDIFF integers are base 128, most significant digit first, with the high bit
+// set on every byte but the last.
+fn write_integer(out: &mut Vec<u8>, value: usize) {
+    for i in (0..integer_len(value)).rev() {
+        let digit = (value >> (7 * i)) as u8 & 0x7f;
+        out.push(if i > 0 { digit | 0x80 } else { digit });
+    }
+}
+
+fn integer_len(value: usize) -> usize {
+    core::cmp::max(
+        1,
+        (usize::BITS - value.leading_zeros()).div_ceil(7) as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{delta, delta_with, DeltaOptions};
+
+    // Applies `patch` to `a` with an independent, xdelta3-compatible decoder.
+    fn decode(a: &[u8], patch: &[u8]) -> Vec<u8> {
+        oxidelta::vcdiff::decoder::decode_memory(patch, a).unwrap()
+    }
+
+    #[test]
+    fn write_integer_rfc_example() {
+        // 123456789 from section 2 of the RFC.
+        let mut out = Vec::new();
+        write_integer(&mut out, 123456789);
+        assert_eq!(out, [0xba, 0xef, 0x9a, 0x15]);
+        for value in [0, 1, 127, 128, 16383, 16384, usize::MAX] {
+            out.clear();
+            write_integer(&mut out, value);
+            assert_eq!(out.len(), integer_len(value));
+        }
+    }
+
+    #[test]
+    fn encode_vcdiff_vector() {
+        let a = b"abcdefghijklmnop";
+        let d = [Compression::Raw(b"xy"), Compression::Match(4, 6)];
+        let expected = [
+            0xd6, 0xc3, 0xc4, 0x00, 0x00, // header
+            0x01, 0x10, 0x00, // source segment a[0..16]
+            0x0a, // length of the delta encoding
+            0x08, 0x00, 0x02, 0x02, 0x01, // target length, indicator, section lengths
+            b'x', b'y', // data
+            0x03, 0x16, // ADD 2, COPY 6 mode 0
+            0x04, // address
+        ];
+        assert_eq!(encode_vcdiff(a, &d), expected);
+        assert_eq!(decode(a, &expected), b"xyefghij");
+    }
+
+    #[test]
+    fn encode_vcdiff_roundtrip() {
+        let a = include_bytes!("../examples/a.txt");
+        let b = include_bytes!("../examples/b.txt");
+        for n in [2, 10, 32] {
+            assert_eq!(decode(a, &encode_vcdiff(a, &delta(a, b, n))), b);
+        }
+    }
+
+    #[test]
+    fn encode_vcdiff_long_runs() {
+        let a: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut b = a[100..900].to_vec();
+        b.extend((0..300u32).map(|i| (i * 13 % 256) as u8));
+        let d = delta(&a, &b, 16);
+        assert!(d
+            .iter()
+            .any(|c| matches!(c, Compression::Match(_, len) if *len > 18)));
+        assert_eq!(decode(&a, &encode_vcdiff(&a, &d)), b);
+    }
+
+    #[test]
+    fn encode_vcdiff_self_reference() {
+        let a = b"xyz";
+        let b = b"abcabcabcabcabcabc";
+        let opts = DeltaOptions::default()
+            .min_match_len(3)
+            .allow_self_reference(true);
+        let d = delta_with(a, b, &opts);
+        assert!(d
+            .iter()
+            .any(|c| matches!(c, Compression::Match(la, _) if *la >= a.len())));
+        assert_eq!(decode(a, &encode_vcdiff(a, &d)), b);
+    }
+
+    #[test]
+    fn encode_vcdiff_empty() {
+        assert_eq!(encode_vcdiff(b"abc", &[]), [0xd6, 0xc3, 0xc4, 0x00, 0x00]);
+        assert_eq!(decode(b"abc", &encode_vcdiff(b"abc", &[])), b"");
+        let d = [Compression::Raw(b"new")];
+        assert_eq!(decode(b"", &encode_vcdiff(b"", &d)), b"new");
+    }
+}